@@ -9,9 +9,9 @@ use bevy_rapier2d::prelude::*;
 
 use super::{
     event::{
-        control_event::ControlEventPlugin, heart_beat_event::HeartBeatEventPlugin,
-        keyboard_event::KeyboardEventPlugin, map_event::MapEventPlugin,
-        skill_event::SkillEventPlugin, sync_event::SyncEventPlugin,
+        combat_event::CombatEventPlugin, control_event::ControlEventPlugin,
+        heart_beat_event::HeartBeatEventPlugin, keyboard_event::KeyboardEventPlugin,
+        map_event::MapEventPlugin, skill_event::SkillEventPlugin, sync_event::SyncEventPlugin,
     },
     plugin::{
         animate_plugin::AnimatePlugin, camera_ctrl_plugin::CameraCtrl,
@@ -82,6 +82,7 @@ pub fn engine_start() {
         .add_plugin(HeartBeatEventPlugin)
         .add_plugin(SyncEventPlugin)
         .add_plugin(SkillEventPlugin)
+        .add_plugin(CombatEventPlugin)
         // .add_plugin(WindowEventPlugin)
         // 地图初始化
         .add_plugin(TileMapPlugin)
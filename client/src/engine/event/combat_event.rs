@@ -0,0 +1,75 @@
+use std::collections::VecDeque;
+
+use bevy::prelude::*;
+use protocol::data::combat_data::{DamageSource, KillData, RespawnData};
+
+pub struct CombatEventPlugin;
+
+impl Plugin for CombatEventPlugin {
+    fn build(&self, app: &mut AppBuilder) {
+        app.insert_resource(CombatState::default());
+    }
+}
+
+/// 最多显示的击杀通知条数
+pub const KILL_FEED_SIZE: usize = 5;
+/// 击杀通知显示时长(毫秒)
+pub const KILL_FEED_DURATION: u128 = 5000;
+
+/// 击杀通知和本地玩家的死亡状态, 以服务器下发的为准
+#[derive(Default)]
+pub struct CombatState {
+    // (收到时的本地时间, 击杀通知)
+    pub kill_feed: VecDeque<(u128, KillData)>,
+    // 本地玩家复活的本地时间(毫秒), 存活时为None
+    pub respawn_at: Option<u128>,
+    // 本地玩家无敌结束的本地时间(毫秒)
+    pub invulnerable_until: u128,
+}
+
+impl CombatState {
+    /// 服务器下发的击杀通知, uid为本地玩家
+    pub fn on_kill(&mut self, kill: KillData, uid: u32, now: u128) {
+        if kill.victim == uid {
+            self.respawn_at = Some(now + kill.respawn_in as u128);
+        }
+        self.kill_feed.push_back((now, kill));
+        while self.kill_feed.len() > KILL_FEED_SIZE {
+            self.kill_feed.pop_front();
+        }
+    }
+
+    /// 服务器下发的复活通知
+    pub fn on_respawn(&mut self, respawn: &RespawnData, uid: u32, now: u128) {
+        if respawn.uid == uid {
+            self.respawn_at = None;
+            self.invulnerable_until = now + respawn.invulnerable as u128;
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.respawn_at.is_some()
+    }
+
+    /// 显示时长内的击杀通知
+    pub fn recent_kills(&self, now: u128) -> impl Iterator<Item = &KillData> {
+        self.kill_feed
+            .iter()
+            .filter(move |(at, _)| now < at + KILL_FEED_DURATION)
+            .map(|(_, kill)| kill)
+    }
+}
+
+/// 击杀通知的显示文本
+pub fn describe(kill: &KillData) -> String {
+    let source = match kill.source {
+        DamageSource::Skill(skill_type) => common::skill::get()
+            .get(skill_type)
+            .map_or_else(|| format!("{:?}", skill_type), |skill| skill.name.clone()),
+        DamageSource::Trap => "陷阱".to_string(),
+    };
+    match kill.attacker {
+        Some(attacker) => format!("玩家{} [{}] 玩家{}", attacker, source, kill.victim),
+        None => format!("玩家{}死于{}", kill.victim, source),
+    }
+}
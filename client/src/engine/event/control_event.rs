@@ -2,6 +2,7 @@ use bevy::prelude::*;
 use bevy_rapier2d::{
     na::Vector2, physics::RigidBodyHandleComponent, rapier::dynamics::RigidBodySet,
 };
+use common::prediction::InputHistory;
 use protocol::{
     data::{control_data::ControlData, player_data::PlayerData},
     packet::Packet,
@@ -19,8 +20,10 @@ impl Plugin for ControlEventPlugin {
                 uid: 0,
                 direction: (0., 0.),
                 action: 0,
+                seq: 0,
             },
         })
+        .insert_resource(InputHistory::default())
         .add_event::<ControlEvent>()
         .add_system(event_listener_system.system());
     }
@@ -42,6 +45,8 @@ fn event_listener_system(
     mut control_event_reader: EventReader<ControlEvent>,
     net_state: ResMut<NetWorkState>,
     mut control_last_one: ResMut<ControlLastOne>,
+    mut input_history: ResMut<InputHistory>,
+    time: Res<Time>,
     mut player_query: Query<&RigidBodyHandleComponent, With<PlayerData>>,
     mut rigid_bodies: ResMut<RigidBodySet>,
 ) {
@@ -52,35 +57,21 @@ fn event_listener_system(
             {
                 continue;
             }
-            to_be_sent_queue.push(Packet::Game(GameRoute::Control(ControlData {
-                uid: 0,
-                direction: control_event.direction,
-                action: control_event.action,
-            })));
-            control_last_one.control = ControlData {
+            let mut control = ControlData {
                 uid: 0,
                 direction: control_event.direction,
                 action: control_event.action,
+                seq: 0,
             };
+            // 记录输入历史, 收到服务器确认前用于重放预测
+            let velocity = control.velocity();
+            control.seq = input_history.push(velocity, time.seconds_since_startup());
+            to_be_sent_queue.push(Packet::Game(GameRoute::Control(control)));
+            control_last_one.control = control;
+            // 本地立即按输入移动, 与服务器使用相同的速度
             if let Ok(rb_handle) = player_query.single_mut() {
                 if let Some(rb) = rigid_bodies.get_mut(rb_handle.handle()) {
-                    if control_event.action == 0 {
-                        rb.set_linvel(Vector2::new(0., 0.), true);
-                    }
-                    if control_event.action == 1 {
-                        rb.set_linvel(
-                            Vector2::new(control_event.direction.0, control_event.direction.1)
-                                * 100.,
-                            true,
-                        );
-                    }
-                    if control_event.action == 2 {
-                        rb.set_linvel(
-                            Vector2::new(control_event.direction.0, control_event.direction.1)
-                                * 150.,
-                            true,
-                        );
-                    }
+                    rb.set_linvel(Vector2::new(velocity.0, velocity.1), true);
                 }
             }
             // println!("收到控制事件: {:?}", control_event);
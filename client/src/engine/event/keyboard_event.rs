@@ -1,9 +1,8 @@
 use bevy::prelude::*;
-use protocol::data::skill_data::SkillType;
 
 use crate::engine::plugin::ui_plugin::UIState;
 
-use super::{control_event::ControlEvent, skill_event::SkillEvent};
+use super::{combat_event::CombatState, control_event::ControlEvent, skill_event::SkillEvent};
 
 pub struct KeyboardEventPlugin;
 
@@ -20,6 +19,7 @@ fn keyboard_event_system(
     mourse_input: Res<Input<MouseButton>>,
     mut ui_state: ResMut<UIState>,
     windows: Res<Windows>,
+    combat_state: Res<CombatState>,
 ) {
     // 控制移动
     let x_axis = -(keyboard_input.pressed(KeyCode::A) as i8) as f32
@@ -30,7 +30,8 @@ fn keyboard_event_system(
     // if y_axis != 0f32 {
     //     action = 2u8;
     // }
-    if x_axis != 0f32 || y_axis != 0f32 {
+    // 死亡后只能停止, 不能释放技能
+    if (x_axis != 0f32 || y_axis != 0f32) && !combat_state.is_dead() {
         control_events.send(ControlEvent {
             direction: (x_axis, y_axis),
             action,
@@ -45,8 +46,28 @@ fn keyboard_event_system(
     if keyboard_input.just_released(KeyCode::Escape) {
         ui_state.windows_enabled[1] = !ui_state.windows_enabled[1];
     }
-    // 释放技能
-    if mourse_input.pressed(MouseButton::Left) || keyboard_input.pressed(KeyCode::Space) {
+    // 释放技能: 鼠标左键/空格释放栏位1, 按键1-6释放对应栏位
+    let slot_keys = [
+        KeyCode::Key1,
+        KeyCode::Key2,
+        KeyCode::Key3,
+        KeyCode::Key4,
+        KeyCode::Key5,
+        KeyCode::Key6,
+    ];
+    let slot = if mourse_input.pressed(MouseButton::Left) || keyboard_input.pressed(KeyCode::Space)
+    {
+        Some(1)
+    } else {
+        slot_keys
+            .iter()
+            .position(|key| keyboard_input.pressed(*key))
+            .map(|index| index as u8 + 1)
+    };
+    let skill = slot
+        .filter(|_| !combat_state.is_dead())
+        .and_then(|slot| common::skill::get().by_slot(slot));
+    if let Some(skill) = skill {
         if let Some(window) = windows.get_primary() {
             let center_point = Vec2::new(window.width() / 2., window.height() / 2.);
             // println!("camera_point: {}", &center_point);
@@ -56,7 +77,7 @@ fn keyboard_event_system(
                 // println!("direction: {}", &direction);
                 skill_events.send(SkillEvent {
                     direction: (direction.x, direction.y),
-                    skill_type: SkillType::Shot,
+                    skill_type: skill.skill_type,
                 });
             }
         }
@@ -8,6 +8,7 @@ impl Plugin for MapEventPlugin {
     }
 }
 pub enum MapEvent {
-    // Clean,
+    // 切换房间, 清除当前地图
+    Clean,
     Add,
 }
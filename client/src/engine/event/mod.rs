@@ -6,3 +6,4 @@ pub mod heart_beat_event;
 pub mod window_event;
 pub mod control_event;
 pub mod skill_event;
+pub mod combat_event;
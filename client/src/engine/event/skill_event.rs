@@ -1,8 +1,8 @@
-use std::time::SystemTime;
+use std::{collections::HashMap, time::SystemTime};
 
 use bevy::prelude::*;
 use protocol::{
-    data::skill_data::{SkillData, SkillType},
+    data::skill_data::{CastResult, CooldownData, SkillData, SkillType},
     packet::Packet,
     route::GameRoute,
 };
@@ -13,7 +13,7 @@ pub struct SkillEventPlugin;
 
 impl Plugin for SkillEventPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.insert_resource(SkillState { last_time: 0 })
+        app.insert_resource(SkillState::default())
             .add_event::<SkillEvent>()
             .add_system(event_listener_system.system());
     }
@@ -27,8 +27,57 @@ pub struct SkillEvent {
     pub skill_type: SkillType,
 }
 
+/// 收到服务器下发的冷却状态前使用的冷却时长(毫秒)
+pub const DEFAULT_COOLDOWN: u32 = 500;
+
+/// 技能冷却状态, 以服务器下发的为准, 本地只用于显示和避免重复发送
+#[derive(Default)]
 pub struct SkillState {
-    pub last_time: u128,
+    // 技能 -> 冷却结束的本地时间(毫秒)
+    pub ready_at: HashMap<SkillType, u128>,
+    // 技能 -> 冷却总时长(毫秒)
+    pub cooldown: HashMap<SkillType, u32>,
+    // 最近一次施法结果
+    pub last_result: Option<CastResult>,
+}
+
+impl SkillState {
+    /// 剩余冷却(毫秒)
+    pub fn remaining(&self, skill_type: SkillType, now: u128) -> u128 {
+        self.ready_at
+            .get(&skill_type)
+            .map_or(0, |ready_at| ready_at.saturating_sub(now))
+    }
+
+    pub fn cooldown(&self, skill_type: SkillType) -> u32 {
+        self.cooldown
+            .get(&skill_type)
+            .cloned()
+            .unwrap_or(DEFAULT_COOLDOWN)
+    }
+
+    /// 服务器下发的冷却状态
+    pub fn apply(&mut self, cooldown_data: &CooldownData, now: u128) {
+        // 魔力不足时间隔一个冷却再尝试, 避免按住按键时连续请求
+        let remaining = if cooldown_data.result == CastResult::NotEnoughMp {
+            cooldown_data.remaining.max(cooldown_data.cooldown)
+        } else {
+            cooldown_data.remaining
+        };
+        self.ready_at
+            .insert(cooldown_data.skill_type, now + remaining as u128);
+        self.cooldown
+            .insert(cooldown_data.skill_type, cooldown_data.cooldown);
+        self.last_result = Some(cooldown_data.result);
+    }
+}
+
+/// 当前本地时间(毫秒)
+pub fn now_millis() -> u128 {
+    SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_millis()
 }
 
 fn event_listener_system(
@@ -36,13 +85,10 @@ fn event_listener_system(
     net_state: ResMut<NetWorkState>,
     mut skill_state: ResMut<SkillState>,
 ) {
-    let time = SystemTime::now()
-        .duration_since(SystemTime::UNIX_EPOCH)
-        .unwrap()
-        .as_millis();
+    let time = now_millis();
     for skill_event in skill_event_reader.iter() {
-        // println!("time: {:?}, last_time: {}", time, skill_state.last_time);
-        if time > skill_state.last_time + 500 {
+        // 冷却中不发送, 服务器回复前按已知冷却时长预先开始冷却
+        if skill_state.remaining(skill_event.skill_type, time) == 0 {
             if let Ok(mut to_be_sent_queue) = net_state.to_be_sent_queue.lock() {
                 to_be_sent_queue.push(Packet::Game(GameRoute::Skill(SkillData {
                     uid: 0,
@@ -52,7 +98,10 @@ fn event_listener_system(
                 })));
                 // println!("收到技能事件: {:?}", skill_event);
             }
-            skill_state.last_time = time;
+            let cooldown = skill_state.cooldown(skill_event.skill_type) as u128;
+            skill_state
+                .ready_at
+                .insert(skill_event.skill_type, time + cooldown);
         }
     }
 }
@@ -2,19 +2,30 @@ use std::time::SystemTime;
 
 use bevy::{core::FixedTimestep, prelude::*};
 use bevy_rapier2d::{
+    na::{UnitComplex, Vector2},
     physics::RigidBodyHandleComponent,
     rapier::dynamics::{RigidBodyBuilder, RigidBodySet},
 };
+use common::{interpolation::InterpolationBuffer, prediction::InputHistory};
 use protocol::data::{
+    interest_data::InterestData,
     player_data::PlayerData,
     update_data::{EntityType, UpdateData},
 };
 
-use crate::engine::plugin::{
-    camera_ctrl_plugin::CameraCtrl,
-    network_plugin::{SynEntity, PLAYER},
+use crate::engine::{
+    event::map_event::MapEvent,
+    plugin::{
+        camera_ctrl_plugin::CameraCtrl,
+        network_plugin::{SynEntity, PLAYER},
+    },
 };
 
+// 本地玩家预测误差超过该距离时直接对齐
+const SNAP_DISTANCE: f32 = 100.;
+// 每次收到快照修正的预测误差比例
+const CORRECTION_RATE: f32 = 0.3;
+
 #[derive(Debug, Hash, PartialEq, Eq, Clone, StageLabel)]
 struct CheckEntityHealthFixedUpdateStage;
 
@@ -22,17 +33,25 @@ pub struct SyncEventPlugin;
 
 impl Plugin for SyncEventPlugin {
     fn build(&self, app: &mut AppBuilder) {
-        app.add_event::<SyncEvent>()
-            .add_system(event_listener_system.system())
-            .add_stage_after(
-                CoreStage::Update,
-                CheckEntityHealthFixedUpdateStage,
-                SystemStage::parallel()
-                    .with_run_criteria(
-                        FixedTimestep::step(100.).with_label("build_map_fixed_timestep"),
-                    )
-                    .with_system(check_entity_health.system()),
-            );
+        let config = config::get();
+        app.insert_resource(InterpolationBuffer::new(
+            config.tick_rate,
+            config.interpolation_delay as f64 / 1000.,
+            config.max_extrapolation as f64 / 1000.,
+        ))
+        .add_event::<SyncEvent>()
+        .add_event::<InterestEvent>()
+        .add_system(event_listener_system.system())
+        .add_system(interpolate_system.system())
+        .add_system(interest_listener_system.system())
+        .add_system(clean_room_system.system())
+        .add_stage_after(
+            CoreStage::Update,
+            CheckEntityHealthFixedUpdateStage,
+            SystemStage::parallel()
+                .with_run_criteria(FixedTimestep::step(100.).with_label("build_map_fixed_timestep"))
+                .with_system(check_entity_health.system()),
+        );
     }
 }
 
@@ -40,6 +59,81 @@ pub struct SyncEvent {
     pub update_data: UpdateData,
 }
 
+// 实体进入/离开视野
+pub struct InterestEvent {
+    pub interest_data: InterestData,
+}
+
+// 离开视野的实体立即清除, 再次进入视野时由同步事件重新生成
+fn interest_listener_system(
+    mut commands: Commands,
+    mut interest_event_reader: EventReader<InterestEvent>,
+    syn_entity_query: Query<(&SynEntity, Entity), Without<CameraCtrl>>,
+) {
+    for interest_event in interest_event_reader.iter() {
+        for (entity_type, id) in interest_event.interest_data.left.iter() {
+            for (syn_entity, entity) in syn_entity_query.iter() {
+                if syn_entity.entity_type == *entity_type && syn_entity.id == *id {
+                    commands.entity(entity).despawn_recursive();
+                }
+            }
+        }
+    }
+}
+
+// 切换房间后旧房间的实体全部清除, 当前玩家由摄像机跟随保留
+fn clean_room_system(
+    mut commands: Commands,
+    mut map_event_reader: EventReader<MapEvent>,
+    syn_entity_query: Query<Entity, (With<SynEntity>, Without<CameraCtrl>)>,
+    mut interpolation_buffer: ResMut<InterpolationBuffer>,
+) {
+    let mut cleaned = false;
+    for map_event in map_event_reader.iter() {
+        if let MapEvent::Clean = map_event {
+            cleaned = true;
+        }
+    }
+    if !cleaned {
+        return;
+    }
+    for entity in syn_entity_query.iter() {
+        commands.entity(entity).despawn_recursive();
+    }
+    // 新房间的帧号重新计数
+    interpolation_buffer.clear();
+}
+
+// 远端实体渲染在落后最新快照的时刻, 在缓冲的快照间插值
+fn interpolate_system(
+    time: Res<Time>,
+    mut interpolation_buffer: ResMut<InterpolationBuffer>,
+    syn_entity_query: Query<(&SynEntity, &RigidBodyHandleComponent), Without<CameraCtrl>>,
+    mut rigid_bodies: ResMut<RigidBodySet>,
+) {
+    interpolation_buffer.advance(time.delta_seconds_f64());
+    for (syn_entity, rb_handle) in syn_entity_query.iter() {
+        // 本地玩家由输入预测控制
+        if syn_entity.entity_type == EntityType::Player
+            && unsafe { PLAYER.uid } == syn_entity.id as u32
+        {
+            continue;
+        }
+        if let Some(state) = interpolation_buffer.sample(syn_entity.entity_type, syn_entity.id) {
+            if let Some(rb) = rigid_bodies.get_mut(rb_handle.handle()) {
+                let mut pos = *rb.position();
+                pos.translation.x = state.translation.0;
+                pos.translation.y = state.translation.1;
+                pos.rotation = UnitComplex::new(state.rotation);
+                rb.set_position(pos, true);
+                // 位置完全由插值决定, 不再叠加物理速度
+                rb.set_linvel(Vector2::zeros(), false);
+                rb.set_angvel(0., false);
+            }
+        }
+    }
+}
+
 fn check_entity_health(
     mut commands: Commands,
     syn_entity_query: Query<(&SynEntity, Entity), Without<CameraCtrl>>,
@@ -66,9 +160,16 @@ fn event_listener_system(
     audio: Res<Audio>,
     mut rigid_bodies: ResMut<RigidBodySet>,
     mut player_query: Query<&mut PlayerData>,
+    mut input_history: ResMut<InputHistory>,
+    mut interpolation_buffer: ResMut<InterpolationBuffer>,
+    time: Res<Time>,
     // mut sync_event_writer: EventWriter<SyncEvent>,
 ) {
     for sync_event in sync_event_reader.iter() {
+        // 乱序或重复的快照直接丢弃
+        if !interpolation_buffer.push(&sync_event.update_data) {
+            continue;
+        }
         let health_now = SystemTime::now()
             .duration_since(SystemTime::UNIX_EPOCH)
             .unwrap()
@@ -81,40 +182,43 @@ fn event_listener_system(
                     syn_entity.health = health_now;
                     syn_entity.animate_type = rigid_body_state.animate;
 
+                    // 远端实体由插值系统按缓冲的快照移动, 这里只校正本地玩家
+                    if rigid_body_state.entity_type != EntityType::Player
+                        || unsafe { PLAYER.uid } != rigid_body_state.id as u32
+                    {
+                        continue 'update_data;
+                    }
+                    if let Ok(mut player_state) = player_query.single_mut() {
+                        player_state.uid = rigid_body_state.id as u32;
+                    }
                     if let Some(rb) = rigid_bodies.get_mut(rb_handle.handle()) {
-                        rb.set_linvel(
-                            [rigid_body_state.linvel.0, rigid_body_state.linvel.1].into(),
-                            true,
-                        );
-                        rb.set_angvel(rigid_body_state.angvel.0, true);
                         let mut pos = rb.position().clone();
-
-                        if (pos.translation.x - rigid_body_state.translation.0).abs() > 10. {
-                            pos.translation.x = rigid_body_state.translation.0;
-                        }
-                        if (pos.translation.y - rigid_body_state.translation.1).abs() > 10. {
-                            pos.translation.y = rigid_body_state.translation.1;
+                        // 以权威坐标重放未确认的输入, 逐步修正预测误差
+                        if let Some(predicted) = input_history.replay(
+                            rigid_body_state.translation,
+                            sync_event.update_data.input,
+                            time.seconds_since_startup(),
+                        ) {
+                            let error = Vector2::new(
+                                predicted.0 - pos.translation.x,
+                                predicted.1 - pos.translation.y,
+                            );
+                            if error.norm() > SNAP_DISTANCE {
+                                pos.translation.x = predicted.0;
+                                pos.translation.y = predicted.1;
+                            } else {
+                                pos.translation.x += error.x * CORRECTION_RATE;
+                                pos.translation.y += error.y * CORRECTION_RATE;
+                            }
                         }
-
+                        input_history.acknowledge(sync_event.update_data.input.seq);
+                        let velocity = input_history.velocity();
+                        rb.set_linvel([velocity.0, velocity.1].into(), true);
                         rb.set_position(pos, true);
-                    }
 
-                    unsafe {
-                        if rigid_body_state.entity_type == EntityType::Player
-                            && PLAYER.uid == rigid_body_state.id as u32
-                        {
-                            if let Ok(mut player_state) = player_query.single_mut() {
-                                player_state.uid = rigid_body_state.id as u32;
-                            }
-
-                            if let Some((mut camera_transform, _)) = camera_query.iter_mut().next()
-                            {
-                                camera_transform.translation = Vec3::new(
-                                    rigid_body_state.translation.0,
-                                    rigid_body_state.translation.1,
-                                    99.0,
-                                );
-                            }
+                        if let Some((mut camera_transform, _)) = camera_query.iter_mut().next() {
+                            camera_transform.translation =
+                                Vec3::new(pos.translation.x, pos.translation.y, 99.0);
                         }
                     }
                     continue 'update_data;
@@ -1,20 +1,35 @@
 use std::{
     io,
     sync::{Arc, Mutex},
-    time::SystemTime,
+    time::{Duration, Instant, SystemTime},
 };
 
-use bevy::prelude::*;
+use bevy::{app::AppExit, prelude::*};
+use common::prediction::InputHistory;
 use protocol::{
-    data::{account_data::AccountData, player_data::PlayerData, update_data::EntityType},
+    channel::Connection,
+    data::{
+        account_data::AccountData,
+        hello_data::{HelloData, HelloResult},
+        interest_data::InterestData,
+        player_data::PlayerData,
+        update_data::EntityType,
+    },
     packet::Packet,
     route::{AccountRoute, GameRoute, HeartbeatRoute},
+    session::Session,
 };
 use tokio::net::UdpSocket;
 
-use crate::engine::event::{heart_beat_event::HeartBeatEvent, sync_event::SyncEvent};
+use crate::engine::event::{
+    combat_event::CombatState,
+    heart_beat_event::HeartBeatEvent,
+    map_event::MapEvent,
+    skill_event::{now_millis, SkillState},
+    sync_event::{InterestEvent, SyncEvent},
+};
 
-use super::player_plugin::PlayerUpdateEvent;
+use super::{player_plugin::PlayerUpdateEvent, ui_plugin::UIState};
 
 // 当前玩家
 pub static mut PLAYER: PlayerData = PlayerData {
@@ -56,7 +71,8 @@ impl Plugin for NetworkPlugin {
             packet_queue,
             to_be_sent_queue,
         })
-        .add_system(net_handler_system.system());
+        .add_system(net_handler_system.system())
+        .add_system_to_stage(CoreStage::PostUpdate, logout_on_exit_system.system());
     }
 }
 
@@ -64,7 +80,13 @@ fn net_handler_system(
     net_state: ResMut<NetWorkState>,
     mut hb_event_writer: EventWriter<HeartBeatEvent>,
     mut sync_event_writer: EventWriter<SyncEvent>,
+    mut interest_event_writer: EventWriter<InterestEvent>,
     mut player_event_writer: EventWriter<PlayerUpdateEvent>,
+    mut map_event_writer: EventWriter<MapEvent>,
+    mut ui_state: ResMut<UIState>,
+    mut input_history: ResMut<InputHistory>,
+    mut skill_state: ResMut<SkillState>,
+    mut combat_state: ResMut<CombatState>,
 ) {
     if let Ok(mut packet_queue) = net_state.packet_queue.lock() {
         // println!("packet_queue: {}", packet_queue.len());
@@ -77,20 +99,50 @@ fn net_handler_system(
 
             match packet {
                 Packet::Heartbeat(heartbeat_route) => match heartbeat_route {
-                    HeartbeatRoute::In => {}
+                    HeartbeatRoute::In(hello_data) => {
+                        // 协议版本不兼容, 提示更新客户端
+                        ui_state.update_required = hello_data.result == HelloResult::Reject;
+                        // 握手成功后才能登录
+                        ui_state.handshaked = hello_data.is_accepted();
+                    }
                     HeartbeatRoute::Out => {}
                     protocol::route::HeartbeatRoute::Keep(time) => {
                         hb_event_writer.send(HeartBeatEvent { time });
                     }
                 },
                 Packet::Account(account_route) => match account_route {
-                    AccountRoute::Login(login_data) => unsafe {
-                        PLAYER.uid = login_data.uid;
-                    },
+                    AccountRoute::Login(_) => {}
                     AccountRoute::Logout(_) => {}
                     AccountRoute::GetInfo(account_data) => unsafe {
                         PLAYER.uid = account_data.uid;
                     },
+                    AccountRoute::Session(session_data) => unsafe {
+                        PLAYER.uid = session_data.uid;
+                    },
+                    AccountRoute::Register(_) => {}
+                    AccountRoute::Enter(account_data) => {
+                        unsafe {
+                            PLAYER.uid = account_data.uid;
+                        }
+                        // 已在游戏中又收到进入消息, 说明切换了房间, 清除旧房间的地图和实体
+                        if ui_state.logged_in && ui_state.room != account_data.group {
+                            map_event_writer.send(MapEvent::Clean);
+                        }
+                        ui_state.room = account_data.group;
+                        // 服务器重新计数已处理的输入
+                        input_history.clear();
+                        // 死亡状态下进入的玩家由服务器立即复活
+                        combat_state.respawn_at = None;
+                        ui_state.logged_in = true;
+                        // 进入游戏后显示玩家状态栏
+                        ui_state.windows_enabled[2] = true;
+                        ui_state.login_form.password.clear();
+                        ui_state.login_form.message.clear();
+                    }
+                    AccountRoute::Failed(failure) => {
+                        ui_state.login_form.message = failure.describe().to_string();
+                    }
+                    AccountRoute::ChangeRoom(_) => {}
                 },
                 Packet::Game(game_route) => match game_route {
                     GameRoute::Update(update_data) => {
@@ -112,31 +164,108 @@ fn net_handler_system(
                         player_event_writer.send(PlayerUpdateEvent { player_list_data });
                     }
                     GameRoute::Skill(_) => {}
+                    GameRoute::Snapshot(_) => {}
+                    GameRoute::SnapshotAck(_) => {}
+                    GameRoute::Interest(interest_data) => {
+                        interest_event_writer.send(InterestEvent { interest_data });
+                    }
+                    // 其他玩家离线, 立即清除其实体
+                    GameRoute::PlayerLeft(uid) => {
+                        interest_event_writer.send(InterestEvent {
+                            interest_data: InterestData {
+                                frame: 0,
+                                entered: Vec::new(),
+                                left: vec![(EntityType::Player, uid as u64)],
+                            },
+                        });
+                    }
+                    // 服务器判定的技能冷却
+                    GameRoute::Cooldown(cooldown_data) => {
+                        skill_state.apply(&cooldown_data, now_millis());
+                    }
+                    GameRoute::Kill(kill_data) => {
+                        combat_state.on_kill(kill_data, unsafe { PLAYER.uid }, now_millis());
+                    }
+                    GameRoute::Respawn(respawn_data) => {
+                        combat_state.on_respawn(&respawn_data, unsafe { PLAYER.uid }, now_millis());
+                    }
                 },
+                Packet::Channel(_) => {}
+                // 会话数据报只由客户端发出
+                Packet::Session(_) => {}
             }
         }
     }
 }
 
+// 退出游戏时登出, 等待发送任务把登出包发出
+fn logout_on_exit_system(
+    mut app_exit_events: EventReader<AppExit>,
+    net_state: Res<NetWorkState>,
+    ui_state: Res<UIState>,
+) {
+    if app_exit_events.iter().next().is_none() || !ui_state.logged_in {
+        return;
+    }
+    if let Ok(mut to_be_sent_queue) = net_state.to_be_sent_queue.lock() {
+        to_be_sent_queue.push(Packet::Account(AccountRoute::Logout(AccountData {
+            uid: unsafe { PLAYER.uid },
+            group: 0,
+        })));
+    }
+    // 发送任务每50ms清空一次队列, 最多等待200ms
+    for _ in 0..4 {
+        std::thread::sleep(Duration::from_millis(50));
+        if net_state
+            .to_be_sent_queue
+            .lock()
+            .map_or(true, |to_be_sent_queue| to_be_sent_queue.is_empty())
+        {
+            break;
+        }
+    }
+}
+
+// 登录后用会话令牌签名数据报
+fn seal(session: &Mutex<Option<Session>>, datagram: Vec<u8>) -> Vec<u8> {
+    match session.lock() {
+        Ok(mut session) => match session.as_mut() {
+            Some(session) => session.seal(datagram),
+            None => datagram,
+        },
+        Err(_) => datagram,
+    }
+}
+
 async fn net_client_start(
     packet_queue: Arc<Mutex<Vec<Packet>>>,
     to_be_sent_queue: Arc<Mutex<Vec<Packet>>>,
 ) -> io::Result<()> {
     // 连接服务器
     println!("客户端网络连接ing...");
-    let sock = UdpSocket::bind(config::CLIENT_ADDR).await?;
-    let remote_addr = config::SERVER_ADDR;
+    let sock = UdpSocket::bind(&config::get().client_addr).await?;
+    let remote_addr = config::get().server_addr.as_str();
     sock.connect(remote_addr).await?;
     println!("客户端网络连接成功: {:?}", sock.local_addr());
     let r = Arc::new(sock);
     let s = r.clone();
 
-    // 登录服务器
+    // 可靠通道, 握手成功后按协商结果启用
+    let nonce: u32 = rand::random();
+    let connection = Arc::new(Mutex::new(Connection::new(0, nonce)));
+    let connection_c = connection.clone();
+
+    // 握手协商协议版本, 成功后再登录
+    let handshake: Arc<Mutex<Option<HelloData>>> = Arc::new(Mutex::new(None));
+    let handshake_c = handshake.clone();
+    // 登录会话, 收到服务器签发的令牌后启用
+    let session: Arc<Mutex<Option<Session>>> = Arc::new(Mutex::new(None));
+    let session_c = session.clone();
+    let session_s = session.clone();
     s.send(
-        &bincode::serialize(&Packet::Account(AccountRoute::Login(AccountData {
-            uid: 0,
-            group: 0,
-        })))
+        &bincode::serialize(&Packet::Heartbeat(HeartbeatRoute::In(HelloData::request(
+            nonce,
+        ))))
         .unwrap()[0..],
     )
     .await
@@ -147,42 +276,127 @@ async fn net_client_start(
         loop {
             interval.tick().await;
             s.send(
-                &bincode::serialize(&Packet::Heartbeat(HeartbeatRoute::Keep(
-                    SystemTime::now()
-                        .duration_since(SystemTime::UNIX_EPOCH)
-                        .unwrap()
-                        .as_millis(),
-                )))
-                .unwrap()[0..],
+                &seal(
+                    &session_c,
+                    bincode::serialize(&Packet::Heartbeat(HeartbeatRoute::Keep(
+                        SystemTime::now()
+                            .duration_since(SystemTime::UNIX_EPOCH)
+                            .unwrap()
+                            .as_millis(),
+                    )))
+                    .unwrap(),
+                )[0..],
             )
             .await
             .unwrap();
 
-            unsafe {
-                if PLAYER.uid == 0 {
-                    s.send(
-                        &bincode::serialize(&Packet::Account(AccountRoute::GetInfo(AccountData {
-                            uid: 0,
-                            group: 0,
-                        })))
-                        .unwrap()[0..],
-                    )
-                    .await
-                    .unwrap();
-                }
+            // 握手包丢失则重发
+            let handshaked = handshake_c.lock().map_or(false, |h| h.is_some());
+            if !handshaked {
+                s.send(
+                    &bincode::serialize(&Packet::Heartbeat(HeartbeatRoute::In(
+                        HelloData::request(nonce),
+                    )))
+                    .unwrap()[0..],
+                )
+                .await
+                .unwrap();
             }
             // println!("发送Heartbeat");
         }
     });
 
-    let mut buf = [0; config::PACKET_SIZE];
+    // 发送队列与可靠通道超时重发
+    let s = r.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(50));
+        loop {
+            interval.tick().await;
+            let datagrams = {
+                let mut datagrams = Vec::new();
+                if let (Ok(mut to_be_sent_queue), Ok(mut connection)) =
+                    (to_be_sent_queue.lock(), connection_c.lock())
+                {
+                    let now = Instant::now();
+                    for to_be_sent_packet in to_be_sent_queue.drain(..) {
+                        match connection.send(&to_be_sent_packet, now) {
+                            Ok(mut sent) => datagrams.append(&mut sent),
+                            Err(e) => println!("{}", e),
+                        }
+                    }
+                    datagrams.append(&mut connection.resend(now));
+                }
+                datagrams
+            };
+            for datagram in datagrams {
+                let _ = s.send(&seal(&session_s, datagram)[..]).await;
+            }
+        }
+    });
+
+    let mut buf = vec![0u8; config::get().packet_size];
     loop {
         // println!("接收ing");
         if let Ok(len) = r.recv(&mut buf).await {
             // println!("接收来自服务器的 {:?} bytes", len);
-            let packet = bincode::deserialize(&buf[..len]);
+            // 可靠通道: 回复确认并按序取出数据包
+            let (packets, replies) = match connection.lock() {
+                Ok(mut connection) => connection.receive(&buf[..len], Instant::now()),
+                Err(_) => (Vec::new(), Vec::new()),
+            };
+            for reply in replies {
+                let _ = r.send(&seal(&session, reply)[..]).await;
+            }
             // 转发事件
-            if let Ok(packet) = packet {
+            for packet in packets {
+                // 服务器签发的会话令牌
+                if let Packet::Account(AccountRoute::Session(session_data)) = &packet {
+                    if let Ok(mut session) = session.lock() {
+                        session.replace(Session::new(session_data.uid, session_data.token));
+                    }
+                }
+                if let Packet::Heartbeat(HeartbeatRoute::In(hello_data)) = &packet {
+                    if hello_data.is_accepted() {
+                        println!("握手成功: {:?}", hello_data);
+                        if let Ok(mut handshake) = handshake.lock() {
+                            handshake.replace(*hello_data);
+                        }
+                        // 启用协商的功能, 登录由界面发起
+                        if let Ok(mut connection) = connection.lock() {
+                            connection.features = hello_data.features;
+                        }
+                    } else {
+                        println!("协议版本不兼容, 请更新客户端: {:?}", hello_data);
+                    }
+                }
+                // 增量快照: 在基准上还原为完整状态并确认
+                let packet = match packet {
+                    Packet::Game(GameRoute::Snapshot(snapshot_data)) => {
+                        let (update_data, datagrams) = {
+                            let mut connection = connection.lock().unwrap();
+                            match connection.snapshots.decode(&snapshot_data) {
+                                Some(snapshot) => (
+                                    snapshot.to_update_data(),
+                                    connection
+                                        .send(
+                                            &Packet::Game(GameRoute::SnapshotAck(
+                                                snapshot_data.seq,
+                                            )),
+                                            Instant::now(),
+                                        )
+                                        .unwrap_or_default(),
+                                ),
+                                // 基准已丢失, 等待服务端回退为完整快照
+                                None => continue,
+                            }
+                        };
+                        for datagram in datagrams {
+                            let _ = r.send(&seal(&session, datagram)[..]).await;
+                        }
+                        Packet::Game(GameRoute::Update(update_data))
+                    }
+                    packet => packet,
+                };
                 if let Ok(mut packet_queue) = packet_queue.lock() {
                     if packet_queue.len() > 512 {
                         packet_queue.remove(0);
@@ -191,19 +405,5 @@ async fn net_client_start(
                 }
             }
         }
-
-        if let Ok(mut to_be_sent_queue) = to_be_sent_queue.lock() {
-            let to_be_sent_queue_c = to_be_sent_queue.clone();
-            to_be_sent_queue.clear();
-            for to_be_sent_packet in to_be_sent_queue_c.iter() {
-                let s = r.clone();
-                let to_be_sent_packet = to_be_sent_packet.clone();
-                tokio::spawn(async move {
-                    s.send(&bincode::serialize(&to_be_sent_packet).unwrap()[0..])
-                        .await
-                        .unwrap();
-                });
-            }
-        }
     }
 }
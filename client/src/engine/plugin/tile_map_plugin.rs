@@ -1,5 +1,9 @@
+use bevy_rapier2d::{
+    na::Vector2,
+    rapier::{dynamics::RigidBodyBuilder, geometry::ColliderBuilder},
+};
 use bevy_tilemap::prelude::*;
-use common::tile_map::get_tile_by_filename;
+use common::{tile_collider::TerrainGrid, tileset};
 use protocol::{
     data::tile_map_data::{Tile, TileMapData, TileState},
     packet::Packet,
@@ -8,6 +12,8 @@ use protocol::{
 
 use bevy::{asset::LoadState, prelude::*, sprite::TextureAtlasBuilder};
 
+use crate::engine::event::map_event::MapEvent;
+
 use super::network_plugin::NetWorkState;
 
 const CHUNK_WIDTH: u32 = 16;
@@ -30,6 +36,7 @@ impl Plugin for TileMapPlugin {
             .add_plugins(TilemapDefaultPlugins)
             .add_startup_system(setup.system())
             .add_system(load.system())
+            .add_system(clean.system())
             .add_system(build.system());
     }
 }
@@ -40,6 +47,9 @@ struct TileMapState {
     spawned: bool,
 }
 
+/// 地形碰撞体, 切换房间时清除
+struct TerrainCollider;
+
 #[derive(Default, Clone)]
 struct TileSpriteHandles {
     handles: Vec<HandleUntyped>,
@@ -48,8 +58,13 @@ struct TileSpriteHandles {
 
 fn get_tile(point: (i32, i32, i32), net_state: &ResMut<NetWorkState>) -> Option<Tile> {
     if let Ok(tile_state) = data::client_db::find_tile_map(point) {
-        let tile = get_tile_by_filename(tile_state.filename);
-        return Some(tile);
+        // 地块定义中没有的地块不显示, 碰撞体以服务器记录的为准
+        return tileset::get()
+            .get(point.2 as usize, &tile_state.filename)
+            .map(|tile| Tile {
+                collider: tile_state.collider,
+                ..tile.clone()
+            });
     } else {
         // println!("获取地图: {:?}", point);
         if let Ok(mut to_be_sent_queue) = net_state.to_be_sent_queue.lock() {
@@ -143,7 +158,25 @@ fn load(
     }
 }
 
+/// 按与服务器相同的区块合并生成地形碰撞体
+fn spawn_terrain_colliders(commands: &mut Commands, grid: &mut TerrainGrid) {
+    for chunk in grid.take_dirty() {
+        for rect in grid.chunk_rects(chunk) {
+            let (half_x, half_y) = rect.half_extents;
+            commands
+                .spawn()
+                .insert(
+                    RigidBodyBuilder::new_static()
+                        .translation(Vector2::new(rect.center.0, rect.center.1)),
+                )
+                .insert(ColliderBuilder::cuboid(half_x, half_y).friction(0.0))
+                .insert(TerrainCollider);
+        }
+    }
+}
+
 fn build(
+    mut commands: Commands,
     mut map_state: ResMut<TileMapState>,
     texture_atlases: Res<Assets<TextureAtlas>>,
     asset_server: Res<AssetServer>,
@@ -157,6 +190,7 @@ fn build(
     for mut map in query.iter_mut() {
         let texture_atlas = texture_atlases.get(map.texture_atlas()).unwrap();
         let mut tiles = Vec::new();
+        let mut grid = TerrainGrid::new();
 
         let min_x = -TILEMAP_WIDTH / 2;
         let max_x = TILEMAP_WIDTH / 2;
@@ -169,6 +203,7 @@ fn build(
                 let tile_point = (x, y);
 
                 if let Some(tile) = get_tile(point, &net_state) {
+                    grid.set(point, tile.collider.clone());
                     // 若最上层也为泥地则不创建精灵
                     if tile.filename.eq("0-tileset_30.png") {
                         continue;
@@ -189,6 +224,42 @@ fn build(
         }
 
         map.insert_tiles(tiles).unwrap();
+        spawn_terrain_colliders(&mut commands, &mut grid);
         map_state.map_loaded = true;
     }
 }
+
+/// 切换房间后清除已加载的地块, 重新请求新房间的地图
+fn clean(
+    mut commands: Commands,
+    mut map_state: ResMut<TileMapState>,
+    mut map_event_reader: EventReader<MapEvent>,
+    mut query: Query<&mut Tilemap>,
+    terrain_query: Query<Entity, With<TerrainCollider>>,
+    net_state: ResMut<NetWorkState>,
+) {
+    let mut cleaned = false;
+    for map_event in map_event_reader.iter() {
+        if let MapEvent::Clean = map_event {
+            cleaned = true;
+        }
+    }
+    if !cleaned {
+        return;
+    }
+    let _ = data::client_db::clear_tile_map();
+    for entity in terrain_query.iter() {
+        commands.entity(entity).despawn();
+    }
+    for mut map in query.iter_mut() {
+        let mut points = Vec::new();
+        for x in -TILEMAP_WIDTH / 2..=TILEMAP_WIDTH / 2 {
+            for y in -TILEMAP_HEIGHT / 2..=TILEMAP_HEIGHT / 2 {
+                points.push(((x, y), 0));
+            }
+        }
+        let _ = map.clear_tiles(points);
+    }
+    map_state.map_loaded = false;
+    refresh_map_data(&net_state);
+}
@@ -2,9 +2,28 @@ use std::collections::BTreeMap;
 
 use bevy::prelude::*;
 use bevy_egui::{
-    egui::{epaint::Shadow, Color32, FontDefinitions, Frame, Id, Image, Label, Stroke, TextureId},
+    egui::{
+        epaint::Shadow, Color32, FontDefinitions, Frame, Id, Image, Label, Stroke, TextEdit,
+        TextureId,
+    },
     EguiPlugin,
 };
+use protocol::{
+    data::{
+        account_data::{
+            is_valid_username, AccountData, AccountFailure, CredentialData, PASSWORD_MIN_LEN,
+        },
+        player_data::PlayerData,
+    },
+    packet::Packet,
+    route::AccountRoute,
+};
+
+use super::network_plugin::{NetWorkState, PLAYER};
+use crate::engine::event::{
+    combat_event::{describe, CombatState},
+    skill_event::{now_millis, SkillState},
+};
 
 const MAIN_MENU_TEXTURE_ID: u64 = 0;
 const BUTTON_TEXTURE_ID: u64 = 1;
@@ -23,6 +42,11 @@ impl Plugin for UIPlugin {
             .insert_resource(UIState {
                 ping: 999f32,
                 windows_enabled: [true, false, false],
+                update_required: false,
+                handshaked: false,
+                logged_in: false,
+                room: 0,
+                login_form: LoginForm::default(),
             })
             .add_startup_system(setup.system())
             .add_system(ui_system.system());
@@ -32,6 +56,51 @@ impl Plugin for UIPlugin {
 pub struct UIState {
     pub ping: f32,
     pub windows_enabled: [bool; 3],
+    // 服务器拒绝当前协议版本
+    pub update_required: bool,
+    // 已完成握手, 可以登录
+    pub handshaked: bool,
+    // 已登录进入游戏
+    pub logged_in: bool,
+    // 当前所在房间
+    pub room: u32,
+    pub login_form: LoginForm,
+}
+
+// 登录/注册表单
+#[derive(Default)]
+pub struct LoginForm {
+    pub username: String,
+    pub password: String,
+    // 登录后进入的房间
+    pub room: u32,
+    // 校验失败或服务器返回的提示
+    pub message: String,
+}
+
+impl LoginForm {
+    // 校验表单, 通过后生成登录或注册请求
+    fn submit(&mut self, register: bool) -> Option<Packet> {
+        if !is_valid_username(&self.username) {
+            self.message = AccountFailure::InvalidUsername.describe().to_string();
+            return None;
+        }
+        if register && self.password.chars().count() < PASSWORD_MIN_LEN {
+            self.message = format!("密码至少{}位", PASSWORD_MIN_LEN);
+            return None;
+        }
+        if self.password.is_empty() {
+            self.message = "请输入密码".to_string();
+            return None;
+        }
+        self.message.clear();
+        let credential_data = CredentialData::new(&self.username, &self.password, self.room);
+        Some(Packet::Account(if register {
+            AccountRoute::Register(credential_data)
+        } else {
+            AccountRoute::Login(credential_data)
+        }))
+    }
 }
 
 fn get_default_fonts() -> FontDefinitions {
@@ -88,6 +157,10 @@ fn ui_system(
     diagnostics: Res<bevy::diagnostic::Diagnostics>,
     mut app_exit_events: EventWriter<bevy::app::AppExit>,
     window: Res<WindowDescriptor>,
+    net_state: Res<NetWorkState>,
+    skill_state: Res<SkillState>,
+    combat_state: Res<CombatState>,
+    player_query: Query<&PlayerData>,
 ) {
     // 性能监控栏
     if ui_state.windows_enabled[0] {
@@ -130,6 +203,69 @@ fn ui_system(
                 );
             });
     }
+    // 版本更新提示
+    if ui_state.update_required {
+        bevy_egui::egui::Window::new("更新提示")
+            .title_bar(false)
+            .id(Id::new(4))
+            .resizable(false)
+            .fixed_rect(bevy_egui::egui::Rect::from_center_size(
+                bevy_egui::egui::Pos2::new(window.width / 2., window.height / 2.),
+                bevy_egui::egui::Vec2::new(300., 100.),
+            ))
+            .show(egui_context.ctx(), |ui| {
+                ui.add(
+                    Label::new("客户端版本过低, 请更新后重新启动")
+                        .text_style(bevy_egui::egui::TextStyle::Heading),
+                );
+                if ui.button("退出游戏").clicked() {
+                    app_exit_events.send(bevy::app::AppExit);
+                }
+            });
+    }
+    // 登录/注册
+    if !ui_state.logged_in && !ui_state.update_required {
+        bevy_egui::egui::Window::new("登录")
+            .title_bar(false)
+            .id(Id::new(5))
+            .resizable(false)
+            .fixed_rect(bevy_egui::egui::Rect::from_center_size(
+                bevy_egui::egui::Pos2::new(window.width / 2., window.height / 2.),
+                bevy_egui::egui::Vec2::new(300., 190.),
+            ))
+            .show(egui_context.ctx(), |ui| {
+                let handshaked = ui_state.handshaked;
+                let form = &mut ui_state.login_form;
+                ui.label("用户名");
+                ui.add(TextEdit::singleline(&mut form.username));
+                ui.label("密码");
+                ui.add(TextEdit::singleline(&mut form.password).password(true));
+                ui.horizontal(|ui| {
+                    for room in config::get().rooms.iter() {
+                        ui.radio_value(&mut form.room, *room, format!("房间{}", room));
+                    }
+                });
+                if !handshaked {
+                    ui.label("正在连接服务器...");
+                } else if !form.message.is_empty() {
+                    ui.colored_label(Color32::from_rgb(200, 60, 60), form.message.clone());
+                }
+                let mut request = None;
+                ui.horizontal(|ui| {
+                    if ui.button("登录").clicked() && handshaked {
+                        request = form.submit(false);
+                    }
+                    if ui.button("注册").clicked() && handshaked {
+                        request = form.submit(true);
+                    }
+                });
+                if let Some(packet) = request {
+                    if let Ok(mut to_be_sent_queue) = net_state.to_be_sent_queue.lock() {
+                        to_be_sent_queue.push(packet);
+                    }
+                }
+            });
+    }
     // 主菜单
     if ui_state.windows_enabled[1] {
         bevy_egui::egui::Window::new("主菜单")
@@ -174,6 +310,36 @@ fn ui_system(
                 }
                 let widget_rect = bevy_egui::egui::Rect::from_center_size(
                     ui.min_rect().min + bevy_egui::egui::Vec2::new(150., 150.),
+                    bevy_egui::egui::Vec2::new(160., 32.),
+                );
+                if ui
+                    .put(
+                        widget_rect,
+                        bevy_egui::egui::Button::new(format!("切换房间(当前{})", ui_state.room))
+                            .fill(Color32::from_rgba_unmultiplied(0, 0, 0, 0)),
+                    )
+                    .clicked()
+                    && ui_state.logged_in
+                {
+                    // 依次切换到下一个房间
+                    let rooms = &config::get().rooms;
+                    let index = rooms
+                        .iter()
+                        .position(|room| *room == ui_state.room)
+                        .map_or(0, |index| (index + 1) % rooms.len());
+                    if let Some(group) = rooms.get(index) {
+                        let account_data = AccountData {
+                            uid: unsafe { PLAYER.uid },
+                            group: *group,
+                        };
+                        if let Ok(mut to_be_sent_queue) = net_state.to_be_sent_queue.lock() {
+                            to_be_sent_queue
+                                .push(Packet::Account(AccountRoute::ChangeRoom(account_data)));
+                        }
+                    }
+                }
+                let widget_rect = bevy_egui::egui::Rect::from_center_size(
+                    ui.min_rect().min + bevy_egui::egui::Vec2::new(150., 200.),
                     bevy_egui::egui::Vec2::new(100., 32.),
                 );
                 if ui
@@ -294,6 +460,68 @@ fn ui_system(
                     Image::new(TextureId::User(INVENTORY_CASE_TEXTURE_ID), (49., 53.))
                         .bg_fill(Color32::TRANSPARENT),
                 );
+                // 技能栏显示技能名称, 冷却中显示剩余秒数
+                for skill in common::skill::get().iter() {
+                    let center = ui.min_rect().min
+                        + bevy_egui::egui::Vec2::new(50. + (skill.slot as f32 - 1.) * 60., 31.);
+                    let remaining = skill_state.remaining(skill.skill_type, now_millis());
+                    let label = if remaining > 0 {
+                        Label::new(format!("{:.1}", remaining as f32 / 1000.))
+                            .text_style(bevy_egui::egui::TextStyle::Heading)
+                    } else {
+                        Label::new(&skill.name)
+                    };
+                    ui.put(
+                        bevy_egui::egui::Rect::from_center_size(
+                            center,
+                            bevy_egui::egui::Vec2::new(49., 53.),
+                        ),
+                        label,
+                    );
+                }
+                // 血量和魔力值
+                let uid = unsafe { PLAYER.uid };
+                if let Some(player) = player_query.iter().find(|player| player.uid == uid) {
+                    let rect = bevy_egui::egui::Rect::from_center_size(
+                        ui.min_rect().min + bevy_egui::egui::Vec2::new(200., 4.),
+                        bevy_egui::egui::Vec2::new(368., 8.),
+                    );
+                    ui.put(
+                        rect,
+                        Label::new(format!(
+                            "HP {}/{}  MP {}/{}",
+                            player.hp, player.max_hp, player.mp, player.max_mp
+                        )),
+                    );
+                }
+            });
+        // 死亡时显示复活倒计时, 复活后显示无敌剩余时间
+        let now = now_millis();
+        let status = match combat_state.respawn_at {
+            Some(respawn_at) => Some(format!(
+                "已阵亡, {:.1}秒后复活",
+                respawn_at.saturating_sub(now) as f32 / 1000.
+            )),
+            None if combat_state.invulnerable_until > now => Some(format!(
+                "无敌 {:.1}秒",
+                (combat_state.invulnerable_until - now) as f32 / 1000.
+            )),
+            None => None,
+        };
+        if let Some(status) = status {
+            bevy_egui::egui::Area::new("死亡状态")
+                .fixed_pos((window.width / 2. - 80., window.height / 2. - 120.))
+                .show(egui_context.ctx(), |ui| {
+                    ui.add(Label::new(status).text_style(bevy_egui::egui::TextStyle::Heading));
+                });
+        }
+        // 击杀通知
+        bevy_egui::egui::Area::new("击杀通知")
+            .fixed_pos((window.width - 300., 10.))
+            .show(egui_context.ctx(), |ui| {
+                for kill in combat_state.recent_kills(now) {
+                    ui.label(describe(kill));
+                }
             });
     }
 }
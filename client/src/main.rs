@@ -4,5 +4,23 @@
 use client::engine::engine_client::engine_start;
 
 fn main() {
+    // 加载配置文件, 环境变量和命令行参数
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    // 技能栏按技能定义绑定
+    if let Err(e) = common::skill::load(&config.skills_file) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    // 地图按地块定义显示
+    if let Err(e) = common::tileset::load(&config.tiles_file) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
     engine_start();
 }
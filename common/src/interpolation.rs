@@ -0,0 +1,225 @@
+use std::collections::{HashMap, VecDeque};
+use std::f32::consts::PI;
+
+use protocol::data::update_data::{EntityState, EntityType, UpdateData};
+
+/// 最多缓存的快照数
+pub const SNAPSHOT_BUFFER: usize = 32;
+
+/// 一帧快照, 按实体索引
+struct BufferedSnapshot {
+    frame: u128,
+    entities: HashMap<(EntityType, u64), EntityState>,
+}
+
+/// 远端实体插值缓冲
+/// 按物理帧号缓存快照, 以落后最新快照delay的时刻渲染, 在前后两帧之间插值
+/// 快照丢失导致渲染时刻超过最新快照时, 按速度外推不超过max_extrapolation
+pub struct InterpolationBuffer {
+    // 每个物理帧的时长(秒)
+    frame_time: f64,
+    // 渲染落后最新快照的时长(秒)
+    delay: f64,
+    // 最长外推时长(秒)
+    max_extrapolation: f64,
+    snapshots: VecDeque<BufferedSnapshot>,
+    // 当前渲染的服务器时刻(秒)
+    render_time: Option<f64>,
+}
+
+impl InterpolationBuffer {
+    pub fn new(tick_rate: u32, delay: f64, max_extrapolation: f64) -> Self {
+        InterpolationBuffer {
+            frame_time: 1f64 / tick_rate.max(1) as f64,
+            delay,
+            max_extrapolation,
+            snapshots: VecDeque::new(),
+            render_time: None,
+        }
+    }
+
+    /// 最新快照的帧号
+    pub fn newest_frame(&self) -> Option<u128> {
+        self.snapshots.back().map(|snapshot| snapshot.frame)
+    }
+
+    /// 缓存快照, 帧号不大于最新快照(乱序或重复)时丢弃并返回false
+    pub fn push(&mut self, update_data: &UpdateData) -> bool {
+        if let Some(newest) = self.newest_frame() {
+            if update_data.frame <= newest {
+                return false;
+            }
+        }
+        let entities = update_data
+            .states
+            .iter()
+            .map(|state| ((state.entity_type, state.id), *state))
+            .collect();
+        self.snapshots.push_back(BufferedSnapshot {
+            frame: update_data.frame,
+            entities,
+        });
+        while self.snapshots.len() > SNAPSHOT_BUFFER {
+            self.snapshots.pop_front();
+        }
+        true
+    }
+
+    /// 清空缓存, 切换房间后帧号重新开始
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+        self.render_time = None;
+    }
+
+    /// 推进渲染时刻, 逐步追赶目标(最新快照 - delay), 落后过多时直接对齐
+    pub fn advance(&mut self, delta: f64) {
+        let newest = match self.newest_frame() {
+            Some(newest) => newest as f64 * self.frame_time,
+            None => return,
+        };
+        let target = newest - self.delay;
+        let mut render_time = match self.render_time {
+            // 落后目标时加速, 超前时减速, 避免网络抖动造成画面跳动
+            Some(render_time) if render_time < target => render_time + delta * 1.1,
+            Some(render_time) if render_time > target => render_time + delta * 0.9,
+            Some(render_time) => render_time + delta,
+            None => target,
+        };
+        if render_time < target - self.delay.max(self.frame_time) {
+            render_time = target;
+        }
+        let render_time = render_time.min(newest + self.max_extrapolation);
+        self.render_time = Some(render_time);
+        // 丢弃渲染时刻之前不再需要的快照, 保留一帧作为插值起点
+        while self.snapshots.len() > 2
+            && self.snapshots[1].frame as f64 * self.frame_time <= render_time
+        {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// 渲染时刻的实体状态
+    pub fn sample(&self, entity_type: EntityType, id: u64) -> Option<EntityState> {
+        let render_time = self.render_time?;
+        let key = (entity_type, id);
+        let time = |snapshot: &BufferedSnapshot| snapshot.frame as f64 * self.frame_time;
+        // 渲染时刻之前最近的快照, 以及之后最近的快照
+        let from =
+            self.snapshots.iter().rev().find(|snapshot| {
+                time(snapshot) <= render_time && snapshot.entities.contains_key(&key)
+            });
+        let to = self
+            .snapshots
+            .iter()
+            .find(|snapshot| time(snapshot) > render_time && snapshot.entities.contains_key(&key));
+        match (from, to) {
+            (Some(from), Some(to)) => {
+                let t = ((render_time - time(from)) / (time(to) - time(from))) as f32;
+                Some(lerp_state(&from.entities[&key], &to.entities[&key], t))
+            }
+            (Some(from), None) => {
+                // 快照丢失, 按最后已知速度外推
+                let elapsed = (render_time - time(from)).min(self.max_extrapolation) as f32;
+                let mut state = from.entities[&key];
+                state.translation.0 += state.linvel.0 * elapsed;
+                state.translation.1 += state.linvel.1 * elapsed;
+                state.rotation += state.angvel.0 * elapsed;
+                Some(state)
+            }
+            // 实体刚进入视野, 还没有更早的快照
+            (None, Some(to)) => Some(to.entities[&key]),
+            (None, None) => None,
+        }
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + (b - a) * t
+}
+
+// 角度沿最短方向插值
+fn lerp_angle(a: f32, b: f32, t: f32) -> f32 {
+    let delta = (b - a + PI).rem_euclid(2. * PI) - PI;
+    a + delta * t
+}
+
+fn lerp_state(from: &EntityState, to: &EntityState, t: f32) -> EntityState {
+    let mut state = *to;
+    state.translation = (
+        lerp(from.translation.0, to.translation.0, t),
+        lerp(from.translation.1, to.translation.1, t),
+    );
+    state.rotation = lerp_angle(from.rotation, to.rotation, t);
+    state.linvel = (
+        lerp(from.linvel.0, to.linvel.0, t),
+        lerp(from.linvel.1, to.linvel.1, t),
+    );
+    state
+}
+
+#[cfg(test)]
+fn update_data(frame: u128, x: f32) -> UpdateData {
+    UpdateData {
+        frame,
+        states: vec![EntityState {
+            id: 1,
+            translation: (x, 0.),
+            rotation: 0.,
+            linvel: (60., 0.),
+            angvel: (0., 0.),
+            texture: (0, 0, 0),
+            entity_type: EntityType::Trap,
+            animate: 0,
+        }],
+        input: Default::default(),
+    }
+}
+
+#[test]
+fn test_interpolate() {
+    // 10帧/秒, 延迟0.2秒
+    let mut buffer = InterpolationBuffer::new(10, 0.2, 0.3);
+    assert!(buffer.push(&update_data(10, 0.)));
+    assert!(buffer.push(&update_data(11, 6.)));
+    assert!(buffer.push(&update_data(12, 12.)));
+    // 乱序和重复的帧被丢弃
+    assert!(!buffer.push(&update_data(11, 100.)));
+    assert!(!buffer.push(&update_data(12, 100.)));
+
+    // 渲染时刻对齐到最新快照之前0.2秒, 即第10帧
+    buffer.advance(0.);
+    let state = buffer.sample(EntityType::Trap, 1).unwrap();
+    assert!((state.translation.0 - 0.).abs() < 0.001);
+    // 第10帧与第11帧之间插值
+    buffer.advance(0.05);
+    let state = buffer.sample(EntityType::Trap, 1).unwrap();
+    assert!((state.translation.0 - 3.).abs() < 0.001);
+    assert!(buffer.sample(EntityType::Trap, 2).is_none());
+}
+
+#[test]
+fn test_extrapolate() {
+    let mut buffer = InterpolationBuffer::new(10, 0.1, 0.2);
+    buffer.push(&update_data(10, 0.));
+    buffer.push(&update_data(11, 6.));
+    buffer.advance(0.);
+    // 快照中断后按速度外推, 不超过最长外推时长
+    buffer.advance(0.15);
+    let state = buffer.sample(EntityType::Trap, 1).unwrap();
+    assert!((state.translation.0 - 9.).abs() < 0.001);
+    buffer.advance(1.);
+    let state = buffer.sample(EntityType::Trap, 1).unwrap();
+    assert!((state.translation.0 - 18.).abs() < 0.001);
+    // 恢复后落后过多, 重新对齐到最新快照之前, 在第11帧与第20帧之间插值
+    buffer.push(&update_data(20, 60.));
+    buffer.advance(0.);
+    let state = buffer.sample(EntityType::Trap, 1).unwrap();
+    assert!((state.translation.0 - 54.).abs() < 0.01);
+}
+
+#[test]
+fn test_lerp_angle() {
+    // 跨越±PI时走最短方向
+    let angle = lerp_angle(PI - 0.1, -PI + 0.1, 0.5);
+    assert!((angle.abs() - PI).abs() < 0.001);
+}
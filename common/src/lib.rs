@@ -1 +1,6 @@
+pub mod interpolation;
+pub mod prediction;
+pub mod skill;
+pub mod tile_collider;
 pub mod tile_map;
+pub mod tileset;
@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+
+use protocol::data::control_data::InputAckData;
+
+/// 最多保留的未确认输入, 长时间收不到确认时丢弃最旧的输入
+pub const INPUT_HISTORY: usize = 256;
+
+/// 已发送的输入
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PendingInput {
+    pub seq: u32,
+    // 输入对应的速度
+    pub velocity: (f32, f32),
+    // 本地发送时间(秒)
+    pub time: f64,
+}
+
+/// 客户端输入历史, 收到权威状态后重放未确认的输入得到预测位置
+#[derive(Debug, Default)]
+pub struct InputHistory {
+    last_seq: u32,
+    inputs: VecDeque<PendingInput>,
+}
+
+impl InputHistory {
+    /// 记录一次输入, 返回分配的序号(从1开始)
+    pub fn push(&mut self, velocity: (f32, f32), time: f64) -> u32 {
+        self.last_seq = self.last_seq.wrapping_add(1).max(1);
+        self.inputs.push_back(PendingInput {
+            seq: self.last_seq,
+            velocity,
+            time,
+        });
+        while self.inputs.len() > INPUT_HISTORY {
+            self.inputs.pop_front();
+        }
+        self.last_seq
+    }
+
+    /// 重新进入世界时清空历史, 序号继续递增避免与在途的旧输入混淆
+    pub fn clear(&mut self) {
+        self.inputs.clear();
+    }
+
+    /// 当前生效的输入速度
+    pub fn velocity(&self) -> (f32, f32) {
+        self.inputs.back().map_or((0., 0.), |input| input.velocity)
+    }
+
+    /// 丢弃已确认序号之前的输入, 已确认的输入保留作为重放起点
+    pub fn acknowledge(&mut self, seq: u32) {
+        while self.inputs.len() > 1 && self.inputs[1].seq <= seq {
+            self.inputs.pop_front();
+        }
+    }
+
+    /// 以服务器权威坐标为起点重放输入, 得到当前时刻的预测坐标
+    /// 服务器坐标对应确认输入生效后经过elapsed的时刻, 此后的移动由本地输入历史补上
+    /// 确认的输入已不在历史中(过期的快照)时返回None
+    pub fn replay(
+        &self,
+        position: (f32, f32),
+        input: InputAckData,
+        now: f64,
+    ) -> Option<(f32, f32)> {
+        let start = if input.seq == 0 {
+            // 服务器尚未处理任何输入, 静止在权威坐标
+            f64::MIN
+        } else {
+            let acked = self
+                .inputs
+                .iter()
+                .find(|pending| pending.seq == input.seq)?;
+            acked.time + input.elapsed as f64 / 1000.
+        };
+        let (mut x, mut y) = position;
+        for (index, pending) in self.inputs.iter().enumerate() {
+            if pending.seq < input.seq {
+                continue;
+            }
+            let end = self.inputs.get(index + 1).map_or(now, |next| next.time);
+            let duration = (end.min(now) - pending.time.max(start)).max(0.) as f32;
+            x += pending.velocity.0 * duration;
+            y += pending.velocity.1 * duration;
+        }
+        Some((x, y))
+    }
+}
+
+#[test]
+fn test_replay() {
+    let mut history = InputHistory::default();
+    // 未输入时停在权威坐标
+    assert_eq!(
+        history.replay((5., 5.), InputAckData::default(), 1.),
+        Some((5., 5.))
+    );
+
+    assert_eq!(history.push((100., 0.), 1.0), 1);
+    assert_eq!(history.push((0., 100.), 2.0), 2);
+    assert_eq!(history.push((0., 0.), 2.5), 3);
+    assert_eq!(history.velocity(), (0., 0.));
+
+    // 服务器尚未处理任何输入: 重放全部输入
+    let position = history.replay((0., 0.), InputAckData::default(), 3.0);
+    assert_eq!(position, Some((100., 50.)));
+
+    // 服务器处理了输入1并模拟了0.5秒, 剩余0.5秒由本地补上
+    let input = InputAckData {
+        seq: 1,
+        elapsed: 500,
+    };
+    let position = history.replay((50., 0.), input, 3.0);
+    assert_eq!(position, Some((100., 50.)));
+
+    // 确认后旧输入被丢弃, 过期的确认无法重放
+    history.acknowledge(2);
+    assert!(history.replay((50., 0.), input, 3.0).is_none());
+    let input = InputAckData {
+        seq: 2,
+        elapsed: 200,
+    };
+    let position = history.replay((100., 20.), input, 3.0);
+    assert_eq!(position, Some((100., 50.)));
+}
+
+#[test]
+fn test_history_limit() {
+    let mut history = InputHistory::default();
+    for index in 0..INPUT_HISTORY + 10 {
+        history.push((1., 0.), index as f64);
+    }
+    assert_eq!(history.inputs.len(), INPUT_HISTORY);
+    assert_eq!(history.inputs[0].seq, 11);
+    // 确认全部输入后保留最后一条作为起点
+    history.acknowledge(u32::MAX);
+    assert_eq!(history.inputs.len(), 1);
+    assert_eq!(history.velocity(), (1., 0.));
+    history.clear();
+    assert_eq!(history.velocity(), (0., 0.));
+    assert_eq!(history.push((1., 0.), 0.), INPUT_HISTORY as u32 + 11);
+}
@@ -0,0 +1,382 @@
+use std::{collections::HashMap, fmt, path::Path, sync::OnceLock};
+
+use protocol::data::skill_data::SkillType;
+use serde::{Deserialize, Serialize};
+
+/// 技能栏格数, 客户端按键1-6对应栏位1-6
+pub const SKILL_SLOTS: u8 = 6;
+
+static SKILL_BOOK: OnceLock<SkillBook> = OnceLock::new();
+
+/// 技能形态
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum SkillShape {
+    /// 沿施法方向飞行的圆形弹道, spin为角速度, 飞行超过range后移除
+    Projectile {
+        radius: f32,
+        speed: f32,
+        spin: f32,
+        range: f32,
+    },
+    /// 施法者前方的扇形, angle为扇形总角度(度)
+    MeleeArc { range: f32, angle: f32 },
+    /// 施法方向上距离range处爆炸, 范围为area_radius
+    AreaBlast { range: f32 },
+    /// 施法者以speed向施法方向冲刺distance
+    Dash { distance: f32, speed: f32 },
+}
+
+/// 技能定义
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SkillDefinition {
+    pub skill_type: SkillType,
+    /// 显示名称
+    pub name: String,
+    /// 技能栏位置, 从1开始
+    pub slot: u8,
+    pub shape: SkillShape,
+    /// 技能实体存在时长(毫秒), 弹道到期移除, 其他形态为特效显示时长, 0为不生成实体
+    pub lifetime: u32,
+    pub damage: u32,
+    /// 魔力消耗
+    pub mp_cost: u32,
+    /// 冷却(毫秒)
+    pub cooldown: u32,
+    /// 弹道命中后是否继续飞行
+    #[serde(default)]
+    pub piercing: bool,
+    /// 范围伤害半径, 弹道命中时对范围内的玩家造成伤害, 0为只伤害命中目标
+    #[serde(default)]
+    pub area_radius: f32,
+    pub texture: (u32, u8, u8),
+}
+
+#[derive(Debug)]
+pub enum SkillError {
+    /// 技能文件读取失败
+    Io(String, std::io::Error),
+    /// 技能文件格式错误
+    Parse(String, String),
+    /// 技能重复定义
+    Duplicate(SkillType),
+    /// 技能栏位置重复或超出范围
+    Slot(SkillType, u8),
+    /// 数值无效
+    Invalid(SkillType, &'static str),
+}
+
+impl fmt::Display for SkillError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkillError::Io(path, e) => write!(f, "读取技能文件{}失败: {}", path, e),
+            SkillError::Parse(path, e) => write!(f, "技能文件{}格式错误: {}", path, e),
+            SkillError::Duplicate(skill_type) => write!(f, "技能{:?}重复定义", skill_type),
+            SkillError::Slot(skill_type, slot) => {
+                write!(f, "技能{:?}的栏位{}重复或超出范围", skill_type, slot)
+            }
+            SkillError::Invalid(skill_type, field) => {
+                write!(f, "技能{:?}的{}无效", skill_type, field)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SkillError {}
+
+impl SkillDefinition {
+    fn validate(&self) -> Result<(), SkillError> {
+        let invalid = |field| Err(SkillError::Invalid(self.skill_type, field));
+        let positive = |value: f32| value.is_finite() && value > 0.;
+        match self.shape {
+            SkillShape::Projectile {
+                radius,
+                speed,
+                spin,
+                range,
+            } => {
+                if !positive(radius) {
+                    return invalid("radius");
+                }
+                if !positive(speed) {
+                    return invalid("speed");
+                }
+                if !spin.is_finite() {
+                    return invalid("spin");
+                }
+                if !positive(range) {
+                    return invalid("range");
+                }
+                // 弹道没有存在时长会一直飞到世界边界
+                if self.lifetime == 0 {
+                    return invalid("lifetime");
+                }
+            }
+            SkillShape::MeleeArc { range, angle } => {
+                if !positive(range) {
+                    return invalid("range");
+                }
+                if !positive(angle) || angle > 360. {
+                    return invalid("angle");
+                }
+            }
+            SkillShape::AreaBlast { range } => {
+                if !range.is_finite() || range < 0. {
+                    return invalid("range");
+                }
+                if !positive(self.area_radius) {
+                    return invalid("area_radius");
+                }
+            }
+            SkillShape::Dash { distance, speed } => {
+                if !positive(distance) {
+                    return invalid("distance");
+                }
+                if !positive(speed) {
+                    return invalid("speed");
+                }
+            }
+        }
+        if !self.area_radius.is_finite() || self.area_radius < 0. {
+            return invalid("area_radius");
+        }
+        Ok(())
+    }
+
+    /// 冲刺持续时长(毫秒)
+    pub fn dash_duration(&self) -> Option<u32> {
+        match self.shape {
+            SkillShape::Dash { distance, speed } => Some((distance / speed * 1000.) as u32),
+            _ => None,
+        }
+    }
+}
+
+/// 技能表, 服务器按此判定技能效果, 客户端按此绑定技能栏
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SkillBook {
+    #[serde(rename = "skill")]
+    skills: Vec<SkillDefinition>,
+}
+
+impl Default for SkillBook {
+    fn default() -> Self {
+        SkillBook {
+            skills: vec![
+                SkillDefinition {
+                    skill_type: SkillType::Shot,
+                    name: "射击".to_string(),
+                    slot: 1,
+                    shape: SkillShape::Projectile {
+                        radius: 5.,
+                        speed: 1000.,
+                        spin: 60.,
+                        range: 1500.,
+                    },
+                    lifetime: 2000,
+                    damage: 5,
+                    mp_cost: 5,
+                    cooldown: 500,
+                    piercing: false,
+                    area_radius: 0.,
+                    texture: (0, 6, 1),
+                },
+                SkillDefinition {
+                    skill_type: SkillType::MeleeArc,
+                    name: "斩击".to_string(),
+                    slot: 2,
+                    shape: SkillShape::MeleeArc {
+                        range: 80.,
+                        angle: 120.,
+                    },
+                    lifetime: 200,
+                    damage: 15,
+                    mp_cost: 10,
+                    cooldown: 800,
+                    piercing: false,
+                    area_radius: 0.,
+                    texture: (1, 7, 1),
+                },
+                SkillDefinition {
+                    skill_type: SkillType::AreaBlast,
+                    name: "爆破".to_string(),
+                    slot: 3,
+                    shape: SkillShape::AreaBlast { range: 200. },
+                    lifetime: 300,
+                    damage: 20,
+                    mp_cost: 30,
+                    cooldown: 3000,
+                    piercing: false,
+                    area_radius: 100.,
+                    texture: (1, 7, 1),
+                },
+                SkillDefinition {
+                    skill_type: SkillType::Dash,
+                    name: "冲刺".to_string(),
+                    slot: 4,
+                    shape: SkillShape::Dash {
+                        distance: 200.,
+                        speed: 800.,
+                    },
+                    lifetime: 0,
+                    damage: 0,
+                    mp_cost: 15,
+                    cooldown: 2000,
+                    piercing: false,
+                    area_radius: 0.,
+                    texture: (0, 6, 1),
+                },
+            ],
+        }
+    }
+}
+
+impl SkillBook {
+    /// 读取TOML技能文件
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<SkillBook, SkillError> {
+        let path = path.as_ref().display().to_string();
+        let text = std::fs::read_to_string(&path).map_err(|e| SkillError::Io(path.clone(), e))?;
+        SkillBook::from_toml(&path, &text)
+    }
+
+    pub fn from_toml(path: &str, text: &str) -> Result<SkillBook, SkillError> {
+        let book: SkillBook =
+            toml::from_str(text).map_err(|e| SkillError::Parse(path.to_string(), e.to_string()))?;
+        book.validate()?;
+        Ok(book)
+    }
+
+    /// 校验数值, 每种技能和每个栏位只能出现一次
+    pub fn validate(&self) -> Result<(), SkillError> {
+        let mut types = HashMap::new();
+        let mut slots = HashMap::new();
+        for skill in self.skills.iter() {
+            if types.insert(skill.skill_type, ()).is_some() {
+                return Err(SkillError::Duplicate(skill.skill_type));
+            }
+            if skill.slot == 0 || skill.slot > SKILL_SLOTS || slots.insert(skill.slot, ()).is_some()
+            {
+                return Err(SkillError::Slot(skill.skill_type, skill.slot));
+            }
+            skill.validate()?;
+        }
+        Ok(())
+    }
+
+    /// 技能定义, 未定义的技能无法释放
+    pub fn get(&self, skill_type: SkillType) -> Option<&SkillDefinition> {
+        self.skills
+            .iter()
+            .find(|skill| skill.skill_type == skill_type)
+    }
+
+    /// 技能栏位置上的技能
+    pub fn by_slot(&self, slot: u8) -> Option<&SkillDefinition> {
+        self.skills.iter().find(|skill| skill.slot == slot)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &SkillDefinition> {
+        self.skills.iter()
+    }
+}
+
+/// 读取技能文件作为全局技能表, 文件不存在时使用内置技能
+pub fn load<P: AsRef<Path>>(path: P) -> Result<&'static SkillBook, SkillError> {
+    let book = if path.as_ref().exists() {
+        SkillBook::from_file(path)?
+    } else {
+        println!("技能文件{}不存在, 使用内置技能", path.as_ref().display());
+        SkillBook::default()
+    };
+    Ok(SKILL_BOOK.get_or_init(|| book))
+}
+
+/// 全局技能表, 未调用load时使用内置技能
+pub fn get() -> &'static SkillBook {
+    SKILL_BOOK.get_or_init(SkillBook::default)
+}
+
+#[test]
+fn test_skill_book() {
+    let book = SkillBook::default();
+    assert!(book.validate().is_ok());
+    for skill_type in SkillType::ALL.iter() {
+        assert_eq!(book.get(*skill_type).unwrap().skill_type, *skill_type);
+    }
+    assert_eq!(book.by_slot(1).unwrap().skill_type, SkillType::Shot);
+    assert!(book.by_slot(SKILL_SLOTS).is_none());
+    assert_eq!(
+        book.get(SkillType::Dash).unwrap().dash_duration(),
+        Some(250)
+    );
+    // 随仓库发布的技能文件与内置技能保持一致
+    assert_eq!(SkillBook::from_file("../skills.toml").unwrap(), book);
+}
+
+#[test]
+fn test_skill_book_errors() {
+    let skill = |skill_type: &str, slot: u8, shape: &str| {
+        format!(
+            "[[skill]]\nskill_type = \"{}\"\nname = \"a\"\nslot = {}\nshape = {}\n\
+             lifetime = 100\ndamage = 1\nmp_cost = 1\ncooldown = 100\ntexture = [0, 1, 1]\n",
+            skill_type, slot, shape
+        )
+    };
+    let projectile =
+        "{ kind = \"projectile\", radius = 5.0, speed = 100.0, spin = 0.0, range = 500.0 }";
+    let text = skill("Shot", 1, projectile);
+    let book = SkillBook::from_toml("test.toml", &text).unwrap();
+    assert!(!book.get(SkillType::Shot).unwrap().piercing);
+    assert!(book.get(SkillType::Dash).is_none());
+
+    let text = skill("Shot", 1, projectile) + &skill("Shot", 2, projectile);
+    assert!(matches!(
+        SkillBook::from_toml("test.toml", &text),
+        Err(SkillError::Duplicate(SkillType::Shot))
+    ));
+    let text = skill("Shot", 1, projectile) + &skill("Dash", 1, projectile);
+    assert!(matches!(
+        SkillBook::from_toml("test.toml", &text),
+        Err(SkillError::Slot(SkillType::Dash, 1))
+    ));
+    let text = skill("Shot", SKILL_SLOTS + 1, projectile);
+    assert!(matches!(
+        SkillBook::from_toml("test.toml", &text),
+        Err(SkillError::Slot(..))
+    ));
+    // 范围爆炸必须有范围
+    let text = skill("AreaBlast", 1, "{ kind = \"area_blast\", range = 10.0 }");
+    assert!(matches!(
+        SkillBook::from_toml("test.toml", &text),
+        Err(SkillError::Invalid(SkillType::AreaBlast, "area_radius"))
+    ));
+    let text = skill(
+        "MeleeArc",
+        1,
+        "{ kind = \"melee_arc\", range = 10.0, angle = 400.0 }",
+    );
+    assert!(matches!(
+        SkillBook::from_toml("test.toml", &text),
+        Err(SkillError::Invalid(SkillType::MeleeArc, "angle"))
+    ));
+    // 未知的技能形态和字段
+    let text = skill("Shot", 1, "{ kind = \"laser\" }");
+    assert!(matches!(
+        SkillBook::from_toml("test.toml", &text),
+        Err(SkillError::Parse(..))
+    ));
+    // 弹道必须有飞行距离
+    let text = skill(
+        "Shot",
+        1,
+        "{ kind = \"projectile\", radius = 5.0, speed = 100.0, spin = 0.0, range = 0.0 }",
+    );
+    assert!(matches!(
+        SkillBook::from_toml("test.toml", &text),
+        Err(SkillError::Invalid(SkillType::Shot, "range"))
+    ));
+    let text = skill("Shot", 1, projectile) + "color = 1\n";
+    assert!(SkillBook::from_toml("test.toml", &text).is_err());
+}
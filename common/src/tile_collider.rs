@@ -0,0 +1,341 @@
+use std::collections::{HashMap, HashSet};
+
+use protocol::data::tile_map_data::TileCollider;
+
+/// 地块边长(像素)
+pub const TILE_SIZE: f32 = 64.0;
+/// 区块边长(地块数), 地形碰撞体按区块合并和重建
+pub const CHUNK_TILES: i32 = 16;
+/// 合并网格的单元边长, 所有碰撞体类型都对齐到四分之一地块
+const CELL_SIZE: f32 = TILE_SIZE / 4.;
+
+/// 地块碰撞体的长方体, 服务器和客户端按同一几何生成碰撞体
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TileCuboid {
+    // 相对地块中心的偏移
+    pub offset: (f32, f32),
+    // 半边长
+    pub half_extents: (f32, f32),
+}
+
+impl TileCuboid {
+    /// 地块坐标处碰撞体中心的世界坐标
+    pub fn center(&self, point: (i32, i32, i32)) -> (f32, f32) {
+        let (x, y) = tile_center(point);
+        (x + self.offset.0, y + self.offset.1)
+    }
+
+    /// 地块坐标处碰撞体覆盖的网格单元, 单元(i, j)覆盖[i, i + 1) * CELL_SIZE
+    fn cells(&self, point: (i32, i32, i32)) -> Vec<(i32, i32)> {
+        let (x, y) = self.center(point);
+        let min_x = ((x - self.half_extents.0) / CELL_SIZE).round() as i32;
+        let min_y = ((y - self.half_extents.1) / CELL_SIZE).round() as i32;
+        let width = (self.half_extents.0 * 2. / CELL_SIZE).round() as i32;
+        let height = (self.half_extents.1 * 2. / CELL_SIZE).round() as i32;
+        let mut cells = Vec::new();
+        for i in min_x..min_x + width {
+            for j in min_y..min_y + height {
+                cells.push((i, j));
+            }
+        }
+        cells
+    }
+}
+
+/// 合并后的地形长方体, 世界坐标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainRect {
+    pub center: (f32, f32),
+    pub half_extents: (f32, f32),
+}
+
+/// 地块中心的世界坐标, 只取x和y, 图层不影响平面位置
+pub fn tile_center(point: (i32, i32, i32)) -> (f32, f32) {
+    (point.0 as f32 * TILE_SIZE, point.1 as f32 * TILE_SIZE)
+}
+
+/// 碰撞体类型对应的长方体, None没有碰撞体
+/// 上下左右与地图连接点一致: 上为+y, 右为+x
+/// 前后为图层方向(z轴)的一半, 投影到平面上仍占满整个地块
+/// HalfCenterX/HalfCenterY为居中且沿x/y方向占一半的条带, HalfCenter为居中的一半大小
+pub fn tile_cuboid(collider: &TileCollider) -> Option<TileCuboid> {
+    let full = TILE_SIZE / 2.;
+    let half = TILE_SIZE / 4.;
+    let cuboid = |offset, half_extents| {
+        Some(TileCuboid {
+            offset,
+            half_extents,
+        })
+    };
+    match collider {
+        TileCollider::None => None,
+        TileCollider::Full | TileCollider::HalfFront | TileCollider::HalfBack => {
+            cuboid((0., 0.), (full, full))
+        }
+        TileCollider::HalfTop => cuboid((0., half), (full, half)),
+        TileCollider::HalfBottom => cuboid((0., -half), (full, half)),
+        TileCollider::HalfLeft => cuboid((-half, 0.), (half, full)),
+        TileCollider::HalfRight => cuboid((half, 0.), (half, full)),
+        TileCollider::HalfCenter => cuboid((0., 0.), (half, half)),
+        TileCollider::HalfCenterX => cuboid((0., 0.), (half, full)),
+        TileCollider::HalfCenterY => cuboid((0., 0.), (full, half)),
+    }
+}
+
+/// 地块所在的区块
+pub fn chunk_of(point: (i32, i32, i32)) -> (i32, i32) {
+    (
+        point.0.div_euclid(CHUNK_TILES),
+        point.1.div_euclid(CHUNK_TILES),
+    )
+}
+
+/// 贪心合并网格单元: 按行扫描, 先沿x扩展到最宽, 再沿y扩展整行
+pub fn merge_cells(cells: &HashSet<(i32, i32)>) -> Vec<TerrainRect> {
+    let mut sorted: Vec<(i32, i32)> = cells.iter().cloned().collect();
+    sorted.sort_by_key(|(x, y)| (*y, *x));
+    let mut remaining = cells.clone();
+    let mut rects = Vec::new();
+    for (x, y) in sorted {
+        if !remaining.contains(&(x, y)) {
+            continue;
+        }
+        let mut width = 1;
+        while remaining.contains(&(x + width, y)) {
+            width += 1;
+        }
+        let mut height = 1;
+        while (0..width).all(|dx| remaining.contains(&(x + dx, y + height))) {
+            height += 1;
+        }
+        for dx in 0..width {
+            for dy in 0..height {
+                remaining.remove(&(x + dx, y + dy));
+            }
+        }
+        let half_extents = (
+            width as f32 * CELL_SIZE / 2.,
+            height as f32 * CELL_SIZE / 2.,
+        );
+        rects.push(TerrainRect {
+            center: (
+                x as f32 * CELL_SIZE + half_extents.0,
+                y as f32 * CELL_SIZE + half_extents.1,
+            ),
+            half_extents,
+        });
+    }
+    rects
+}
+
+/// 区块内地块坐标 -> 碰撞体类型
+type ChunkTiles = HashMap<(i32, i32, i32), TileCollider>;
+
+/// 按区块记录地块碰撞体, 地块变化时只重建所在的区块
+#[derive(Debug, Default)]
+pub struct TerrainGrid {
+    // 区块 -> 区块内的地块
+    chunks: HashMap<(i32, i32), ChunkTiles>,
+    // 需要重建的区块
+    dirty: HashSet<(i32, i32)>,
+}
+
+impl TerrainGrid {
+    pub fn new() -> Self {
+        TerrainGrid::default()
+    }
+
+    /// 设置地块碰撞体, 类型变化时标记所在区块需要重建
+    pub fn set(&mut self, point: (i32, i32, i32), collider: TileCollider) {
+        let chunk = chunk_of(point);
+        let tiles = self.chunks.entry(chunk).or_default();
+        let changed = if collider == TileCollider::None {
+            tiles.remove(&point).is_some()
+        } else {
+            tiles.insert(point, collider.clone()) != Some(collider)
+        };
+        if changed {
+            self.dirty.insert(chunk);
+        }
+        if tiles.is_empty() {
+            self.chunks.remove(&chunk);
+        }
+    }
+
+    /// 移除区块内的所有地块, 区块原本有碰撞体时标记需要重建
+    pub fn remove_chunk(&mut self, chunk: (i32, i32)) {
+        if self.chunks.remove(&chunk).is_some() {
+            self.dirty.insert(chunk);
+        }
+    }
+
+    /// 取出需要重建的区块
+    pub fn take_dirty(&mut self) -> Vec<(i32, i32)> {
+        self.dirty.drain().collect()
+    }
+
+    /// 区块内合并后的长方体, 不同图层的碰撞体合并为一层
+    pub fn chunk_rects(&self, chunk: (i32, i32)) -> Vec<TerrainRect> {
+        let mut cells = HashSet::new();
+        if let Some(tiles) = self.chunks.get(&chunk) {
+            for (point, collider) in tiles.iter() {
+                if let Some(cuboid) = tile_cuboid(collider) {
+                    cells.extend(cuboid.cells(*point));
+                }
+            }
+        }
+        merge_cells(&cells)
+    }
+}
+
+#[test]
+fn test_tile_cuboid() {
+    let geometry =
+        |collider| tile_cuboid(&collider).map(|cuboid| (cuboid.offset, cuboid.half_extents));
+    assert_eq!(geometry(TileCollider::None), None);
+    assert_eq!(geometry(TileCollider::Full), Some(((0., 0.), (32., 32.))));
+    assert_eq!(
+        geometry(TileCollider::HalfTop),
+        Some(((0., 16.), (32., 16.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfBottom),
+        Some(((0., -16.), (32., 16.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfLeft),
+        Some(((-16., 0.), (16., 32.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfRight),
+        Some(((16., 0.), (16., 32.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfFront),
+        Some(((0., 0.), (32., 32.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfBack),
+        Some(((0., 0.), (32., 32.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfCenter),
+        Some(((0., 0.), (16., 16.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfCenterX),
+        Some(((0., 0.), (16., 32.)))
+    );
+    assert_eq!(
+        geometry(TileCollider::HalfCenterY),
+        Some(((0., 0.), (32., 16.)))
+    );
+}
+
+#[test]
+fn test_tile_cuboid_bounds() {
+    let colliders = [
+        TileCollider::Full,
+        TileCollider::HalfTop,
+        TileCollider::HalfBottom,
+        TileCollider::HalfLeft,
+        TileCollider::HalfRight,
+        TileCollider::HalfFront,
+        TileCollider::HalfBack,
+        TileCollider::HalfCenter,
+        TileCollider::HalfCenterX,
+        TileCollider::HalfCenterY,
+    ];
+    // 碰撞体不超出地块
+    for collider in colliders.iter() {
+        let cuboid = tile_cuboid(collider).unwrap();
+        assert!(cuboid.offset.0.abs() + cuboid.half_extents.0 <= TILE_SIZE / 2.);
+        assert!(cuboid.offset.1.abs() + cuboid.half_extents.1 <= TILE_SIZE / 2.);
+    }
+    // 世界坐标按地块中心加偏移计算
+    let cuboid = tile_cuboid(&TileCollider::HalfRight).unwrap();
+    assert_eq!(cuboid.center((2, -1, 1)), (144., -64.));
+}
+
+#[test]
+fn test_merge_cells() {
+    // 2x2的实心方块和一个单独的单元
+    let cells: HashSet<(i32, i32)> = [(0, 0), (1, 0), (0, 1), (1, 1), (5, 5)]
+        .iter()
+        .cloned()
+        .collect();
+    let rects = merge_cells(&cells);
+    assert_eq!(
+        rects,
+        vec![
+            TerrainRect {
+                center: (16., 16.),
+                half_extents: (16., 16.),
+            },
+            TerrainRect {
+                center: (88., 88.),
+                half_extents: (8., 8.),
+            },
+        ]
+    );
+    // L形拆为两块, 覆盖的面积不变
+    let cells: HashSet<(i32, i32)> = [(0, 0), (1, 0), (2, 0), (0, 1), (0, 2)]
+        .iter()
+        .cloned()
+        .collect();
+    let rects = merge_cells(&cells);
+    assert_eq!(rects.len(), 2);
+    let area: f32 = rects
+        .iter()
+        .map(|rect| rect.half_extents.0 * rect.half_extents.1 * 4.)
+        .sum();
+    assert_eq!(area, 5. * CELL_SIZE * CELL_SIZE);
+    assert!(merge_cells(&HashSet::new()).is_empty());
+}
+
+#[test]
+fn test_terrain_grid() {
+    let mut grid = TerrainGrid::new();
+    // 一行相邻的实心地块合并为一个长方体
+    for x in 0..4 {
+        grid.set((x, 0, 1), TileCollider::Full);
+    }
+    assert_eq!(grid.take_dirty(), vec![(0, 0)]);
+    assert_eq!(
+        grid.chunk_rects((0, 0)),
+        vec![TerrainRect {
+            center: (96., 0.),
+            half_extents: (128., 32.),
+        }]
+    );
+    // 半块与相邻的实心地块合并
+    grid.set((4, 0, 1), TileCollider::HalfLeft);
+    assert_eq!(
+        grid.chunk_rects((0, 0)),
+        vec![TerrainRect {
+            center: (112., 0.),
+            half_extents: (144., 32.),
+        }]
+    );
+    // 类型不变时不重建, 只重建变化的区块
+    grid.take_dirty();
+    grid.set((0, 0, 1), TileCollider::Full);
+    assert!(grid.take_dirty().is_empty());
+    grid.set((-1, 0, 0), TileCollider::Full);
+    grid.set((0, 0, 1), TileCollider::None);
+    let mut dirty = grid.take_dirty();
+    dirty.sort();
+    assert_eq!(dirty, vec![(-1, 0), (0, 0)]);
+    assert_eq!(grid.chunk_rects((0, 0)).len(), 1);
+    // 清空的区块没有碰撞体
+    grid.set((-1, 0, 0), TileCollider::None);
+    assert!(grid.chunk_rects((-1, 0)).is_empty());
+    // 卸载区块
+    grid.take_dirty();
+    grid.remove_chunk((0, 0));
+    grid.remove_chunk((5, 5));
+    assert_eq!(grid.take_dirty(), vec![(0, 0)]);
+    assert!(grid.chunk_rects((0, 0)).is_empty());
+    assert_eq!(chunk_of((-1, 15, 0)), (-1, 0));
+    assert_eq!(chunk_of((16, -16, 0)), (1, -1));
+}
@@ -1,88 +1,292 @@
-use std::collections::HashMap;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, VecDeque},
+    sync::Mutex,
+};
+
+use data::server_db::{find_tile_map, GameData};
+use glam::{IVec3, Vec3};
+use protocol::data::tile_map_data::{Slot, Tile, TileJoint, TileMap, TileState};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::{
+    tile_collider::{chunk_of, CHUNK_TILES},
+    tileset,
+};
+
+/// 区块的图层数
+const CHUNK_LAYERS: i32 = 2;
+
+/// 区块生成锁, 同一时间只生成一个区块
+static GENERATING: Mutex<()> = Mutex::new(());
+
+/// 相邻区块共享的生成区域, 与区块坐标一起推导区域种子
+/// 交叉区: 四个区块交界处2x2的地块
+const REGION_CROSSING: u64 = 1;
+/// 左右相邻区块之间2格宽的接缝
+const REGION_SEAM_X: u64 = 2;
+/// 上下相邻区块之间2格高的接缝
+const REGION_SEAM_Y: u64 = 3;
+
+/// 预生成出生点附近的区块, map_id为房间分组, chunks为每个方向生成的区块数
+/// seed只在地图还没有种子时使用, 不指定时随机生成
+/// 其余区块在玩家靠近时由服务器按需生成
+pub fn create_init_map(map_id: u32, chunks: i32, seed: Option<u64>) {
+    println!("地图{}种子: {}", map_id, load_or_create_seed(map_id, seed));
+    for x in -chunks..=chunks {
+        for y in -chunks..=chunks {
+            let tiles = load_or_create_chunk(map_id, (x, y));
+            println!("区块({}, {}): {}个地块", x, y, tiles.len());
+        }
+    }
+}
+
+/// 读取地图的世界种子, 没有时保存seed或随机生成的种子
+/// 种子一旦保存不再改变, 否则新旧区块的地形会接不上
+pub fn load_or_create_seed(map_id: u32, seed: Option<u64>) -> u64 {
+    if let Some(seed) = data::server_db::find(GameData::map_seed(map_id, None))
+        .ok()
+        .and_then(|data| data.parse().ok())
+    {
+        return seed;
+    }
+    let seed = seed.unwrap_or_else(rand::random);
+    let _ = data::server_db::save(GameData::map_seed(map_id, Some(seed.to_string())));
+    seed
+}
+
+/// 由世界种子和区块坐标推导区块种子(splitmix64), 与生成顺序无关
+pub fn chunk_seed(world_seed: u64, chunk: (i32, i32)) -> u64 {
+    fn mix(mut z: u64) -> u64 {
+        z = z.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+    let point = ((chunk.0 as u32 as u64) << 32) | chunk.1 as u32 as u64;
+    mix(mix(world_seed) ^ point)
+}
+
+/// 读取区块内的地块, 区块未生成时生成并保存
+/// 区块地形只由世界种子和区块坐标决定, 与生成顺序无关
+pub fn load_or_create_chunk(map_id: u32, chunk: (i32, i32)) -> Vec<TileState> {
+    if data::server_db::find(GameData::tile_chunk(map_id, chunk, None)).is_ok() {
+        return load_chunk(map_id, chunk);
+    }
+    // 同时请求的区块依次生成, 同一区块不会重复生成和保存
+    let _generating = GENERATING.lock().unwrap_or_else(|e| e.into_inner());
+    if data::server_db::find(GameData::tile_chunk(map_id, chunk, None)).is_ok() {
+        return load_chunk(map_id, chunk);
+    }
+    let tiles = generate_chunk(load_or_create_seed(map_id, None), chunk);
+    for tile_state in tiles.iter() {
+        let _ = data::server_db::save_tile_map(map_id, tile_state.clone());
+    }
+    let _ = data::server_db::save(GameData::tile_chunk(map_id, chunk, Some("1".to_string())));
+    tiles
+}
 
-use data::server_db::find_tile_map;
-use glam::{IVec3, UVec3, Vec3};
-use protocol::data::tile_map_data::{Slot, Tile, TileCollider, TileJoint, TileMap, TileState};
-use rand::Rng;
+/// 区域种子, 区域内的地形只由世界种子和区域坐标决定
+fn region_seed(world_seed: u64, region: u64, chunk: (i32, i32)) -> u64 {
+    chunk_seed(
+        world_seed ^ region.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+        chunk,
+    )
+}
 
-/// 创建地图
-pub fn create_init_map() {
-    for x in -5..=5 {
-        for y in -5..=5 {
-            let mut tile_map = TileMap {
-                center_point: IVec3::new(x * 10, y * 10, 0),
-                texture_size: UVec3::new(64, 64, 1),
-                chunk_size: UVec3::new(1, 1, 1),
-                map_size: UVec3::new(20, 20, 2),
-                slot_map: HashMap::new(),
+/// 坍缩min到max(含)范围内的地块, fixed中的地块作为固定的连接条件
+fn collapse_region(
+    fixed: &HashMap<IVec3, Tile>,
+    min: (i32, i32),
+    max: (i32, i32),
+    seed: u64,
+) -> HashMap<IVec3, Tile> {
+    let mut slot_map: HashMap<IVec3, Slot> = fixed
+        .iter()
+        .map(|(point, tile)| {
+            let slot = Slot {
+                point: *point,
+                superposition: Vec::new(),
+                entropy: 0,
+                tile: Some(tile.clone()),
             };
-            create_map(&mut tile_map);
-            for (point, slot) in tile_map.slot_map {
-                if let Some(tile) = slot.tile.clone() {
-                    let tile_state = TileState {
-                        point: (point.x, point.y, point.z),
-                        filename: tile.filename,
-                        collider: tile.collider,
-                    };
-                    if let Ok(_result) = data::server_db::save_tile_map(tile_state.clone()) {
-                        // println!("save: {}==={:?}", point, &slot.tile.unwrap());
-                    }
-                    if let Ok(data) = data::server_db::find_tile_map(tile_state.point) {
-                        println!("saved: {:?}==={:?}", tile_state.point, data);
-                    }
+            (*point, slot)
+        })
+        .collect();
+    fill_region(&mut slot_map, min, max, CHUNK_LAYERS, |_| None);
+    collapse(&mut slot_map, &mut StdRng::seed_from_u64(seed));
+    slot_map
+        .into_iter()
+        .filter(|(point, _)| !fixed.contains_key(point))
+        .filter_map(|(point, slot)| slot.tile.map(|tile| (point, tile)))
+        .collect()
+}
+
+/// 区块左下角的交叉区, 与左, 下, 左下三个区块共享
+fn crossing(seed: u64, chunk: (i32, i32)) -> HashMap<IVec3, Tile> {
+    let (x, y) = (chunk.0 * CHUNK_TILES, chunk.1 * CHUNK_TILES);
+    collapse_region(
+        &HashMap::new(),
+        (x - 1, y - 1),
+        (x, y),
+        region_seed(seed, REGION_CROSSING, chunk),
+    )
+}
+
+/// 区块与左侧区块之间的接缝, 两端的交叉区作为连接条件
+fn seam_x(seed: u64, chunk: (i32, i32)) -> HashMap<IVec3, Tile> {
+    let (x, y) = (chunk.0 * CHUNK_TILES, chunk.1 * CHUNK_TILES);
+    let mut fixed = crossing(seed, chunk);
+    fixed.extend(crossing(seed, (chunk.0, chunk.1 + 1)));
+    collapse_region(
+        &fixed,
+        (x - 1, y + 1),
+        (x, y + CHUNK_TILES - 2),
+        region_seed(seed, REGION_SEAM_X, chunk),
+    )
+}
+
+/// 区块与下方区块之间的接缝, 两端的交叉区作为连接条件
+fn seam_y(seed: u64, chunk: (i32, i32)) -> HashMap<IVec3, Tile> {
+    let (x, y) = (chunk.0 * CHUNK_TILES, chunk.1 * CHUNK_TILES);
+    let mut fixed = crossing(seed, chunk);
+    fixed.extend(crossing(seed, (chunk.0 + 1, chunk.1)));
+    collapse_region(
+        &fixed,
+        (x + 1, y - 1),
+        (x + CHUNK_TILES - 2, y),
+        region_seed(seed, REGION_SEAM_Y, chunk),
+    )
+}
+
+/// 生成区块内的地块
+/// 区块边缘的一圈地块属于与相邻区块共享的交叉区和接缝, 由世界种子单独生成
+/// 相邻区块无论谁先生成, 边缘都相同且能相接; 内部以边缘为连接条件坍缩
+fn generate_chunk(seed: u64, chunk: (i32, i32)) -> Vec<TileState> {
+    let (c, m) = chunk;
+    let mut border = HashMap::new();
+    for corner in [(c, m), (c + 1, m), (c, m + 1), (c + 1, m + 1)].iter() {
+        border.extend(crossing(seed, *corner));
+    }
+    for side in [(c, m), (c + 1, m)].iter() {
+        border.extend(seam_x(seed, *side));
+    }
+    for side in [(c, m), (c, m + 1)].iter() {
+        border.extend(seam_y(seed, *side));
+    }
+    let (x, y) = (c * CHUNK_TILES, m * CHUNK_TILES);
+    let inner = collapse_region(
+        &border,
+        (x + 1, y + 1),
+        (x + CHUNK_TILES - 2, y + CHUNK_TILES - 2),
+        chunk_seed(seed, chunk),
+    );
+    let mut tiles: Vec<TileState> = border
+        .into_iter()
+        .chain(inner)
+        // 只保留本区块的地块
+        .filter(|(point, _)| chunk_of((point.x, point.y, point.z)) == chunk)
+        .map(|(point, tile)| TileState {
+            point: (point.x, point.y, point.z),
+            filename: tile.filename,
+            collider: tile.collider,
+        })
+        .collect();
+    tiles.sort_by_key(|tile_state| tile_state.point);
+    tiles
+}
+
+/// 从数据库读取已生成区块内的地块
+fn load_chunk(map_id: u32, chunk: (i32, i32)) -> Vec<TileState> {
+    let mut tiles = Vec::new();
+    for x in chunk.0 * CHUNK_TILES..(chunk.0 + 1) * CHUNK_TILES {
+        for y in chunk.1 * CHUNK_TILES..(chunk.1 + 1) * CHUNK_TILES {
+            for z in 0..CHUNK_LAYERS {
+                if let Ok(tile_state) = find_tile_map(map_id, (x, y, z)) {
+                    tiles.push(tile_state);
                 }
             }
         }
     }
+    tiles
 }
 
-pub fn create_map(tile_map: &mut TileMap) {
-    // 1. 计算地图边界值
+/// 数据库中已保存的地块
+fn saved_tile(map_id: u32, point: IVec3) -> Option<Tile> {
+    find_tile_map(map_id, (point.x, point.y, point.z))
+        .ok()
+        .and_then(|tile_state| {
+            tileset::get()
+                .get(point.z as usize, &tile_state.filename)
+                .cloned()
+        })
+}
 
+pub fn create_map(map_id: u32, tile_map: &mut TileMap) {
+    // 已保存的地块作为固定的连接条件
+    fill_slots(tile_map, |point| saved_tile(map_id, point));
+    collapse(
+        &mut tile_map.slot_map,
+        &mut StdRng::seed_from_u64(tile_map.seed),
+    );
+}
+
+/// 按地图范围初始化Slot: 填充叠加态和熵, fixed返回地块时该Slot已确定
+fn fill_slots<F: Fn(IVec3) -> Option<Tile>>(tile_map: &mut TileMap, fixed: F) {
+    // 计算地图边界值
     let min_x = tile_map.center_point.x - (tile_map.map_size.x as i32 / 2);
     let max_x = tile_map.center_point.x + (tile_map.map_size.x as i32 / 2);
     let min_y = tile_map.center_point.y - (tile_map.map_size.y as i32 / 2);
     let max_y = tile_map.center_point.y + (tile_map.map_size.y as i32 / 2);
+    fill_region(
+        &mut tile_map.slot_map,
+        (min_x, min_y),
+        (max_x, max_y),
+        tile_map.map_size.z as i32,
+        fixed,
+    );
+}
 
-    // 2. 按Z轴从小到大生成图层
-    for z in 0..tile_map.map_size.z {
-        for point_x in min_x..=max_x {
-            for point_y in min_y..=max_y {
-                let point = IVec3::new(point_x, point_y, z as i32);
+/// 初始化min到max(含)范围内各图层的Slot
+fn fill_region<F: Fn(IVec3) -> Option<Tile>>(
+    slot_map: &mut HashMap<IVec3, Slot>,
+    min: (i32, i32),
+    max: (i32, i32),
+    layers: i32,
+    fixed: F,
+) {
+    // 按Z轴从小到大生成图层
+    for z in 0..layers {
+        for point_x in min.0..=max.0 {
+            for point_y in min.1..=max.1 {
+                let point = IVec3::new(point_x, point_y, z);
 
                 // 判断是否已初始化
-                if tile_map.slot_map.contains_key(&point) {
+                if slot_map.contains_key(&point) {
                     continue;
                 }
 
-                // 初始化Slot: 填充叠加态, 初始化熵
-                let superposition = load_default_superposition(z);
-                let entropy = superposition.len();
-                let mut slot = Slot {
-                    point,
-                    superposition,
-                    entropy,
-                    tile: None,
-                };
-                // 获取数据库数据, 存在则载入, 不存在则保持初始化
-                if let Ok(tile_state) = find_tile_map((point.x, point.y, point.z)) {
-                    let tile = get_tile_by_filename(tile_state.filename);
-                    slot = Slot {
+                let slot = match fixed(point) {
+                    Some(tile) => Slot {
                         point,
                         superposition: Vec::new(),
                         entropy: 0,
                         tile: Some(tile),
-                    };
-                }
-
-                tile_map.slot_map.insert(point, slot);
+                    },
+                    None => {
+                        let superposition = tileset::get().superposition(z as usize);
+                        Slot {
+                            point,
+                            entropy: superposition.len(),
+                            superposition,
+                            tile: None,
+                        }
+                    }
+                };
+                slot_map.insert(point, slot);
             }
         }
     }
-    // 4-2. 按照熵值从小到大坍缩
-    // TODO 4-2-1. 填充当前地图块四周已坍缩的tile，以供计算边缘slot的叠加态与熵
-    // 4-2-2. 递归坍缩
-    tile_map.slot_map = collapse(tile_map.slot_map.clone());
 }
 
 /// 世界坐标->地图索引
@@ -92,1392 +296,547 @@ pub fn pos_to_global_point(tile_map: &TileMap, pos: Vec3) -> IVec3 {
     point.as_i32()
 }
 
-/// 递归坍缩
-fn collapse(mut slot_map: HashMap<IVec3, Slot>) -> HashMap<IVec3, Slot> {
-    let mut slot_list: Vec<Slot> = Vec::new();
-
-    // 取出当前所有未坍缩的slot
-    for (_point, slot) in slot_map.iter() {
-        if slot.entropy > 0 {
-            slot_list.push(slot.clone());
+/// 相邻方向, 下标与连接点一致: 0上 1下 2左 3右 4前 5后, 相反方向为下标^1
+const DIRECTIONS: [(i32, i32, i32); 6] = [
+    (0, 1, 0),
+    (0, -1, 0),
+    (-1, 0, 0),
+    (1, 0, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+/// 矛盾时最多回溯的次数, 超出后无法满足的Slot填充胶水tile
+const MAX_BACKTRACKS: usize = 2000;
+
+/// 两个相对的连接点能否相接
+/// All可接任意连接点, None只能接All
+/// 带"空"的标签(如"草空")只接"空", "空"接所有带"空"的标签, 其余标签需相同
+pub fn joints_match(a: &TileJoint, b: &TileJoint) -> bool {
+    match (a, b) {
+        (TileJoint::All, _) | (_, TileJoint::All) => true,
+        (TileJoint::None, _) | (_, TileJoint::None) => false,
+        (TileJoint::TagOne(a), TileJoint::TagOne(b)) => {
+            if a == "空" {
+                b.contains('空')
+            } else if b == "空" {
+                a.contains('空')
+            } else {
+                !a.contains('空') && a == b
+            }
         }
     }
+}
 
-    // 重新计算熵
-    // println!("{:?}", &slot_list.len());
-    for slot in &mut slot_list {
-        let superposition: Vec<Tile> = slot.superposition.clone();
+/// 坍缩求解: 每个Slot的可选tile用布尔表记录, 删除记录在trail中用于回溯
+struct Solver {
+    points: Vec<IVec3>,
+    // 可选tile, 已确定的Slot只有一个
+    tiles: Vec<Vec<Tile>>,
+    // 可选tile各方向连接点的编号
+    joints: Vec<Vec<[usize; 6]>>,
+    allowed: Vec<Vec<bool>>,
+    counts: Vec<usize>,
+    neighbors: Vec<[Option<usize>; 6]>,
+    // 初始已确定(从数据库载入)的Slot, 不参与删减
+    fixed: Vec<bool>,
+    // 无法满足而填充胶水tile的Slot
+    glue: Vec<bool>,
+    // 连接点编号两两能否相接
+    compatible: Vec<Vec<bool>>,
+    // 删除记录(Slot, tile)
+    trail: Vec<(usize, usize)>,
+    // 按熵从小到大取出待坍缩的Slot, 熵相同时按下标(即坐标顺序)取出, 保证结果可复现
+    queue: BinaryHeap<Reverse<(usize, usize)>>,
+}
 
-        // 取得紧贴的slot连接限制条件tile_joint
-        let mut joint_list = [
-            TileJoint::All, // 0上
-            TileJoint::All, // 1下
-            TileJoint::All, // 2左
-            TileJoint::All, // 3右
-            TileJoint::All, // 4前
-            TileJoint::All, // 5后
-        ];
-        // 上
-        let point = IVec3::new(slot.point.x, slot.point.y + 1, slot.point.z);
-        if let Some(slot) = slot_map.get(&point) {
-            if let Some(tile) = &slot.tile {
-                joint_list[0] = tile.joints[1].clone();
+impl Solver {
+    fn new(slot_map: &HashMap<IVec3, Slot>) -> Self {
+        let mut points: Vec<IVec3> = slot_map
+            .values()
+            .filter(|slot| slot.tile.is_some() || !slot.superposition.is_empty())
+            .map(|slot| slot.point)
+            .collect();
+        points.sort_by_key(|point| (point.x, point.y, point.z));
+        let index: HashMap<IVec3, usize> = points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| (*point, i))
+            .collect();
+
+        let mut unique_joints: Vec<TileJoint> = Vec::new();
+        let mut joint_id = |joint: &TileJoint| match unique_joints.iter().position(|j| j == joint) {
+            Some(id) => id,
+            None => {
+                unique_joints.push(joint.clone());
+                unique_joints.len() - 1
             }
+        };
+        let mut tiles = Vec::new();
+        let mut joints = Vec::new();
+        let mut fixed = Vec::new();
+        for point in points.iter() {
+            let slot = &slot_map[point];
+            let options = match &slot.tile {
+                Some(tile) => vec![tile.clone()],
+                None => slot.superposition.clone(),
+            };
+            joints.push(
+                options
+                    .iter()
+                    .map(|tile| {
+                        let mut ids = [0; 6];
+                        for (i, joint) in tile.joints.iter().enumerate() {
+                            ids[i] = joint_id(joint);
+                        }
+                        ids
+                    })
+                    .collect(),
+            );
+            tiles.push(options);
+            fixed.push(slot.tile.is_some());
         }
-        // 下
-        let point = IVec3::new(slot.point.x, slot.point.y - 1, slot.point.z);
-        if let Some(slot) = slot_map.get(&point) {
-            if let Some(tile) = &slot.tile {
-                joint_list[1] = tile.joints[0].clone();
-            }
+        let compatible = unique_joints
+            .iter()
+            .map(|a| unique_joints.iter().map(|b| joints_match(a, b)).collect())
+            .collect();
+        let neighbors = points
+            .iter()
+            .map(|point| {
+                let mut neighbors = [None; 6];
+                for (i, (dx, dy, dz)) in DIRECTIONS.iter().enumerate() {
+                    neighbors[i] = index
+                        .get(&IVec3::new(point.x + dx, point.y + dy, point.z + dz))
+                        .cloned();
+                }
+                neighbors
+            })
+            .collect();
+
+        let mut solver = Solver {
+            allowed: tiles
+                .iter()
+                .map(|options| vec![true; options.len()])
+                .collect(),
+            counts: tiles.iter().map(|options| options.len()).collect(),
+            glue: vec![false; points.len()],
+            points,
+            tiles,
+            joints,
+            neighbors,
+            fixed,
+            compatible,
+            trail: Vec::new(),
+            queue: BinaryHeap::new(),
+        };
+        for slot in 0..solver.points.len() {
+            solver.push_queue(slot);
         }
-        // 左
-        let point = IVec3::new(slot.point.x - 1, slot.point.y, slot.point.z);
-        if let Some(slot) = slot_map.get(&point) {
-            if let Some(tile) = &slot.tile {
-                joint_list[2] = tile.joints[3].clone();
-            }
+        solver
+    }
+
+    fn push_queue(&mut self, slot: usize) {
+        if self.counts[slot] > 1 && !self.glue[slot] {
+            self.queue.push(Reverse((self.counts[slot], slot)));
         }
-        // 右
-        let point = IVec3::new(slot.point.x + 1, slot.point.y, slot.point.z);
-        if let Some(slot) = slot_map.get(&point) {
-            if let Some(tile) = &slot.tile {
-                joint_list[3] = tile.joints[2].clone();
+    }
+
+    /// 取出熵最小的未坍缩Slot, 跳过过期的记录
+    fn next_slot(&mut self) -> Option<usize> {
+        while let Some(Reverse((count, slot))) = self.queue.pop() {
+            if count == self.counts[slot] && count > 1 && !self.glue[slot] {
+                return Some(slot);
             }
         }
-        // 前
-        let point = IVec3::new(slot.point.x, slot.point.y, slot.point.z + 1);
-        if let Some(slot) = slot_map.get(&point) {
-            if let Some(tile) = &slot.tile {
-                joint_list[4] = tile.joints[5].clone();
+        None
+    }
+
+    fn remove(&mut self, slot: usize, option: usize) {
+        if self.allowed[slot][option] {
+            self.allowed[slot][option] = false;
+            self.counts[slot] -= 1;
+            self.trail.push((slot, option));
+            self.push_queue(slot);
+        }
+    }
+
+    /// 撤销删除记录直到mark
+    fn undo(&mut self, mark: usize) {
+        while self.trail.len() > mark {
+            if let Some((slot, option)) = self.trail.pop() {
+                self.allowed[slot][option] = true;
+                self.counts[slot] += 1;
+                self.push_queue(slot);
             }
         }
-        // 后
-        let point = IVec3::new(slot.point.x, slot.point.y, slot.point.z - 1);
-        if let Some(slot) = slot_map.get(&point) {
-            if let Some(tile) = &slot.tile {
-                joint_list[5] = tile.joints[4].clone();
+    }
+
+    /// 按权重随机选择一个可选tile, 其余删除
+    fn observe<R: Rng>(&mut self, slot: usize, rng: &mut R) -> usize {
+        let options: Vec<usize> = (0..self.tiles[slot].len())
+            .filter(|option| self.allowed[slot][*option])
+            .collect();
+        let total: u32 = options
+            .iter()
+            .map(|option| self.tiles[slot][*option].rng_seed as u32)
+            .sum();
+        let choice = if total == 0 {
+            options[rng.gen_range(0..options.len())]
+        } else {
+            let mut roll = rng.gen_range(0..total);
+            let mut choice = options[options.len() - 1];
+            for option in options.iter() {
+                let weight = self.tiles[slot][*option].rng_seed as u32;
+                if roll < weight {
+                    choice = *option;
+                    break;
+                }
+                roll -= weight;
+            }
+            choice
+        };
+        for option in options {
+            if option != choice {
+                self.remove(slot, option);
             }
         }
+        choice
+    }
 
-        // 剔除无效坍缩态
-        let mut superposition_new = Vec::new();
-        'tile: for tile in superposition.iter() {
-            // println!("-------------------{:?}", &tile.filename);
-            for i in 0..6 as usize {
-                match joint_list[i] {
-                    TileJoint::None => {
-                        superposition_new = Vec::new();
-                        break 'tile;
+    /// 删除与相邻Slot的任何可选tile都无法相接的tile, 直到不再变化
+    /// 出现没有可选tile的Slot时返回该Slot
+    fn propagate(&mut self, pending: &mut VecDeque<usize>) -> Result<(), usize> {
+        while let Some(source) = pending.pop_front() {
+            for direction in 0..6 {
+                let target = match self.neighbors[source][direction] {
+                    Some(target) => target,
+                    None => continue,
+                };
+                if self.fixed[target] || self.glue[target] || self.glue[source] {
+                    continue;
+                }
+                // source在target的反方向, 胶水tile可接任意连接点
+                let side = direction ^ 1;
+                let source_joints: Vec<usize> = (0..self.tiles[source].len())
+                    .filter(|option| self.allowed[source][*option])
+                    .map(|option| self.joints[source][option][direction])
+                    .collect();
+                let mut changed = false;
+                for option in 0..self.tiles[target].len() {
+                    if !self.allowed[target][option] {
+                        continue;
                     }
-                    TileJoint::TagOne(ref tag) => {
-                        match &tile.joints[i] {
-                            TileJoint::All => {}
-                            TileJoint::None => {
-                                continue 'tile;
-                            }
-                            TileJoint::TagOne(t_tag) => {
-                                // println!("{}:{:?}---{:?}:{}", i, tag, t_tag, i);
-                                if tag.contains("空") && !tag.eq("空") {
-                                    if !t_tag.eq("空") {
-                                        continue 'tile;
-                                    }
-                                } else if tag.eq("空") {
-                                    if !t_tag.contains("空") {
-                                        continue 'tile;
-                                    }
-                                } else {
-                                    if !t_tag.eq(tag) {
-                                        continue 'tile;
-                                    }
-                                }
-                            }
-                        }
-                        // continue 'tile;
+                    let joint = self.joints[target][option][side];
+                    if !source_joints
+                        .iter()
+                        .any(|source_joint| self.compatible[joint][*source_joint])
+                    {
+                        self.remove(target, option);
+                        changed = true;
                     }
-                    _ => {}
+                }
+                if self.counts[target] == 0 {
+                    pending.push_front(source);
+                    return Err(target);
+                }
+                if changed {
+                    pending.push_back(target);
                 }
             }
-            superposition_new.push(tile.clone());
         }
-
-        // 更新slot
-        slot.superposition = superposition_new;
-        slot.entropy = slot.superposition.len();
-
-        // println!("{:?}", joint_list);
-        // println! {"{:?}-{:?}", &slot.point, &slot.entropy};
+        Ok(())
     }
 
-    // 获取最小熵slot
-    let mut min_entropy = usize::MAX;
-    let mut min_slot = None;
-    for slot in slot_list {
-        if slot.entropy == 0 {
-            slot_map.insert(slot.point, slot.clone());
-            continue;
-        }
-        if slot.entropy < min_entropy && slot.entropy != 0 {
-            min_entropy = slot.entropy;
-            min_slot = Some(slot);
+    /// 按熵从小到大坍缩, 矛盾时回溯到上一次选择并排除该选择
+    /// 无法回溯或回溯次数用尽时, 矛盾的Slot填充可接任意连接点的胶水tile
+    fn solve<R: Rng>(&mut self, rng: &mut R) {
+        let mut pending: VecDeque<usize> = (0..self.points.len()).collect();
+        // 选择记录(Slot, 选择前的trail长度, 选择的tile)
+        let mut decisions: Vec<(usize, usize, usize)> = Vec::new();
+        let mut backtracks = 0;
+        loop {
+            if let Err(contradiction) = self.propagate(&mut pending) {
+                let mut resolved = false;
+                while backtracks < MAX_BACKTRACKS {
+                    let (slot, mark, choice) = match decisions.pop() {
+                        Some(decision) => decision,
+                        None => break,
+                    };
+                    backtracks += 1;
+                    self.undo(mark);
+                    self.remove(slot, choice);
+                    if self.counts[slot] > 0 {
+                        pending.clear();
+                        pending.push_back(slot);
+                        resolved = true;
+                        break;
+                    }
+                }
+                if !resolved {
+                    self.glue[contradiction] = true;
+                }
+                continue;
+            }
+            let slot = match self.next_slot() {
+                Some(slot) => slot,
+                None => break,
+            };
+            let mark = self.trail.len();
+            let choice = self.observe(slot, rng);
+            decisions.push((slot, mark, choice));
+            pending.push_back(slot);
         }
     }
 
-    // 执行slot坍缩
-    if let Some(mut slot) = min_slot {
-        let mut superposition_for_rng = Vec::new();
-        for tile in &slot.superposition {
-            for _ in 0..tile.rng_seed {
-                superposition_for_rng.push(tile.clone());
+    /// 坍缩结果写回Slot
+    fn write(&self, slot_map: &mut HashMap<IVec3, Slot>) {
+        let glue = tileset::get().glue().clone();
+        for (slot, point) in self.points.iter().enumerate() {
+            let tile = if self.glue[slot] {
+                glue.clone()
+            } else {
+                match self.allowed[slot].iter().position(|allowed| *allowed) {
+                    Some(option) => self.tiles[slot][option].clone(),
+                    None => glue.clone(),
+                }
+            };
+            if let Some(slot) = slot_map.get_mut(point) {
+                slot.tile = Some(tile);
+                slot.superposition = Vec::new();
+                slot.entropy = 0;
             }
         }
-        let i = rand::thread_rng().gen_range(0..superposition_for_rng.len());
-
-        slot.tile = Some(superposition_for_rng[i].clone());
-        slot.superposition = Vec::new();
-        slot.entropy = 0;
-        if let Some(_slot) = slot_map.insert(slot.point, slot.clone()) {
-            // println!("更新{:?}", slot);
-        }
-        // 判断是否完成坍缩, 完成则退出递归返回tile_map结果, 否则继续
-        return collapse(slot_map);
-    } else {
-        return slot_map;
     }
 }
 
-#[test]
-fn test_create_map() {
+/// 波函数坍缩: 按熵从小到大选择Slot坍缩并向相邻Slot传播约束, 矛盾时回溯
+pub fn collapse<R: Rng>(slot_map: &mut HashMap<IVec3, Slot>, rng: &mut R) {
+    let mut solver = Solver::new(slot_map);
+    solver.solve(rng);
+    solver.write(slot_map);
+}
+
+#[cfg(test)]
+fn test_tile_map(size: u32, layers: u32) -> TileMap {
     use glam::UVec3;
-    let mut tile_map = TileMap {
+    TileMap {
         center_point: IVec3::new(0, 0, 0),
         texture_size: UVec3::new(64, 64, 1),
         chunk_size: UVec3::new(1, 1, 1),
-        map_size: UVec3::new(5, 5, 1),
+        map_size: UVec3::new(size, size, layers),
         slot_map: HashMap::new(),
-    };
-    create_map(&mut tile_map);
-    // println!("{:?}", tile_map);
-}
-
-/// 加载默认胶水tile初始化叠加态
-pub fn load_glue_superposition() -> Vec<Tile> {
-    let mut superposition = Vec::new();
-    superposition.push(Tile {
-        filename: "0-tileset_30.png".to_string(),
-        layer: 0,
-        rng_seed: 1,
-        collider: TileCollider::Full,
-        joints: [
-            TileJoint::All, // 0上
-            TileJoint::All, // 1下
-            TileJoint::All, // 2左
-            TileJoint::All, // 3右
-            TileJoint::All, // 4前
-            TileJoint::All, // 5后
-        ],
-    });
-    superposition
+        seed: 0,
+    }
 }
 
-/// 加载默认tile初始化叠加态
-pub fn load_default_superposition(layer: u32) -> Vec<Tile> {
-    match layer {
-        // 背景
-        0 => load_background_superposition(layer as usize),
-        // 地形
-        1 => load_terrain_superposition(layer as usize),
-        // 设施
-        2 => load_item_superposition(layer as usize),
-        _ => Vec::new(),
+/// 所有Slot已坍缩, 相邻的tile连接点都能相接
+#[cfg(test)]
+fn assert_collapsed(slot_map: &HashMap<IVec3, Slot>) {
+    for (point, slot) in slot_map.iter() {
+        let tile = slot.tile.as_ref().expect("未坍缩的Slot");
+        assert_eq!(slot.entropy, 0);
+        for (i, (dx, dy, dz)) in DIRECTIONS.iter().enumerate() {
+            let neighbor = IVec3::new(point.x + dx, point.y + dy, point.z + dz);
+            if let Some(other) = slot_map.get(&neighbor).and_then(|slot| slot.tile.as_ref()) {
+                assert!(
+                    joints_match(&tile.joints[i], &other.joints[i ^ 1]),
+                    "{:?}与{:?}无法相接",
+                    point,
+                    neighbor
+                );
+            }
+        }
     }
 }
 
-/// 加载所有背景层
-pub fn load_background_superposition(layer: usize) -> Vec<Tile> {
-    let mut superposition = Vec::new();
-    superposition.push(Tile {
-        filename: "0-tileset_30.png".to_string(),
-        layer,
-        rng_seed: 1,
-        collider: TileCollider::None,
-        joints: [
-            TileJoint::All, // 0上
-            TileJoint::All, // 1下
-            TileJoint::All, // 2左
-            TileJoint::All, // 3右
-            TileJoint::All, // 4前
-            TileJoint::All, // 5后
-        ],
-    });
-    superposition
+#[test]
+fn test_joints_match() {
+    let tag = |tag: &str| TileJoint::TagOne(tag.to_string());
+    assert!(joints_match(&TileJoint::All, &TileJoint::None));
+    assert!(!joints_match(&TileJoint::None, &tag("空")));
+    assert!(joints_match(&tag("空"), &tag("草空")));
+    assert!(joints_match(&tag("草空"), &tag("空")));
+    assert!(!joints_match(&tag("草空"), &tag("草空")));
+    assert!(joints_match(&tag("x|边|草"), &tag("x|边|草")));
+    assert!(!joints_match(&tag("x|边|草"), &tag("y|边|草")));
 }
 
-/// 加载所有地形
-pub fn load_terrain_superposition(layer: usize) -> Vec<Tile> {
-    let mut superposition = Vec::new();
-    // 添加空地
-    superposition.push(Tile {
-        filename: "0-tileset_30.png".to_string(),
-        layer,
-        rng_seed: 40,
-        collider: TileCollider::None,
-        joints: [
-            TileJoint::TagOne("空".to_string()), // 0上
-            TileJoint::TagOne("空".to_string()), // 1下
-            TileJoint::TagOne("空".to_string()), // 2左
-            TileJoint::TagOne("空".to_string()), // 3右
-            TileJoint::All,                       // 4前
-            TileJoint::All,                       // 5后
-        ],
-    });
-
-    // 草地
-    {
-        superposition.push(Tile {
-            filename: "0-tileset_01.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("草空".to_string()),    // 0上
-                TileJoint::TagOne("x|边|草".to_string()), // 1下
-                TileJoint::TagOne("草空".to_string()),    // 2左
-                TileJoint::TagOne("y|边|草".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_02.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("草空".to_string()),    // 0上
-                TileJoint::TagOne("草".to_string()),       // 1下
-                TileJoint::TagOne("y|边|草".to_string()), // 2左
-                TileJoint::TagOne("y|边|草".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_03.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("草空".to_string()),    // 0上
-                TileJoint::TagOne("x|草|边".to_string()), // 1下
-                TileJoint::TagOne("y|边|草".to_string()), // 2左
-                TileJoint::TagOne("草空".to_string()),    // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_04.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("草".to_string()), // 0上
-                TileJoint::TagOne("草".to_string()), // 1下
-                TileJoint::TagOne("草".to_string()), // 2左
-                TileJoint::TagOne("草".to_string()), // 3右
-                TileJoint::All,                       // 4前
-                TileJoint::All,                       // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_21.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|边|草".to_string()), // 0上
-                TileJoint::TagOne("x|边|草".to_string()), // 1下
-                TileJoint::TagOne("草空".to_string()),    // 2左
-                TileJoint::TagOne("草".to_string()),       // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_23.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|草|边".to_string()), // 0上
-                TileJoint::TagOne("x|草|边".to_string()), // 1下
-                TileJoint::TagOne("草".to_string()),       // 2左
-                TileJoint::TagOne("草空".to_string()),    // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_39.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|边|草".to_string()), // 0上
-                TileJoint::TagOne("草空".to_string()),    // 1下
-                TileJoint::TagOne("草空".to_string()),    // 2左
-                TileJoint::TagOne("y|草|边".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_40.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("草".to_string()),       // 0上
-                TileJoint::TagOne("草空".to_string()),    // 1下
-                TileJoint::TagOne("y|草|边".to_string()), // 2左
-                TileJoint::TagOne("y|草|边".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_41.png".to_string(),
-            layer,
-            rng_seed: 2,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|草|边".to_string()), // 0上
-                TileJoint::TagOne("草空".to_string()),    // 1下
-                TileJoint::TagOne("y|草|边".to_string()), // 2左
-                TileJoint::TagOne("草空".to_string()),    // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::All,                             // 5后
-            ],
-        });
-    }
-
-    // 池塘
-    {
-        superposition.push(Tile {
-            filename: "0-tileset_17.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("池空".to_string()),    // 0上
-                TileJoint::TagOne("x|边|水".to_string()), // 1下
-                TileJoint::TagOne("池空".to_string()),    // 2左
-                TileJoint::TagOne("y|边|水".to_string()), // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_18.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("池空".to_string()),    // 0上
-                TileJoint::TagOne("水".to_string()),       // 1下
-                TileJoint::TagOne("y|边|水".to_string()), // 2左
-                TileJoint::TagOne("y|边|水".to_string()), // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_19.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("池空".to_string()),    // 0上
-                TileJoint::TagOne("x|水|边".to_string()), // 1下
-                TileJoint::TagOne("y|边|水".to_string()), // 2左
-                TileJoint::TagOne("池空".to_string()),    // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_36.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|边|水".to_string()), // 0上
-                TileJoint::TagOne("x|边|水".to_string()), // 1下
-                TileJoint::TagOne("池空".to_string()),    // 2左
-                TileJoint::TagOne("水".to_string()),       // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_37.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("水".to_string()), // 0上
-                TileJoint::TagOne("水".to_string()), // 1下
-                TileJoint::TagOne("水".to_string()), // 2左
-                TileJoint::TagOne("水".to_string()), // 3右
-                TileJoint::None,                      // 4前
-                TileJoint::None,                      // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_38.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|水|边".to_string()), // 0上
-                TileJoint::TagOne("x|水|边".to_string()), // 1下
-                TileJoint::TagOne("水".to_string()),       // 2左
-                TileJoint::TagOne("池空".to_string()),    // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_54.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|边|水".to_string()), // 0上
-                TileJoint::TagOne("池空".to_string()),    // 1下
-                TileJoint::TagOne("池空".to_string()),    // 2左
-                TileJoint::TagOne("y|水|边".to_string()), // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_55.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("水".to_string()),       // 0上
-                TileJoint::TagOne("池空".to_string()),    // 1下
-                TileJoint::TagOne("y|水|边".to_string()), // 2左
-                TileJoint::TagOne("y|水|边".to_string()), // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_56.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::Full,
-            joints: [
-                TileJoint::TagOne("x|水|边".to_string()), // 0上
-                TileJoint::TagOne("池空".to_string()),    // 1下
-                TileJoint::TagOne("y|水|边".to_string()), // 2左
-                TileJoint::TagOne("池空".to_string()),    // 3右
-                TileJoint::None,                            // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-    }
-
-    // 泥地
-    // {
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_09.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("边".to_string()),       // 0上
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 1下
-    //             TileJoint::TagOne("边".to_string()),       // 2左
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_10.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("边".to_string()),       // 0上
-    //             TileJoint::TagOne("泥".to_string()),       // 1下
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 2左
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_11.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("边".to_string()),       // 0上
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 1下
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 2左
-    //             TileJoint::TagOne("边".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_29.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 0上
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 1下
-    //             TileJoint::TagOne("边".to_string()),       // 2左
-    //             TileJoint::TagOne("泥".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_30.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("泥".to_string()), // 0上
-    //             TileJoint::TagOne("泥".to_string()), // 1下
-    //             TileJoint::TagOne("泥".to_string()), // 2左
-    //             TileJoint::TagOne("泥".to_string()), // 3右
-    //             TileJoint::All,                       // 4前
-    //             TileJoint::None,                      // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_31.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 0上
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 1下
-    //             TileJoint::TagOne("泥".to_string()),       // 2左
-    //             TileJoint::TagOne("边".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_47.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 0上
-    //             TileJoint::TagOne("边".to_string()),       // 1下
-    //             TileJoint::TagOne("边".to_string()),       // 2左
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_48.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("泥".to_string()),       // 0上
-    //             TileJoint::TagOne("边".to_string()),       // 1下
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 2左
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_49.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 0上
-    //             TileJoint::TagOne("边".to_string()),       // 1下
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 2左
-    //             TileJoint::TagOne("边".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    // }
-
-    // 砖地
-    {
-        superposition.push(Tile {
-            filename: "0-tileset_13.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("砖空".to_string()),    // 0上
-                TileJoint::TagOne("x|边|砖".to_string()), // 1下
-                TileJoint::TagOne("砖空".to_string()),    // 2左
-                TileJoint::TagOne("y|边|砖".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_14.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("砖空".to_string()),    // 0上
-                TileJoint::TagOne("砖".to_string()),       // 1下
-                TileJoint::TagOne("y|边|砖".to_string()), // 2左
-                TileJoint::TagOne("y|边|砖".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_15.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("砖空".to_string()),    // 0上
-                TileJoint::TagOne("x|砖|边".to_string()), // 1下
-                TileJoint::TagOne("y|边|砖".to_string()), // 2左
-                TileJoint::TagOne("砖空".to_string()),    // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_33.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("x|边|砖".to_string()), // 0上
-                TileJoint::TagOne("x|边|砖".to_string()), // 1下
-                TileJoint::TagOne("砖空".to_string()),    // 2左
-                TileJoint::TagOne("砖".to_string()),       // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_34.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("砖".to_string()), // 0上
-                TileJoint::TagOne("砖".to_string()), // 1下
-                TileJoint::TagOne("砖".to_string()), // 2左
-                TileJoint::TagOne("砖".to_string()), // 3右
-                TileJoint::All,                       // 4前
-                TileJoint::None,                      // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_35.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("x|砖|边".to_string()), // 0上
-                TileJoint::TagOne("x|砖|边".to_string()), // 1下
-                TileJoint::TagOne("砖".to_string()),       // 2左
-                TileJoint::TagOne("砖空".to_string()),    // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_51.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("x|边|砖".to_string()), // 0上
-                TileJoint::TagOne("砖空".to_string()),    // 1下
-                TileJoint::TagOne("砖空".to_string()),    // 2左
-                TileJoint::TagOne("y|砖|边".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_52.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("砖".to_string()),       // 0上
-                TileJoint::TagOne("砖空".to_string()),    // 1下
-                TileJoint::TagOne("y|砖|边".to_string()), // 2左
-                TileJoint::TagOne("y|砖|边".to_string()), // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
-        superposition.push(Tile {
-            filename: "0-tileset_53.png".to_string(),
-            layer,
-            rng_seed: 1,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("x|砖|边".to_string()), // 0上
-                TileJoint::TagOne("砖空".to_string()),    // 1下
-                TileJoint::TagOne("y|砖|边".to_string()), // 2左
-                TileJoint::TagOne("砖空".to_string()),    // 3右
-                TileJoint::All,                             // 4前
-                TileJoint::None,                            // 5后
-            ],
-        });
+#[test]
+fn test_collapse() {
+    for seed in 0..5 {
+        let mut tile_map = test_tile_map(20, 2);
+        fill_slots(&mut tile_map, |_| None);
+        collapse(&mut tile_map.slot_map, &mut StdRng::seed_from_u64(seed));
+        assert_eq!(tile_map.slot_map.len(), 21 * 21 * 2);
+        assert_collapsed(&tile_map.slot_map);
+        // 默认地形不需要填充胶水tile
+        let glue = Some(tileset::get().glue().clone());
+        assert!(tile_map.slot_map.values().all(|slot| slot.tile != glue));
     }
-
-    superposition
 }
 
-/// 加载地形上层建筑
-pub fn load_item_superposition(layer: usize) -> Vec<Tile> {
-    let mut superposition = Vec::new();
-    superposition.push(Tile {
-        filename: "0-tileset_50.png".to_string(),
-        layer,
+#[test]
+fn test_collapse_contradiction() {
+    use protocol::data::tile_map_data::TileCollider;
+    let tag = |tag: &str| TileJoint::TagOne(tag.to_string());
+    // joints: 上 下 左 右
+    let tile = |filename: &str, joints: [&str; 4]| Tile {
+        filename: filename.to_string(),
+        layer: 1,
         rng_seed: 1,
         collider: TileCollider::None,
         joints: [
-            TileJoint::TagOne("边".to_string()), // 0上
-            TileJoint::TagOne("边".to_string()), // 1下
-            TileJoint::TagOne("边".to_string()), // 2左
-            TileJoint::TagOne("边".to_string()), // 3右
-            TileJoint::All,                       // 4前
-            TileJoint::All,                       // 5后
+            tag(joints[0]),
+            tag(joints[1]),
+            tag(joints[2]),
+            tag(joints[3]),
+            TileJoint::All,
+            TileJoint::All,
         ],
-    });
-    superposition
-}
+    };
+    let slot = |x, y, superposition: Vec<Tile>, tile| Slot {
+        point: IVec3::new(x, y, 0),
+        entropy: superposition.len(),
+        superposition,
+        tile,
+    };
 
-pub fn get_tile_by_filename(filename: String) -> Tile {
-    let mut superposition = HashMap::new();
-    // 添加空地
-    superposition.insert(
-        "0-tileset_30.png".to_string(),
-        Tile {
-            filename: "0-tileset_30.png".to_string(),
-            layer: 1,
-            rng_seed: 40,
-            collider: TileCollider::None,
-            joints: [
-                TileJoint::TagOne("空".to_string()), // 0上
-                TileJoint::TagOne("空".to_string()), // 1下
-                TileJoint::TagOne("空".to_string()), // 2左
-                TileJoint::TagOne("空".to_string()), // 3右
-                TileJoint::All,                       // 4前
-                TileJoint::All,                       // 5后
-            ],
-        },
+    // 与固定tile矛盾的选择在传播时排除
+    let a = tile("a", ["a", "a", "a", "a"]);
+    let b = tile("b", ["b", "b", "b", "b"]);
+    let mut slot_map = HashMap::new();
+    slot_map.insert(
+        IVec3::new(0, 0, 0),
+        slot(0, 0, vec![a.clone(), b.clone()], None),
     );
-
-    // 草地
-    {
-        superposition.insert(
-            "0-tileset_01.png".to_string(),
-            Tile {
-                filename: "0-tileset_01.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("草空".to_string()),    // 0上
-                    TileJoint::TagOne("x|边|草".to_string()), // 1下
-                    TileJoint::TagOne("草空".to_string()),    // 2左
-                    TileJoint::TagOne("y|边|草".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_02.png".to_string(),
-            Tile {
-                filename: "0-tileset_02.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("草空".to_string()),    // 0上
-                    TileJoint::TagOne("草".to_string()),       // 1下
-                    TileJoint::TagOne("y|边|草".to_string()), // 2左
-                    TileJoint::TagOne("y|边|草".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_03.png".to_string(),
-            Tile {
-                filename: "0-tileset_03.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("草空".to_string()),    // 0上
-                    TileJoint::TagOne("x|草|边".to_string()), // 1下
-                    TileJoint::TagOne("y|边|草".to_string()), // 2左
-                    TileJoint::TagOne("草空".to_string()),    // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_04.png".to_string(),
-            Tile {
-                filename: "0-tileset_04.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("草".to_string()), // 0上
-                    TileJoint::TagOne("草".to_string()), // 1下
-                    TileJoint::TagOne("草".to_string()), // 2左
-                    TileJoint::TagOne("草".to_string()), // 3右
-                    TileJoint::All,                       // 4前
-                    TileJoint::All,                       // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_21.png".to_string(),
-            Tile {
-                filename: "0-tileset_21.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|边|草".to_string()), // 0上
-                    TileJoint::TagOne("x|边|草".to_string()), // 1下
-                    TileJoint::TagOne("草空".to_string()),    // 2左
-                    TileJoint::TagOne("草".to_string()),       // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_23.png".to_string(),
-            Tile {
-                filename: "0-tileset_23.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|草|边".to_string()), // 0上
-                    TileJoint::TagOne("x|草|边".to_string()), // 1下
-                    TileJoint::TagOne("草".to_string()),       // 2左
-                    TileJoint::TagOne("草空".to_string()),    // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_39.png".to_string(),
-            Tile {
-                filename: "0-tileset_39.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|边|草".to_string()), // 0上
-                    TileJoint::TagOne("草空".to_string()),    // 1下
-                    TileJoint::TagOne("草空".to_string()),    // 2左
-                    TileJoint::TagOne("y|草|边".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_40.png".to_string(),
-            Tile {
-                filename: "0-tileset_40.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("草".to_string()),       // 0上
-                    TileJoint::TagOne("草空".to_string()),    // 1下
-                    TileJoint::TagOne("y|草|边".to_string()), // 2左
-                    TileJoint::TagOne("y|草|边".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
+    slot_map.insert(
+        IVec3::new(1, 0, 0),
+        slot(1, 0, vec![a.clone(), b.clone()], None),
+    );
+    slot_map.insert(IVec3::new(2, 0, 0), slot(2, 0, Vec::new(), Some(b.clone())));
+    collapse(&mut slot_map, &mut StdRng::seed_from_u64(0));
+    assert_collapsed(&slot_map);
+    assert_eq!(slot_map[&IVec3::new(0, 0, 0)].tile, Some(b));
+
+    // 2x2无解的地图: 每行左右不同, 左列上下相同, 右列上下不同
+    // 传播无法发现矛盾, 回溯所有选择后填充胶水tile
+    let same_x = tile("x", ["3", "3", "2", "1"]);
+    let same_y = tile("y", ["4", "4", "1", "2"]);
+    let diff_x = tile("x", ["5", "6", "2", "1"]);
+    let diff_y = tile("y", ["6", "5", "1", "2"]);
+    let mut slot_map = HashMap::new();
+    for y in 0..2 {
+        slot_map.insert(
+            IVec3::new(0, y, 0),
+            slot(0, y, vec![same_x.clone(), same_y.clone()], None),
         );
-        superposition.insert(
-            "0-tileset_41.png".to_string(),
-            Tile {
-                filename: "0-tileset_41.png".to_string(),
-                layer: 1,
-                rng_seed: 2,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|草|边".to_string()), // 0上
-                    TileJoint::TagOne("草空".to_string()),    // 1下
-                    TileJoint::TagOne("y|草|边".to_string()), // 2左
-                    TileJoint::TagOne("草空".to_string()),    // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::All,                             // 5后
-                ],
-            },
+        slot_map.insert(
+            IVec3::new(1, y, 0),
+            slot(1, y, vec![diff_x.clone(), diff_y.clone()], None),
         );
     }
+    collapse(&mut slot_map, &mut StdRng::seed_from_u64(0));
+    assert_collapsed(&slot_map);
+    let glue = Some(tileset::get().glue().clone());
+    assert!(slot_map.values().any(|slot| slot.tile == glue));
+}
 
-    // 池塘
-    {
-        superposition.insert(
-            "0-tileset_17.png".to_string(),
-            Tile {
-                filename: "0-tileset_17.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("池空".to_string()),    // 0上
-                    TileJoint::TagOne("x|边|水".to_string()), // 1下
-                    TileJoint::TagOne("池空".to_string()),    // 2左
-                    TileJoint::TagOne("y|边|水".to_string()), // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_18.png".to_string(),
-            Tile {
-                filename: "0-tileset_18.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("池空".to_string()),    // 0上
-                    TileJoint::TagOne("水".to_string()),       // 1下
-                    TileJoint::TagOne("y|边|水".to_string()), // 2左
-                    TileJoint::TagOne("y|边|水".to_string()), // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_19.png".to_string(),
-            Tile {
-                filename: "0-tileset_19.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("池空".to_string()),    // 0上
-                    TileJoint::TagOne("x|水|边".to_string()), // 1下
-                    TileJoint::TagOne("y|边|水".to_string()), // 2左
-                    TileJoint::TagOne("池空".to_string()),    // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_36.png".to_string(),
-            Tile {
-                filename: "0-tileset_36.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|边|水".to_string()), // 0上
-                    TileJoint::TagOne("x|边|水".to_string()), // 1下
-                    TileJoint::TagOne("池空".to_string()),    // 2左
-                    TileJoint::TagOne("水".to_string()),       // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_37.png".to_string(),
-            Tile {
-                filename: "0-tileset_37.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("水".to_string()), // 0上
-                    TileJoint::TagOne("水".to_string()), // 1下
-                    TileJoint::TagOne("水".to_string()), // 2左
-                    TileJoint::TagOne("水".to_string()), // 3右
-                    TileJoint::None,                      // 4前
-                    TileJoint::None,                      // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_38.png".to_string(),
-            Tile {
-                filename: "0-tileset_38.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|水|边".to_string()), // 0上
-                    TileJoint::TagOne("x|水|边".to_string()), // 1下
-                    TileJoint::TagOne("水".to_string()),       // 2左
-                    TileJoint::TagOne("池空".to_string()),    // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_54.png".to_string(),
-            Tile {
-                filename: "0-tileset_54.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|边|水".to_string()), // 0上
-                    TileJoint::TagOne("池空".to_string()),    // 1下
-                    TileJoint::TagOne("池空".to_string()),    // 2左
-                    TileJoint::TagOne("y|水|边".to_string()), // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_55.png".to_string(),
-            Tile {
-                filename: "0-tileset_55.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("水".to_string()),       // 0上
-                    TileJoint::TagOne("池空".to_string()),    // 1下
-                    TileJoint::TagOne("y|水|边".to_string()), // 2左
-                    TileJoint::TagOne("y|水|边".to_string()), // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_56.png".to_string(),
-            Tile {
-                filename: "0-tileset_56.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::Full,
-                joints: [
-                    TileJoint::TagOne("x|水|边".to_string()), // 0上
-                    TileJoint::TagOne("池空".to_string()),    // 1下
-                    TileJoint::TagOne("y|水|边".to_string()), // 2左
-                    TileJoint::TagOne("池空".to_string()),    // 3右
-                    TileJoint::None,                            // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-    }
+#[test]
+fn test_create_map() {
+    use glam::UVec3;
+    let mut tile_map = TileMap {
+        center_point: IVec3::new(0, 0, 0),
+        texture_size: UVec3::new(64, 64, 1),
+        chunk_size: UVec3::new(1, 1, 1),
+        map_size: UVec3::new(5, 5, 1),
+        slot_map: HashMap::new(),
+        seed: 0,
+    };
+    create_map(0, &mut tile_map);
+    // println!("{:?}", tile_map);
+}
 
-    // 泥地
-    // {
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_09.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("边".to_string()),       // 0上
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 1下
-    //             TileJoint::TagOne("边".to_string()),       // 2左
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_10.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("边".to_string()),       // 0上
-    //             TileJoint::TagOne("泥".to_string()),       // 1下
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 2左
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_11.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("边".to_string()),       // 0上
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 1下
-    //             TileJoint::TagOne("y|边|泥".to_string()), // 2左
-    //             TileJoint::TagOne("边".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_29.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 0上
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 1下
-    //             TileJoint::TagOne("边".to_string()),       // 2左
-    //             TileJoint::TagOne("泥".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_30.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("泥".to_string()), // 0上
-    //             TileJoint::TagOne("泥".to_string()), // 1下
-    //             TileJoint::TagOne("泥".to_string()), // 2左
-    //             TileJoint::TagOne("泥".to_string()), // 3右
-    //             TileJoint::All,                       // 4前
-    //             TileJoint::None,                      // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_31.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 0上
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 1下
-    //             TileJoint::TagOne("泥".to_string()),       // 2左
-    //             TileJoint::TagOne("边".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_47.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|边|泥".to_string()), // 0上
-    //             TileJoint::TagOne("边".to_string()),       // 1下
-    //             TileJoint::TagOne("边".to_string()),       // 2左
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_48.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("泥".to_string()),       // 0上
-    //             TileJoint::TagOne("边".to_string()),       // 1下
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 2左
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    //     superposition.push(Tile {
-    //         filename: "0-tileset_49.png".to_string(),
-    //         layer,
-    //         collider: TileCollider::Full,
-    //         joints: [
-    //             TileJoint::TagOne("x|泥|边".to_string()), // 0上
-    //             TileJoint::TagOne("边".to_string()),       // 1下
-    //             TileJoint::TagOne("y|泥|边".to_string()), // 2左
-    //             TileJoint::TagOne("边".to_string()),       // 3右
-    //             TileJoint::All,                             // 4前
-    //             TileJoint::None,                            // 5后
-    //         ],
-    //     });
-    // }
+#[test]
+fn test_chunk_seed() {
+    assert_eq!(chunk_seed(42, (0, 0)), chunk_seed(42, (0, 0)));
+    assert_eq!(chunk_seed(42, (0, 0)), 0x57e1_faba_6510_7204);
+    assert_eq!(chunk_seed(42, (-1, 3)), 0x82eb_87dc_a9c2_840a);
+    assert_ne!(chunk_seed(42, (0, 1)), chunk_seed(42, (1, 0)));
+    assert_ne!(chunk_seed(42, (0, 0)), chunk_seed(43, (0, 0)));
+}
 
-    // 砖地
-    {
-        superposition.insert(
-            "0-tileset_13.png".to_string(),
-            Tile {
-                filename: "0-tileset_13.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("砖空".to_string()),    // 0上
-                    TileJoint::TagOne("x|边|砖".to_string()), // 1下
-                    TileJoint::TagOne("砖空".to_string()),    // 2左
-                    TileJoint::TagOne("y|边|砖".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_14.png".to_string(),
-            Tile {
-                filename: "0-tileset_14.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("砖空".to_string()),    // 0上
-                    TileJoint::TagOne("砖".to_string()),       // 1下
-                    TileJoint::TagOne("y|边|砖".to_string()), // 2左
-                    TileJoint::TagOne("y|边|砖".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_15.png".to_string(),
-            Tile {
-                filename: "0-tileset_15.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("砖空".to_string()),    // 0上
-                    TileJoint::TagOne("x|砖|边".to_string()), // 1下
-                    TileJoint::TagOne("y|边|砖".to_string()), // 2左
-                    TileJoint::TagOne("砖空".to_string()),    // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_33.png".to_string(),
-            Tile {
-                filename: "0-tileset_33.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("x|边|砖".to_string()), // 0上
-                    TileJoint::TagOne("x|边|砖".to_string()), // 1下
-                    TileJoint::TagOne("砖空".to_string()),    // 2左
-                    TileJoint::TagOne("砖".to_string()),       // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_34.png".to_string(),
-            Tile {
-                filename: "0-tileset_34.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("砖".to_string()), // 0上
-                    TileJoint::TagOne("砖".to_string()), // 1下
-                    TileJoint::TagOne("砖".to_string()), // 2左
-                    TileJoint::TagOne("砖".to_string()), // 3右
-                    TileJoint::All,                       // 4前
-                    TileJoint::None,                      // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_35.png".to_string(),
-            Tile {
-                filename: "0-tileset_35.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("x|砖|边".to_string()), // 0上
-                    TileJoint::TagOne("x|砖|边".to_string()), // 1下
-                    TileJoint::TagOne("砖".to_string()),       // 2左
-                    TileJoint::TagOne("砖空".to_string()),    // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_51.png".to_string(),
-            Tile {
-                filename: "0-tileset_51.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("x|边|砖".to_string()), // 0上
-                    TileJoint::TagOne("砖空".to_string()),    // 1下
-                    TileJoint::TagOne("砖空".to_string()),    // 2左
-                    TileJoint::TagOne("y|砖|边".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_52.png".to_string(),
-            Tile {
-                filename: "0-tileset_52.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("砖".to_string()),       // 0上
-                    TileJoint::TagOne("砖空".to_string()),    // 1下
-                    TileJoint::TagOne("y|砖|边".to_string()), // 2左
-                    TileJoint::TagOne("y|砖|边".to_string()), // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
-        );
-        superposition.insert(
-            "0-tileset_53.png".to_string(),
-            Tile {
-                filename: "0-tileset_53.png".to_string(),
-                layer: 1,
-                rng_seed: 1,
-                collider: TileCollider::None,
-                joints: [
-                    TileJoint::TagOne("x|砖|边".to_string()), // 0上
-                    TileJoint::TagOne("砖空".to_string()),    // 1下
-                    TileJoint::TagOne("y|砖|边".to_string()), // 2左
-                    TileJoint::TagOne("砖空".to_string()),    // 3右
-                    TileJoint::All,                             // 4前
-                    TileJoint::None,                            // 5后
-                ],
-            },
+#[test]
+fn test_seeded_map() {
+    let generate = |seed: u64| {
+        let mut tile_map = test_tile_map(9, 2);
+        tile_map.seed = seed;
+        fill_slots(&mut tile_map, |_| None);
+        collapse(
+            &mut tile_map.slot_map,
+            &mut StdRng::seed_from_u64(tile_map.seed),
         );
+        let mut tiles: Vec<((i32, i32, i32), String)> = tile_map
+            .slot_map
+            .into_iter()
+            .map(|(point, slot)| ((point.x, point.y, point.z), slot.tile.unwrap().filename))
+            .collect();
+        tiles.sort();
+        tiles
+    };
+    // 相同种子得到相同地图, 不同种子得到不同地图
+    let tiles = generate(7);
+    assert_eq!(tiles, generate(7));
+    assert_ne!(tiles, generate(8));
+    assert_eq!(tiles[0], ((-4, -4, 0), "0-tileset_30.png".to_string()));
+}
+
+#[test]
+fn test_chunk_order() {
+    let chunks = [(0, 0), (1, 0), (0, 1), (1, 1), (-1, -1)];
+    let filenames = |tiles: &[TileState]| -> Vec<((i32, i32, i32), String)> {
+        tiles
+            .iter()
+            .map(|tile_state| (tile_state.point, tile_state.filename.clone()))
+            .collect()
+    };
+    let generate = |order: &[usize]| {
+        let mut tiles = HashMap::new();
+        for i in order {
+            tiles.insert(chunks[*i], generate_chunk(7, chunks[*i]));
+        }
+        tiles
+    };
+    // 不同的生成顺序得到相同的地形
+    let tiles = generate(&[0, 1, 2, 3, 4]);
+    let reordered = generate(&[4, 3, 1, 2, 0]);
+    for chunk in chunks.iter() {
+        assert_eq!(filenames(&tiles[chunk]), filenames(&reordered[chunk]));
     }
+    assert_ne!(
+        filenames(&tiles[&(0, 0)]),
+        filenames(&generate_chunk(8, (0, 0)))
+    );
 
-    superposition.get(&filename).unwrap().clone()
+    // 相邻区块的边缘能相接
+    let mut slot_map = HashMap::new();
+    for chunk in chunks[0..4].iter() {
+        assert_eq!(
+            tiles[chunk].len(),
+            (CHUNK_TILES * CHUNK_TILES * CHUNK_LAYERS) as usize
+        );
+        for tile_state in tiles[chunk].iter() {
+            let point = IVec3::new(tile_state.point.0, tile_state.point.1, tile_state.point.2);
+            let tile = tileset::get()
+                .get(point.z as usize, &tile_state.filename)
+                .cloned()
+                .unwrap();
+            let slot = Slot {
+                point,
+                superposition: Vec::new(),
+                entropy: 0,
+                tile: Some(tile),
+            };
+            slot_map.insert(point, slot);
+        }
+    }
+    assert_collapsed(&slot_map);
 }
@@ -0,0 +1,333 @@
+use std::{collections::HashMap, fmt, path::Path, sync::OnceLock};
+
+use protocol::data::tile_map_data::{Tile, TileCollider, TileJoint};
+use serde::{Deserialize, Serialize};
+
+use crate::tile_map::joints_match;
+
+/// 随仓库发布的地块定义, 未调用load时使用
+const DEFAULT_TILES: &str = include_str!("../../tiles.ron");
+
+/// 连接点方向名称, 下标与Tile::joints一致
+const JOINT_NAMES: [&str; 6] = ["上", "下", "左", "右", "前", "后"];
+
+static TILE_SET: OnceLock<TileSet> = OnceLock::new();
+
+/// 地块定义
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TileDefinition {
+    /// 贴图文件名
+    pub filename: String,
+    /// 层级: 0背景 1地形 2设施
+    pub layer: usize,
+    /// 随机权重
+    pub weight: u8,
+    pub collider: TileCollider,
+    /// 连接点: 上 下 左 右 前 后
+    pub joints: [TileJoint; 6],
+}
+
+/// 地块文件内容
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TileSetFile {
+    tags: Vec<String>,
+    glue: TileDefinition,
+    tiles: Vec<TileDefinition>,
+}
+
+#[derive(Debug)]
+pub enum TileSetError {
+    /// 地块文件读取失败
+    Io(String, std::io::Error),
+    /// 地块文件格式错误
+    Parse(String, String),
+    /// 同一层级内文件名重复
+    Duplicate(usize, String),
+    /// 连接点使用了未声明的标签
+    UnknownTag(String, String),
+    /// 连接点在相邻方向上没有可相接的地块
+    Asymmetric(String, usize, String),
+    /// 胶水地块的连接点不是All
+    Glue,
+    /// 胶水地块与其他地块文件名相同
+    GlueFilename(String),
+}
+
+impl fmt::Display for TileSetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TileSetError::Io(path, e) => write!(f, "读取地块文件{}失败: {}", path, e),
+            TileSetError::Parse(path, e) => write!(f, "地块文件{}格式错误: {}", path, e),
+            TileSetError::Duplicate(layer, filename) => {
+                write!(f, "地块{}在层级{}重复定义", filename, layer)
+            }
+            TileSetError::UnknownTag(filename, tag) => {
+                write!(f, "地块{}使用了未声明的标签{}", filename, tag)
+            }
+            TileSetError::Asymmetric(filename, direction, tag) => write!(
+                f,
+                "地块{}的{}连接点{}没有可相接的地块",
+                filename, JOINT_NAMES[*direction], tag
+            ),
+            TileSetError::Glue => write!(f, "胶水地块的连接点必须都是All"),
+            TileSetError::GlueFilename(filename) => {
+                write!(f, "胶水地块{}与其他地块文件名相同", filename)
+            }
+        }
+    }
+}
+
+impl std::error::Error for TileSetError {}
+
+impl TileDefinition {
+    fn tile(&self) -> Tile {
+        Tile {
+            filename: self.filename.clone(),
+            layer: self.layer,
+            rng_seed: self.weight,
+            collider: self.collider.clone(),
+            joints: self.joints.clone(),
+        }
+    }
+}
+
+/// 按层级和文件名索引的地块表
+#[derive(Debug, Clone, PartialEq)]
+pub struct TileSet {
+    glue: Tile,
+    tiles: Vec<Tile>,
+    // 层级 -> 该层地块在tiles中的下标
+    layers: Vec<Vec<usize>>,
+    // (层级, 文件名) -> 下标
+    index: HashMap<(usize, String), usize>,
+}
+
+impl Default for TileSet {
+    fn default() -> Self {
+        TileSet::from_ron("tiles.ron", DEFAULT_TILES).expect("内置地块定义无效")
+    }
+}
+
+impl TileSet {
+    /// 读取RON地块文件
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<TileSet, TileSetError> {
+        let path = path.as_ref().display().to_string();
+        let text = std::fs::read_to_string(&path).map_err(|e| TileSetError::Io(path.clone(), e))?;
+        TileSet::from_ron(&path, &text)
+    }
+
+    pub fn from_ron(path: &str, text: &str) -> Result<TileSet, TileSetError> {
+        let file: TileSetFile = ron::from_str(text)
+            .map_err(|e| TileSetError::Parse(path.to_string(), e.to_string()))?;
+        validate(&file)?;
+
+        let mut tile_set = TileSet {
+            glue: file.glue.tile(),
+            tiles: Vec::new(),
+            layers: Vec::new(),
+            index: HashMap::new(),
+        };
+        for definition in file.tiles.iter() {
+            let id = tile_set.tiles.len();
+            let key = (definition.layer, definition.filename.clone());
+            if tile_set.index.insert(key, id).is_some() {
+                return Err(TileSetError::Duplicate(
+                    definition.layer,
+                    definition.filename.clone(),
+                ));
+            }
+            if tile_set.layers.len() <= definition.layer {
+                tile_set.layers.resize(definition.layer + 1, Vec::new());
+            }
+            tile_set.layers[definition.layer].push(id);
+            tile_set.tiles.push(definition.tile());
+        }
+        Ok(tile_set)
+    }
+
+    /// 层级的所有地块, 用于初始化Slot的叠加态
+    pub fn superposition(&self, layer: usize) -> Vec<Tile> {
+        self.layers.get(layer).map_or_else(Vec::new, |ids| {
+            ids.iter().map(|id| self.tiles[*id].clone()).collect()
+        })
+    }
+
+    /// 按层级和文件名查找地块, 胶水地块在任意层级都能查到
+    pub fn get(&self, layer: usize, filename: &str) -> Option<&Tile> {
+        if filename == self.glue.filename {
+            return Some(&self.glue);
+        }
+        self.index
+            .get(&(layer, filename.to_string()))
+            .map(|id| &self.tiles[*id])
+    }
+
+    /// 坍缩无法满足时填充的胶水地块
+    pub fn glue(&self) -> &Tile {
+        &self.glue
+    }
+}
+
+/// 校验标签都已声明, 胶水地块可接任意地块, 每个连接点在相邻方向上都有可相接的地块
+fn validate(file: &TileSetFile) -> Result<(), TileSetError> {
+    if file
+        .glue
+        .joints
+        .iter()
+        .any(|joint| *joint != TileJoint::All)
+    {
+        return Err(TileSetError::Glue);
+    }
+    // 胶水地块按文件名识别, 不能与其他地块混淆
+    if file
+        .tiles
+        .iter()
+        .any(|definition| definition.filename == file.glue.filename)
+    {
+        return Err(TileSetError::GlueFilename(file.glue.filename.clone()));
+    }
+    for definition in file.tiles.iter() {
+        for (direction, joint) in definition.joints.iter().enumerate() {
+            let tag = match joint {
+                TileJoint::TagOne(tag) => tag,
+                _ => continue,
+            };
+            if !file.tags.contains(tag) {
+                return Err(TileSetError::UnknownTag(
+                    definition.filename.clone(),
+                    tag.clone(),
+                ));
+            }
+            // 上下左右与同层相接, 前后与上一层和下一层相接
+            let layer = match direction {
+                4 => definition.layer + 1,
+                5 if definition.layer == 0 => continue,
+                5 => definition.layer - 1,
+                _ => definition.layer,
+            };
+            let mut neighbors = file
+                .tiles
+                .iter()
+                .filter(|other| other.layer == layer)
+                .peekable();
+            // 没有相邻层级时不受约束
+            if neighbors.peek().is_some()
+                && !neighbors.any(|other| joints_match(joint, &other.joints[direction ^ 1]))
+            {
+                return Err(TileSetError::Asymmetric(
+                    definition.filename.clone(),
+                    direction,
+                    tag.clone(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// 读取地块文件作为全局地块表, 文件不存在时使用内置地块
+pub fn load<P: AsRef<Path>>(path: P) -> Result<&'static TileSet, TileSetError> {
+    let tile_set = if path.as_ref().exists() {
+        TileSet::from_file(path)?
+    } else {
+        println!("地块文件{}不存在, 使用内置地块", path.as_ref().display());
+        TileSet::default()
+    };
+    Ok(TILE_SET.get_or_init(|| tile_set))
+}
+
+/// 全局地块表, 未调用load时使用内置地块
+pub fn get() -> &'static TileSet {
+    TILE_SET.get_or_init(TileSet::default)
+}
+
+#[test]
+fn test_tile_set() {
+    let tile_set = TileSet::default();
+    assert_eq!(tile_set.superposition(0).len(), 1);
+    assert_eq!(tile_set.superposition(1).len(), 28);
+    assert_eq!(tile_set.superposition(2).len(), 1);
+    assert!(tile_set.superposition(3).is_empty());
+    // 同一贴图在不同层级是不同的地块
+    let empty = tile_set.get(1, "0-tileset_30.png").unwrap();
+    assert_eq!(empty.rng_seed, 40);
+    assert_eq!(empty.layer, 1);
+    assert_eq!(tile_set.get(0, "0-tileset_30.png").unwrap().layer, 0);
+    assert!(tile_set.get(2, "0-tileset_30.png").is_none());
+    assert_eq!(tile_set.glue().collider, TileCollider::Full);
+    // 胶水地块在任意层级都能查到
+    for layer in 0..3 {
+        assert_eq!(
+            tile_set.get(layer, &tile_set.glue().filename),
+            Some(tile_set.glue())
+        );
+    }
+    // 随仓库发布的地块文件与内置地块一致
+    assert_eq!(TileSet::from_file("../tiles.ron").unwrap(), tile_set);
+}
+
+#[test]
+fn test_tile_set_errors() {
+    let tile = |filename: &str, layer: usize, joints: &str| {
+        format!(
+            "(filename: \"{}\", layer: {}, weight: 1, collider: None, joints: ({}))",
+            filename, layer, joints
+        )
+    };
+    let file = |tiles: &[&str]| {
+        format!(
+            "(tags: [\"草\", \"x|边|草\"], glue: {}, tiles: [{}])",
+            tile("glue.png", 0, "All, All, All, All, All, All"),
+            tiles.join(", ")
+        )
+    };
+    let grass = tile(
+        "a.png",
+        1,
+        "TagOne(\"草\"), TagOne(\"草\"), All, All, All, All",
+    );
+    let tile_set = TileSet::from_ron("test.ron", &file(&[&grass])).unwrap();
+    assert_eq!(tile_set.superposition(1).len(), 1);
+    assert!(tile_set.superposition(0).is_empty());
+
+    let text = file(&[&grass, &grass]);
+    assert!(matches!(
+        TileSet::from_ron("test.ron", &text),
+        Err(TileSetError::Duplicate(1, _))
+    ));
+    // 未声明的标签
+    let text = file(&[&tile("b.png", 1, "TagOne(\"砖\"), All, All, All, All, All")]);
+    assert!(matches!(
+        TileSet::from_ron("test.ron", &text),
+        Err(TileSetError::UnknownTag(_, tag)) if tag == "砖"
+    ));
+    // 下方连接点没有地块能从上方相接
+    let text = file(&[
+        &grass,
+        &tile("c.png", 1, "None, TagOne(\"x|边|草\"), All, All, All, All"),
+    ]);
+    assert!(matches!(
+        TileSet::from_ron("test.ron", &text),
+        Err(TileSetError::Asymmetric(filename, 1, _)) if filename == "c.png"
+    ));
+    // 胶水地块必须可接任意地块
+    let text = file(&[&grass]).replacen("All", "None", 1);
+    assert!(matches!(
+        TileSet::from_ron("test.ron", &text),
+        Err(TileSetError::Glue)
+    ));
+    // 胶水地块不能与其他地块同名
+    let text = file(&[&grass, &tile("glue.png", 1, "All, All, All, All, All, All")]);
+    assert!(matches!(
+        TileSet::from_ron("test.ron", &text),
+        Err(TileSetError::GlueFilename(filename)) if filename == "glue.png"
+    ));
+    // 未知字段
+    let text = file(&[&grass.replace("weight", "rng_seed")]);
+    assert!(matches!(
+        TileSet::from_ron("test.ron", &text),
+        Err(TileSetError::Parse(..))
+    ));
+}
@@ -1,29 +1,360 @@
-pub const SERVER_PORT: u16 = 2101;
-pub const PACKET_SIZE: usize = 2048;
-/// 服务器地址
-// PC
-pub const SERVER_ADDR: &str = "192.168.101.198:2101";
-// ROG JLer-0
-// pub const SERVER_ADDR: &str = "192.168.101.17:2101";
-// ROG JLer
-// pub const SERVER_ADDR: &str = "192.168.101.220:2101";
-// ROG 网线
-// pub const SERVER_ADDR: &str = "192.168.101.228:2101";
-// 野兽
-// pub const SERVER_ADDR: &str = "180.115.194.174:2101";
-// 发布 server
-// pub const SERVER_ADDR: &str = "0.0.0.0:2101";
-// Mac USB
-// pub const SERVER_ADDR: &str = "172.20.10.5:2101";
-// 阿里云
-// pub const SERVER_ADDR: &str = "47.114.179.240:2101";
-// 腾讯云
-// pub const SERVER_ADDR: &str = "jler.vip:2101";
-/// 客户端地址
-pub const CLIENT_ADDR: &str = "0.0.0.0:2102";
-/// 帧间时间
-pub const INTER_FRAME_TIME: f64 = 1f64 / 60f64;
-/// 服务器数据库文件目录
-pub const DB_PATH_SERVER: &str = "db_data/db_server";
-/// 客户端数据库文件目录
-pub const DB_PATH_CLIENT: &str = "db_data/db_client";
+use std::{collections::HashMap, fmt, path::Path, sync::OnceLock};
+
+use serde::{Deserialize, Serialize};
+
+/// 默认配置文件, 位于运行目录下
+pub const CONFIG_FILE: &str = "prime.toml";
+/// 环境变量前缀, 如 PRIME_SERVER_ADDR
+pub const ENV_PREFIX: &str = "PRIME_";
+
+static CONFIG: OnceLock<Config> = OnceLock::new();
+
+/// 运行时配置, 优先级: 命令行参数 > 环境变量 > 配置文件 > 默认值
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// 客户端连接的服务器地址
+    pub server_addr: String,
+    /// 服务器监听地址
+    pub bind_addr: String,
+    /// 客户端本地地址
+    pub client_addr: String,
+    /// 服务器数据库文件目录
+    pub db_path_server: String,
+    /// 客户端数据库文件目录
+    pub db_path_client: String,
+    /// 单个数据报最大字节数
+    pub packet_size: usize,
+    /// 服务器物理模拟频率(帧/秒)
+    pub tick_rate: u32,
+    /// 服务器状态快照发送频率(次/秒), 不高于模拟频率
+    pub snapshot_rate: u32,
+    /// 服务器落后时单次最多补跑的模拟帧数
+    pub max_catch_up_ticks: u32,
+    /// 模拟帧耗时统计输出间隔(秒)
+    pub tick_stats_interval: u64,
+    /// 玩家视野半径, 超出范围的实体不同步
+    pub view_radius: f32,
+    /// 客户端渲染远端实体落后最新快照的时长(毫秒)
+    pub interpolation_delay: u32,
+    /// 快照中断时客户端最长外推时长(毫秒)
+    pub max_extrapolation: u32,
+    /// 服务器承载的房间(独立世界)分组, 玩家登录时按分组进入
+    pub rooms: Vec<u32>,
+    /// 世界边界, 坐标绝对值超出的实体被清除, 玩家被拉回边界
+    pub world_size: f32,
+    /// 初始化地图时出生点周围每个方向预生成的区块数, 其余区块在玩家靠近时生成
+    pub map_chunks: i32,
+    /// 玩家周围加载地形的区块数(每个区块16x16个地块)
+    pub chunk_load_radius: i32,
+    /// 离所有玩家超过该区块数的地形被卸载, 不小于加载半径
+    pub chunk_unload_radius: i32,
+    /// 每个房间初始生成的陷阱数
+    pub trap_count: u32,
+    /// 陷阱随机分布范围
+    pub trap_spread: i32,
+    /// 新玩家随机出生范围
+    pub spawn_spread: i32,
+    /// 玩家每秒恢复的魔力值
+    pub mp_regen: u32,
+    /// 技能定义文件
+    pub skills_file: String,
+    /// 地块定义文件
+    pub tiles_file: String,
+    /// 死亡后的复活等待时长(毫秒)
+    pub respawn_delay: u32,
+    /// 复活后的无敌时长(毫秒)
+    pub respawn_invulnerability: u32,
+    /// 复活点, 选择离其他玩家最远的一个, 为空时在出生范围内随机复活
+    pub spawn_points: Vec<(f32, f32)>,
+    /// 每个房间的队伍数, 玩家进入时加入人数最少的队伍, 0为各自为战
+    pub team_count: u32,
+    /// 是否允许伤害队友
+    pub friendly_fire: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            server_addr: "127.0.0.1:2101".to_string(),
+            bind_addr: "0.0.0.0:2101".to_string(),
+            client_addr: "0.0.0.0:2102".to_string(),
+            db_path_server: "db_data/db_server".to_string(),
+            db_path_client: "db_data/db_client".to_string(),
+            packet_size: 2048,
+            tick_rate: 60,
+            snapshot_rate: 20,
+            max_catch_up_ticks: 5,
+            tick_stats_interval: 10,
+            view_radius: 1200.0,
+            interpolation_delay: 100,
+            max_extrapolation: 250,
+            rooms: vec![0, 1, 2],
+            world_size: 9999.0,
+            map_chunks: 1,
+            chunk_load_radius: 2,
+            chunk_unload_radius: 3,
+            trap_count: 100,
+            trap_spread: 1000,
+            spawn_spread: 500,
+            mp_regen: 5,
+            skills_file: "skills.toml".to_string(),
+            tiles_file: "tiles.ron".to_string(),
+            respawn_delay: 5000,
+            respawn_invulnerability: 3000,
+            spawn_points: vec![
+                (0.0, 0.0),
+                (-500.0, -500.0),
+                (-500.0, 500.0),
+                (500.0, -500.0),
+                (500.0, 500.0),
+            ],
+            team_count: 0,
+            friendly_fire: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    /// 配置文件读取失败
+    Io(String, std::io::Error),
+    /// 配置文件格式错误
+    Parse(String, String),
+    /// 未知的配置项
+    UnknownKey(String),
+    /// 配置值无法解析
+    InvalidValue(String, String),
+    /// 命令行参数缺少值
+    MissingValue(String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "读取配置文件{}失败: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "配置文件{}格式错误: {}", path, e),
+            ConfigError::UnknownKey(key) => write!(f, "未知的配置项: {}", key),
+            ConfigError::InvalidValue(key, value) => {
+                write!(f, "配置项{}的值无效: {}", key, value)
+            }
+            ConfigError::MissingValue(key) => write!(f, "参数{}缺少值", key),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+fn parse<T: std::str::FromStr>(key: &str, value: &str) -> Result<T, ConfigError> {
+    value
+        .trim()
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue(key.to_string(), value.to_string()))
+}
+
+impl Config {
+    /// 读取TOML配置文件, 未填写的项使用默认值
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Config, ConfigError> {
+        let path = path.as_ref().display().to_string();
+        let text = std::fs::read_to_string(&path).map_err(|e| ConfigError::Io(path.clone(), e))?;
+        Config::from_toml(&path, &text)
+    }
+
+    pub fn from_toml(path: &str, text: &str) -> Result<Config, ConfigError> {
+        toml::from_str(text).map_err(|e| ConfigError::Parse(path.to_string(), e.to_string()))
+    }
+
+    /// 按配置项名称设置值, 名称中的'-'视为'_'
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), ConfigError> {
+        let name = key.replace('-', "_");
+        match name.as_str() {
+            "server_addr" => self.server_addr = value.to_string(),
+            "bind_addr" => self.bind_addr = value.to_string(),
+            "client_addr" => self.client_addr = value.to_string(),
+            "db_path_server" => self.db_path_server = value.to_string(),
+            "db_path_client" => self.db_path_client = value.to_string(),
+            "packet_size" => self.packet_size = parse(key, value)?,
+            "tick_rate" => self.tick_rate = parse(key, value)?,
+            "snapshot_rate" => self.snapshot_rate = parse(key, value)?,
+            "max_catch_up_ticks" => self.max_catch_up_ticks = parse(key, value)?,
+            "tick_stats_interval" => self.tick_stats_interval = parse(key, value)?,
+            "view_radius" => self.view_radius = parse(key, value)?,
+            "interpolation_delay" => self.interpolation_delay = parse(key, value)?,
+            "max_extrapolation" => self.max_extrapolation = parse(key, value)?,
+            "rooms" => {
+                self.rooms = value
+                    .split(',')
+                    .filter(|room| !room.trim().is_empty())
+                    .map(|room| parse(key, room))
+                    .collect::<Result<_, _>>()?
+            }
+            "world_size" => self.world_size = parse(key, value)?,
+            "map_chunks" => self.map_chunks = parse(key, value)?,
+            "chunk_load_radius" => self.chunk_load_radius = parse(key, value)?,
+            "chunk_unload_radius" => self.chunk_unload_radius = parse(key, value)?,
+            "trap_count" => self.trap_count = parse(key, value)?,
+            "trap_spread" => self.trap_spread = parse(key, value)?,
+            "spawn_spread" => self.spawn_spread = parse(key, value)?,
+            "mp_regen" => self.mp_regen = parse(key, value)?,
+            "skills_file" => self.skills_file = value.to_string(),
+            "tiles_file" => self.tiles_file = value.to_string(),
+            "respawn_delay" => self.respawn_delay = parse(key, value)?,
+            "respawn_invulnerability" => self.respawn_invulnerability = parse(key, value)?,
+            // 格式: x,y;x,y
+            "spawn_points" => {
+                self.spawn_points = value
+                    .split(';')
+                    .filter(|point| !point.trim().is_empty())
+                    .map(|point| match point.split_once(',') {
+                        Some((x, y)) => Ok((parse(key, x)?, parse(key, y)?)),
+                        None => Err(ConfigError::InvalidValue(
+                            key.to_string(),
+                            value.to_string(),
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?
+            }
+            "team_count" => self.team_count = parse(key, value)?,
+            "friendly_fire" => self.friendly_fire = parse(key, value)?,
+            _ => return Err(ConfigError::UnknownKey(key.to_string())),
+        }
+        Ok(())
+    }
+
+    /// 应用 PRIME_ 前缀的环境变量, 未知的变量忽略
+    pub fn apply_env(&mut self, vars: &HashMap<String, String>) -> Result<(), ConfigError> {
+        for (name, value) in vars {
+            if let Some(key) = name.strip_prefix(ENV_PREFIX) {
+                let key = key.to_lowercase();
+                if key == "config" {
+                    continue;
+                }
+                match self.set(&key, value) {
+                    Err(ConfigError::UnknownKey(_)) => {}
+                    result => result?,
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// 应用命令行参数, 支持 --key value 与 --key=value
+    pub fn apply_args(&mut self, args: &[String]) -> Result<(), ConfigError> {
+        let mut iter = args.iter();
+        while let Some(arg) = iter.next() {
+            let arg = match arg.strip_prefix("--") {
+                Some(arg) => arg,
+                None => return Err(ConfigError::UnknownKey(arg.clone())),
+            };
+            let (key, value) = match arg.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => match iter.next() {
+                    Some(value) => (arg.to_string(), value.clone()),
+                    None => return Err(ConfigError::MissingValue(arg.to_string())),
+                },
+            };
+            if key == "config" {
+                continue;
+            }
+            self.set(&key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// 按优先级合并各来源的配置
+    pub fn from_sources(
+        args: &[String],
+        vars: &HashMap<String, String>,
+    ) -> Result<Config, ConfigError> {
+        // 配置文件路径: --config > PRIME_CONFIG > 默认文件(存在时)
+        let mut path = vars.get(&format!("{}CONFIG", ENV_PREFIX)).cloned();
+        for (index, arg) in args.iter().enumerate() {
+            if let Some(file) = arg.strip_prefix("--config=") {
+                path = Some(file.to_string());
+            } else if arg == "--config" {
+                match args.get(index + 1) {
+                    Some(file) => path = Some(file.clone()),
+                    None => return Err(ConfigError::MissingValue("config".to_string())),
+                }
+            }
+        }
+        let mut config = match path {
+            Some(path) => Config::from_file(path)?,
+            None if Path::new(CONFIG_FILE).exists() => Config::from_file(CONFIG_FILE)?,
+            None => Config::default(),
+        };
+        config.apply_env(vars)?;
+        config.apply_args(args)?;
+        Ok(config)
+    }
+}
+
+/// 读取进程的命令行参数, 环境变量和配置文件, 作为全局配置
+pub fn load() -> Result<&'static Config, ConfigError> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let vars: HashMap<String, String> = std::env::vars().collect();
+    let config = Config::from_sources(&args, &vars)?;
+    Ok(CONFIG.get_or_init(|| config))
+}
+
+/// 全局配置, 未调用load时使用默认值
+pub fn get() -> &'static Config {
+    CONFIG.get_or_init(Config::default)
+}
+
+#[test]
+fn test_from_toml() {
+    let config = Config::from_toml(
+        "test.toml",
+        "server_addr = \"10.0.0.1:2101\"\ntick_rate = 30\nrooms = [0, 5]\n",
+    )
+    .unwrap();
+    assert_eq!(config.server_addr, "10.0.0.1:2101");
+    assert_eq!(config.tick_rate, 30);
+    assert_eq!(config.rooms, vec![0, 5]);
+    // 未填写的项使用默认值
+    assert_eq!(config.snapshot_rate, Config::default().snapshot_rate);
+    assert!(Config::from_toml("test.toml", "unknown = 1").is_err());
+    // 示例配置与默认值保持一致
+    assert_eq!(
+        Config::from_file("../prime.example.toml").unwrap(),
+        Config::default()
+    );
+}
+
+#[test]
+fn test_override() {
+    let args: Vec<String> = vec![
+        "--tick-rate",
+        "30",
+        "--rooms=1,2",
+        "--bind-addr",
+        "0.0.0.0:3000",
+        "--spawn-points=0,0;-100,50.5",
+    ]
+    .into_iter()
+    .map(String::from)
+    .collect();
+    let mut vars = HashMap::new();
+    vars.insert("PRIME_TICK_RATE".to_string(), "20".to_string());
+    vars.insert("PRIME_VIEW_RADIUS".to_string(), "800".to_string());
+    vars.insert("PRIME_OTHER".to_string(), "1".to_string());
+    vars.insert("PATH".to_string(), "/bin".to_string());
+    let config = Config::from_sources(&args, &vars).unwrap();
+    // 命令行参数优先于环境变量
+    assert_eq!(config.tick_rate, 30);
+    assert_eq!(config.view_radius, 800.0);
+    assert_eq!(config.rooms, vec![1, 2]);
+    assert_eq!(config.bind_addr, "0.0.0.0:3000");
+    assert_eq!(config.spawn_points, vec![(0.0, 0.0), (-100.0, 50.5)]);
+
+    let mut config = Config::default();
+    assert!(config.apply_args(&["--tick-rate".to_string()]).is_err());
+    assert!(config
+        .apply_args(&["--tick-rate=fast".to_string()])
+        .is_err());
+    assert!(config.apply_args(&["--unknown=1".to_string()]).is_err());
+    assert!(config
+        .apply_args(&["--spawn-points=0;1,1".to_string()])
+        .is_err());
+}
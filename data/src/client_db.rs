@@ -11,7 +11,10 @@ pub struct GameData {
     pub data: Option<String>,
 }
 
-static DB_PATH: &str = config::DB_PATH_CLIENT;
+// 数据库目录, 由运行时配置决定
+fn db_path() -> &'static str {
+    &config::get().db_path_client
+}
 
 impl GameData {
     pub fn player_online(data: Option<String>) -> Self {
@@ -59,7 +62,7 @@ impl GameData {
 }
 
 pub fn find(key: GameData) -> Result<String, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data = &db.get(format!("{}-({})", key.table, key.key).as_bytes())?;
     if let Some(data) = data {
         Ok(String::from_utf8(data.to_vec())?)
@@ -72,7 +75,7 @@ pub fn find(key: GameData) -> Result<String, Box<dyn Error>> {
 }
 
 pub fn next_u64(key: GameData) -> Result<u64, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data_bt = &db.get(format!("{}-({})", key.table, key.key).as_bytes())?;
     if let Some(data) = data_bt {
         let data_str = String::from_utf8(data.to_vec())?;
@@ -87,7 +90,7 @@ pub fn next_u64(key: GameData) -> Result<u64, Box<dyn Error>> {
 }
 
 pub fn save(data: GameData) -> Result<(), Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let result = db.insert(
         format!("{}-({})", data.table, data.key).as_bytes(),
         data.data.unwrap().as_bytes(),
@@ -104,7 +107,7 @@ pub fn save(data: GameData) -> Result<(), Box<dyn Error>> {
 }
 
 pub fn find_tile_map(point: (i32, i32, i32)) -> Result<TileState, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data = &db.get(format!("tile_map-{:?}", point).as_bytes())?;
     if let Some(data) = data {
         Ok(bincode::deserialize(data)?)
@@ -117,7 +120,7 @@ pub fn find_tile_map(point: (i32, i32, i32)) -> Result<TileState, Box<dyn Error>
 }
 
 pub fn save_tile_map(tile: TileState) -> Result<(), Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let result = db.insert(
         format!("tile_map-{:?}", tile.point).as_bytes(),
         bincode::serialize(&tile)?,
@@ -133,8 +136,18 @@ pub fn save_tile_map(tile: TileState) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
+/// 清空本地地图缓存, 切换房间后重新向服务器请求
+pub fn clear_tile_map() -> Result<(), Box<dyn Error>> {
+    let db = &SledDB::open(db_path())?.db;
+    for iter in db.scan_prefix("tile_map-") {
+        let (key, _) = iter?;
+        db.remove(key)?;
+    }
+    Ok(())
+}
+
 pub fn save_player(player: PlayerData) -> Result<(), Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let result = db.insert(
         format!("player-({})", player.uid).as_bytes(),
         bincode::serialize(&player)?,
@@ -151,7 +164,7 @@ pub fn save_player(player: PlayerData) -> Result<(), Box<dyn Error>> {
 }
 
 pub fn find_player(uid: u32) -> Result<PlayerData, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data = &db.get(format!("player-({})", uid).as_bytes())?;
     if let Some(data) = data {
         Ok(bincode::deserialize(data)?)
@@ -165,5 +178,5 @@ pub fn find_player(uid: u32) -> Result<PlayerData, Box<dyn Error>> {
 
 #[test]
 fn test_iter() {
-    SledDB::show_all(&format!("../{}", config::DB_PATH_CLIENT));
+    SledDB::show_all(&format!("../{}", config::get().db_path_client));
 }
@@ -1,6 +1,9 @@
 use std::error::Error;
 
-use protocol::data::{player_data::PlayerData, tile_map_data::TileState};
+use protocol::{
+    account::AccountRecord,
+    data::{player_data::PlayerData, tile_map_data::TileState},
+};
 
 use crate::sled_db::SledDB;
 
@@ -11,7 +14,10 @@ pub struct GameData {
     pub data: Option<String>,
 }
 
-static DB_PATH: &str = config::DB_PATH_SERVER;
+// 数据库目录, 由运行时配置决定
+fn db_path() -> &'static str {
+    &config::get().db_path_server
+}
 
 impl GameData {
     pub fn player_online(data: Option<String>) -> Self {
@@ -42,6 +48,28 @@ impl GameData {
             data,
         }
     }
+    pub fn player_uid_addr(uid: u32, data: Option<String>) -> Self {
+        GameData {
+            table: "player".to_string(),
+            key: format!("uid_addr_{}", uid),
+            data,
+        }
+    }
+    pub fn player_addr_version(addr: String, data: Option<String>) -> Self {
+        GameData {
+            table: "player".to_string(),
+            key: format!("addr_version_{}", addr),
+            data,
+        }
+    }
+    // 玩家离线时的坐标, 格式"x,y"
+    pub fn player_position(uid: u32, data: Option<String>) -> Self {
+        GameData {
+            table: "player".to_string(),
+            key: format!("position_{}", uid),
+            data,
+        }
+    }
     pub fn player_queue_uid(data: Option<String>) -> Self {
         GameData {
             table: "player".to_string(),
@@ -56,10 +84,26 @@ impl GameData {
             data,
         }
     }
+    // 已生成的地图区块, 值为"1"
+    pub fn tile_chunk(map_id: u32, chunk: (i32, i32), data: Option<String>) -> Self {
+        GameData {
+            table: "tile_chunk".to_string(),
+            key: format!("{},{},{}", map_id, chunk.0, chunk.1),
+            data,
+        }
+    }
+    // 地图的世界种子, 区块种子由它和区块坐标推导
+    pub fn map_seed(map_id: u32, data: Option<String>) -> Self {
+        GameData {
+            table: "map_seed".to_string(),
+            key: map_id.to_string(),
+            data,
+        }
+    }
 }
 
 pub fn find(key: GameData) -> Result<String, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data = &db.get(format!("{}-({})", key.table, key.key).as_bytes())?;
     if let Some(data) = data {
         Ok(String::from_utf8(data.to_vec())?)
@@ -72,7 +116,7 @@ pub fn find(key: GameData) -> Result<String, Box<dyn Error>> {
 }
 
 pub fn next_u64(key: GameData) -> Result<u64, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data_bt = &db.get(format!("{}-({})", key.table, key.key).as_bytes())?;
     if let Some(data) = data_bt {
         let data_str = String::from_utf8(data.to_vec())?;
@@ -87,7 +131,7 @@ pub fn next_u64(key: GameData) -> Result<u64, Box<dyn Error>> {
 }
 
 pub fn save(data: GameData) -> Result<(), Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let result = db.insert(
         format!("{}-({})", data.table, data.key).as_bytes(),
         data.data.unwrap().as_bytes(),
@@ -103,9 +147,25 @@ pub fn save(data: GameData) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-pub fn find_tile_map(point: (i32, i32, i32)) -> Result<TileState, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
-    let data = &db.get(format!("tile_map-{:?}", point).as_bytes())?;
+/// 删除记录, 记录不存在时忽略
+pub fn remove(key: GameData) -> Result<(), Box<dyn Error>> {
+    let db = &SledDB::open(db_path())?.db;
+    db.remove(format!("{}-({})", key.table, key.key).as_bytes())?;
+    Ok(())
+}
+
+/// 房间地图的键前缀, 0号房间沿用原有的键
+pub fn tile_map_prefix(map_id: u32) -> String {
+    if map_id == 0 {
+        "tile_map-".to_string()
+    } else {
+        format!("tile_map_{}-", map_id)
+    }
+}
+
+pub fn find_tile_map(map_id: u32, point: (i32, i32, i32)) -> Result<TileState, Box<dyn Error>> {
+    let db = &SledDB::open(db_path())?.db;
+    let data = &db.get(format!("{}{:?}", tile_map_prefix(map_id), point).as_bytes())?;
     if let Some(data) = data {
         Ok(bincode::deserialize(data)?)
     } else {
@@ -116,10 +176,10 @@ pub fn find_tile_map(point: (i32, i32, i32)) -> Result<TileState, Box<dyn Error>
     }
 }
 
-pub fn save_tile_map(tile: TileState) -> Result<(), Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+pub fn save_tile_map(map_id: u32, tile: TileState) -> Result<(), Box<dyn Error>> {
+    let db = &SledDB::open(db_path())?.db;
     let result = db.insert(
-        format!("tile_map-{:?}", tile.point).as_bytes(),
+        format!("{}{:?}", tile_map_prefix(map_id), tile.point).as_bytes(),
         bincode::serialize(&tile)?,
     );
     match result {
@@ -133,10 +193,10 @@ pub fn save_tile_map(tile: TileState) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-pub fn find_all_tile() -> Vec<TileState> {
+pub fn find_all_tile(map_id: u32) -> Vec<TileState> {
     let mut tiles = Vec::new();
-    let db = &SledDB::open(DB_PATH).unwrap().db;
-    for iter in db.scan_prefix("tile_map-") {
+    let db = &SledDB::open(db_path()).unwrap().db;
+    for iter in db.scan_prefix(tile_map_prefix(map_id)) {
         match iter {
             Ok((_k, v)) => {
                 if let Ok(t) = bincode::deserialize(&v) {
@@ -151,9 +211,9 @@ pub fn find_all_tile() -> Vec<TileState> {
     tiles
 }
 
-pub fn all_tile(path: &str) {
+pub fn all_tile(path: &str, map_id: u32) {
     let db = &SledDB::open(path).unwrap().db;
-    for iter in db.scan_prefix("tile_map-") {
+    for iter in db.scan_prefix(tile_map_prefix(map_id)) {
         match iter {
             Ok((k, v)) => {
                 println!(
@@ -170,7 +230,7 @@ pub fn all_tile(path: &str) {
 }
 
 pub fn save_player(player: PlayerData) -> Result<(), Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let result = db.insert(
         format!("player-({})", player.uid).as_bytes(),
         bincode::serialize(&player)?,
@@ -187,7 +247,7 @@ pub fn save_player(player: PlayerData) -> Result<(), Box<dyn Error>> {
 }
 
 pub fn find_player(uid: u32) -> Result<PlayerData, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data = &db.get(format!("player-({})", uid).as_bytes())?;
     if let Some(data) = data {
         Ok(bincode::deserialize(data)?)
@@ -199,8 +259,32 @@ pub fn find_player(uid: u32) -> Result<PlayerData, Box<dyn Error>> {
     }
 }
 
+/// 创建账号, 用户名已存在时返回false
+pub fn create_account(username: &str, record: &AccountRecord) -> Result<bool, Box<dyn Error>> {
+    let db = &SledDB::open(db_path())?.db;
+    let result = db.compare_and_swap(
+        format!("account-({})", username).as_bytes(),
+        None as Option<&[u8]>,
+        Some(bincode::serialize(record)?),
+    )?;
+    Ok(result.is_ok())
+}
+
+pub fn find_account(username: &str) -> Result<AccountRecord, Box<dyn Error>> {
+    let db = &SledDB::open(db_path())?.db;
+    let data = &db.get(format!("account-({})", username).as_bytes())?;
+    if let Some(data) = data {
+        Ok(bincode::deserialize(data)?)
+    } else {
+        Err(Box::new(std::io::Error::new(
+            std::io::ErrorKind::Other,
+            "无数据!",
+        )))
+    }
+}
+
 pub fn next_entity_id(entity_type: u8) -> Result<u64, Box<dyn Error>> {
-    let db = &SledDB::open(DB_PATH)?.db;
+    let db = &SledDB::open(db_path())?.db;
     let data_bt = &db.get(format!("entity-id-({})", entity_type).as_bytes())?;
     if let Some(data) = data_bt {
         let data_str = String::from_utf8(data.to_vec())?;
@@ -84,5 +84,5 @@ fn test_sled() {
 
 #[test]
 fn test_iter() {
-    SledDB::show_all(&format!("../{}", config::DB_PATH_SERVER));
+    SledDB::show_all(&format!("../{}", config::get().db_path_server));
 }
@@ -0,0 +1,65 @@
+use serde::{Deserialize, Serialize};
+
+use crate::session::hmac;
+
+// 密码哈希迭代次数
+pub const PASSWORD_ITERATIONS: u32 = 10_000;
+
+/// PBKDF2-HMAC-SHA256, 只取第一个分块(32字节)
+pub fn pbkdf2(password: &[u8], salt: &[u8], iterations: u32) -> [u8; 32] {
+    let mut u = hmac(password, &[salt, &1u32.to_be_bytes()]);
+    let mut result = u;
+    for _ in 1..iterations {
+        u = hmac(password, &[&u]);
+        for (r, b) in result.iter_mut().zip(u.iter()) {
+            *r ^= b;
+        }
+    }
+    result
+}
+
+/// 服务端保存的账号记录: 加盐的密码哈希
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountRecord {
+    pub uid: u32,
+    pub salt: [u8; 16],
+    pub hash: [u8; 32],
+}
+
+impl AccountRecord {
+    pub fn new(uid: u32, salt: [u8; 16], password: &[u8]) -> Self {
+        AccountRecord {
+            uid,
+            salt,
+            hash: pbkdf2(password, &salt, PASSWORD_ITERATIONS),
+        }
+    }
+
+    /// 校验密码, 逐字节比较全部字节
+    pub fn verify(&self, password: &[u8]) -> bool {
+        let hash = pbkdf2(password, &self.salt, PASSWORD_ITERATIONS);
+        hash.iter()
+            .zip(self.hash.iter())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b))
+            == 0
+    }
+}
+
+#[test]
+fn test_pbkdf2() {
+    // RFC 7914 PBKDF2-HMAC-SHA256 测试向量
+    let hash = pbkdf2(b"passwd", b"salt", 1);
+    assert_eq!(hash[..8], [0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f]);
+}
+
+#[test]
+fn test_account_record() {
+    let record = AccountRecord::new(3, [7; 16], b"secret");
+    assert!(record.verify(b"secret"));
+    assert!(!record.verify(b"Secret"));
+    // 相同密码不同盐的哈希不同
+    assert_ne!(record.hash, AccountRecord::new(3, [8; 16], b"secret").hash);
+
+    let bytes = bincode::serialize(&record).unwrap();
+    assert_eq!(bincode::deserialize::<AccountRecord>(&bytes).unwrap(), record);
+}
@@ -0,0 +1,685 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    convert::TryFrom,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    data::{
+        channel_data::{AckData, ChannelData, FragmentData},
+        hello_data::{FEATURE_FRAGMENT, FEATURE_RELIABLE},
+    },
+    packet::Packet,
+    route::{ChannelRoute, GameRoute},
+    snapshot::SnapshotHistory,
+};
+
+/// 未确认数据包重发间隔
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(200);
+/// 最大重发次数, 超过则放弃该数据包, 改为发送占位数据让接收端跳过该序号
+pub const MAX_RESEND: u32 = 25;
+/// 接收窗口, 超前太多的数据包直接丢弃, 等待对端重发
+pub const RECV_WINDOW: u32 = 1024;
+/// 单个分片最大负载, 保证数据报不超过常见MTU
+pub const FRAGMENT_PAYLOAD: usize = 1024;
+/// 单条消息最大分片数, 超过则整条消息丢弃
+pub const MAX_FRAGMENTS: usize = 64;
+/// 未完成重组的消息超时时间
+pub const FRAGMENT_TIMEOUT: Duration = Duration::from_secs(5);
+/// 同时重组的消息数量上限
+pub const MAX_REASSEMBLY: usize = 32;
+
+// 传输通道, 每个可靠通道独立编号与排序
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Channel {
+    // 不可靠: 心跳, 状态同步
+    Unreliable = 0,
+    // 可靠有序: 账号
+    Account = 1,
+    // 可靠有序: 地图
+    Map = 2,
+    // 可靠有序: 控制, 技能
+    Game = 3,
+}
+
+impl TryFrom<u8> for Channel {
+    type Error = u8;
+
+    fn try_from(num: u8) -> Result<Self, Self::Error> {
+        match num {
+            0 => Ok(Channel::Unreliable),
+            1 => Ok(Channel::Account),
+            2 => Ok(Channel::Map),
+            3 => Ok(Channel::Game),
+            _ => Err(num),
+        }
+    }
+}
+
+impl Channel {
+    /// 可靠通道编号, 未知编号和不可靠通道返回None
+    pub fn reliable(num: u8) -> Option<Channel> {
+        match Channel::try_from(num) {
+            Ok(Channel::Unreliable) | Err(_) => None,
+            Ok(channel) => Some(channel),
+        }
+    }
+
+    /// 数据包所属通道
+    pub fn of(packet: &Packet) -> Channel {
+        match packet {
+            Packet::Heartbeat(_) => Channel::Unreliable,
+            Packet::Account(_) => Channel::Account,
+            Packet::Game(game_route) => match game_route {
+                GameRoute::Update(_) => Channel::Unreliable,
+                GameRoute::Player(_) => Channel::Unreliable,
+                GameRoute::PlayerList(_) => Channel::Unreliable,
+                GameRoute::TileMap(_) => Channel::Map,
+                GameRoute::Tile(_) => Channel::Map,
+                GameRoute::Control(_) => Channel::Game,
+                GameRoute::Skill(_) => Channel::Game,
+                GameRoute::Snapshot(_) => Channel::Unreliable,
+                GameRoute::SnapshotAck(_) => Channel::Unreliable,
+                GameRoute::Interest(_) => Channel::Game,
+                GameRoute::PlayerLeft(_) => Channel::Game,
+                GameRoute::Cooldown(_) => Channel::Game,
+                GameRoute::Kill(_) => Channel::Game,
+                GameRoute::Respawn(_) => Channel::Game,
+            },
+            Packet::Channel(_) => Channel::Unreliable,
+            Packet::Session(_) => Channel::Unreliable,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SendError {
+    /// 数据包超过最大分片数或对端不支持分片, 参数为数据报长度
+    TooLarge(usize),
+}
+
+impl fmt::Display for SendError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SendError::TooLarge(len) => write!(f, "数据包过大({}b), 已丢弃", len),
+        }
+    }
+}
+
+impl std::error::Error for SendError {}
+
+/// 编码发往未建立连接的对端的数据包, 无法分片, 超过单个分片负载则丢弃
+pub fn encode(packet: &Packet) -> Result<Vec<u8>, SendError> {
+    let datagram = bincode::serialize(packet).unwrap();
+    if datagram.len() > FRAGMENT_PAYLOAD {
+        return Err(SendError::TooLarge(datagram.len()));
+    }
+    Ok(datagram)
+}
+
+// 待确认数据包
+struct Pending {
+    datagrams: Vec<Vec<u8>>,
+    sent_at: Instant,
+    resend: u32,
+    // 已放弃的消息的占位数据, 一直重发直到确认
+    skip: bool,
+}
+
+// 重组中的消息
+struct Reassembly {
+    received: usize,
+    fragments: Vec<Option<Vec<u8>>>,
+    created_at: Instant,
+}
+
+#[derive(Default)]
+struct SendChannel {
+    next_seq: u32,
+    pending: BTreeMap<u32, Pending>,
+}
+
+#[derive(Default)]
+struct RecvChannel {
+    next_seq: u32,
+    buffer: BTreeMap<u32, Vec<u8>>,
+}
+
+/// 单个对端的可靠传输状态, 不涉及IO, 由调用方负责收发数据报
+pub struct Connection {
+    // 握手协商的功能标志位, 未启用的功能按原样发送
+    pub features: u32,
+    // 握手时的连接标识
+    pub nonce: u32,
+    // 超过重发次数被放弃的数据包数量
+    pub lost: u64,
+    // 超限或重组超时被丢弃的消息数量
+    pub dropped: u64,
+    // 增量快照基准
+    pub snapshots: SnapshotHistory,
+    send_channels: HashMap<Channel, SendChannel>,
+    recv_channels: HashMap<Channel, RecvChannel>,
+    next_fragment_id: u32,
+    reassembly: HashMap<u32, Reassembly>,
+}
+
+impl Connection {
+    pub fn new(features: u32, nonce: u32) -> Self {
+        Connection {
+            features,
+            nonce,
+            lost: 0,
+            dropped: 0,
+            snapshots: SnapshotHistory::default(),
+            send_channels: HashMap::new(),
+            recv_channels: HashMap::new(),
+            next_fragment_id: 0,
+            reassembly: HashMap::new(),
+        }
+    }
+
+    pub fn has_feature(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+
+    /// 打包数据包, 返回待发送的数据报; 可靠通道的数据报会保留直到收到确认
+    /// 数据包过大时返回错误, 不占用可靠通道的序号
+    pub fn send(&mut self, packet: &Packet, now: Instant) -> Result<Vec<Vec<u8>>, SendError> {
+        let channel = Channel::of(packet);
+        let payload = bincode::serialize(packet).unwrap();
+        if !self.has_feature(FEATURE_RELIABLE) || channel == Channel::Unreliable {
+            return self.split(payload);
+        }
+        let seq = self.send_channels.entry(channel).or_default().next_seq;
+        let datagram = bincode::serialize(&Packet::Channel(ChannelRoute::Data(ChannelData {
+            channel: channel as u8,
+            seq,
+            payload,
+        })))
+        .unwrap();
+        // 先拆分再分配序号, 丢弃的数据包不会在接收端留下空洞
+        let datagrams = self.split(datagram)?;
+        let send_channel = self.send_channels.entry(channel).or_default();
+        send_channel.next_seq += 1;
+        send_channel.pending.insert(
+            seq,
+            Pending {
+                datagrams: datagrams.clone(),
+                sent_at: now,
+                resend: 0,
+                skip: false,
+            },
+        );
+        Ok(datagrams)
+    }
+
+    /// 超过单个分片负载的数据报拆分为多个分片, 超过最大分片数或对端不支持分片则丢弃
+    fn split(&mut self, datagram: Vec<u8>) -> Result<Vec<Vec<u8>>, SendError> {
+        if datagram.len() <= FRAGMENT_PAYLOAD {
+            return Ok(vec![datagram]);
+        }
+        let count = datagram.len().div_ceil(FRAGMENT_PAYLOAD);
+        if !self.has_feature(FEATURE_FRAGMENT) || count > MAX_FRAGMENTS {
+            self.dropped += 1;
+            return Err(SendError::TooLarge(datagram.len()));
+        }
+        let id = self.next_fragment_id;
+        self.next_fragment_id = self.next_fragment_id.wrapping_add(1);
+        Ok(datagram
+            .chunks(FRAGMENT_PAYLOAD)
+            .enumerate()
+            .map(|(index, payload)| {
+                bincode::serialize(&Packet::Channel(ChannelRoute::Fragment(FragmentData {
+                    id,
+                    index: index as u16,
+                    count: count as u16,
+                    payload: payload.to_vec(),
+                })))
+                .unwrap()
+            })
+            .collect())
+    }
+
+    /// 收集分片, 全部到齐后返回原始数据报
+    fn reassemble(&mut self, fragment_data: FragmentData, now: Instant) -> Option<Vec<u8>> {
+        let count = fragment_data.count as usize;
+        let index = fragment_data.index as usize;
+        if count == 0
+            || count > MAX_FRAGMENTS
+            || index >= count
+            || fragment_data.payload.len() > FRAGMENT_PAYLOAD
+        {
+            self.dropped += 1;
+            return None;
+        }
+        if !self.reassembly.contains_key(&fragment_data.id)
+            && self.reassembly.len() >= MAX_REASSEMBLY
+        {
+            self.dropped += 1;
+            return None;
+        }
+        let reassembly = self
+            .reassembly
+            .entry(fragment_data.id)
+            .or_insert_with(|| Reassembly {
+                received: 0,
+                fragments: vec![None; count],
+                created_at: now,
+            });
+        // 同一消息编号的分片总数不一致, 丢弃整条消息
+        if reassembly.fragments.len() != count {
+            self.reassembly.remove(&fragment_data.id);
+            self.dropped += 1;
+            return None;
+        }
+        if reassembly.fragments[index].is_none() {
+            reassembly.fragments[index] = Some(fragment_data.payload);
+            reassembly.received += 1;
+        }
+        if reassembly.received < count {
+            return None;
+        }
+        self.reassembly.remove(&fragment_data.id).map(|reassembly| {
+            reassembly
+                .fragments
+                .into_iter()
+                .flatten()
+                .flatten()
+                .collect()
+        })
+    }
+
+    /// 解析收到的数据报, 返回按序交付的数据包与需要回复的确认数据报
+    pub fn receive(&mut self, datagram: &[u8], now: Instant) -> (Vec<Packet>, Vec<Vec<u8>>) {
+        let mut packets = Vec::new();
+        let mut replies = Vec::new();
+        match bincode::deserialize::<Packet>(datagram) {
+            Ok(Packet::Channel(ChannelRoute::Data(channel_data))) => {
+                // 未知通道的数据既不交付也不确认
+                let channel = match Channel::reliable(channel_data.channel) {
+                    Some(channel) => channel,
+                    None => {
+                        self.dropped += 1;
+                        return (packets, replies);
+                    }
+                };
+                let recv_channel = self.recv_channels.entry(channel).or_default();
+                // 超出接收窗口, 不确认等待重发
+                if channel_data.seq >= recv_channel.next_seq + RECV_WINDOW {
+                    return (packets, replies);
+                }
+                replies.push(
+                    bincode::serialize(&Packet::Channel(ChannelRoute::Ack(AckData {
+                        channel: channel_data.channel,
+                        seq: channel_data.seq,
+                    })))
+                    .unwrap(),
+                );
+                // 重复数据包只回复确认
+                if channel_data.seq < recv_channel.next_seq {
+                    return (packets, replies);
+                }
+                recv_channel
+                    .buffer
+                    .insert(channel_data.seq, channel_data.payload);
+                // 按序交付, 空负载是发送端放弃的消息, 只推进序号
+                while let Some(payload) = recv_channel.buffer.remove(&recv_channel.next_seq) {
+                    if payload.is_empty() {
+                        recv_channel.next_seq += 1;
+                        continue;
+                    }
+                    if let Ok(packet) = bincode::deserialize::<Packet>(&payload) {
+                        packets.push(packet);
+                    }
+                    recv_channel.next_seq += 1;
+                }
+            }
+            Ok(Packet::Channel(ChannelRoute::Fragment(fragment_data))) => {
+                if let Some(datagram) = self.reassemble(fragment_data, now) {
+                    return self.receive(&datagram, now);
+                }
+            }
+            Ok(Packet::Channel(ChannelRoute::Ack(ack_data))) => {
+                if let Some(send_channel) = Channel::reliable(ack_data.channel)
+                    .and_then(|channel| self.send_channels.get_mut(&channel))
+                {
+                    send_channel.pending.remove(&ack_data.seq);
+                }
+            }
+            Ok(packet) => packets.push(packet),
+            Err(_) => {}
+        }
+        (packets, replies)
+    }
+
+    /// 返回超时需要重发的数据报, 同时清理超时未完成的分片重组
+    pub fn resend(&mut self, now: Instant) -> Vec<Vec<u8>> {
+        let reassembly_len = self.reassembly.len();
+        self.reassembly
+            .retain(|_, reassembly| now.duration_since(reassembly.created_at) < FRAGMENT_TIMEOUT);
+        self.dropped += (reassembly_len - self.reassembly.len()) as u64;
+
+        let mut datagrams = Vec::new();
+        for (channel, send_channel) in self.send_channels.iter_mut() {
+            let mut give_up = Vec::new();
+            for (seq, pending) in send_channel.pending.iter_mut() {
+                if now.duration_since(pending.sent_at) < RESEND_INTERVAL {
+                    continue;
+                }
+                if pending.resend >= MAX_RESEND && !pending.skip {
+                    give_up.push(*seq);
+                    continue;
+                }
+                pending.resend = pending.resend.saturating_add(1);
+                pending.sent_at = now;
+                datagrams.extend(pending.datagrams.iter().cloned());
+            }
+            // 放弃的消息改为发送空负载的占位数据, 否则接收端会一直等待该序号
+            for seq in give_up {
+                let datagram =
+                    bincode::serialize(&Packet::Channel(ChannelRoute::Data(ChannelData {
+                        channel: *channel as u8,
+                        seq,
+                        payload: Vec::new(),
+                    })))
+                    .unwrap();
+                datagrams.push(datagram.clone());
+                send_channel.pending.insert(
+                    seq,
+                    Pending {
+                        datagrams: vec![datagram],
+                        sent_at: now,
+                        resend: 0,
+                        skip: true,
+                    },
+                );
+                self.lost += 1;
+            }
+        }
+        datagrams
+    }
+
+    /// 等待确认的数据包数量
+    pub fn pending_len(&self) -> usize {
+        self.send_channels
+            .values()
+            .map(|send_channel| send_channel.pending.len())
+            .sum()
+    }
+}
+
+#[cfg(test)]
+fn control_packet(action: u8) -> Packet {
+    use crate::data::control_data::ControlData;
+    Packet::Game(GameRoute::Control(ControlData {
+        uid: 0,
+        direction: (1., 0.),
+        action,
+        seq: 0,
+    }))
+}
+
+#[cfg(test)]
+fn action_of(packet: &Packet) -> u8 {
+    match packet {
+        Packet::Game(GameRoute::Control(control_data)) => control_data.action,
+        _ => u8::MAX,
+    }
+}
+
+#[test]
+fn test_reliable_in_order() {
+    let now = Instant::now();
+    let mut client = Connection::new(FEATURE_RELIABLE, 0);
+    let mut server = Connection::new(FEATURE_RELIABLE, 0);
+
+    let d0 = client.send(&control_packet(0), now).unwrap().remove(0);
+    let d1 = client.send(&control_packet(1), now).unwrap().remove(0);
+    let d2 = client.send(&control_packet(2), now).unwrap().remove(0);
+    assert_eq!(client.pending_len(), 3);
+
+    // 乱序到达, 按序交付
+    let (packets, acks2) = server.receive(&d2, now);
+    assert!(packets.is_empty());
+    let (packets, acks1) = server.receive(&d1, now);
+    assert!(packets.is_empty());
+    let (packets, acks0) = server.receive(&d0, now);
+    assert_eq!(
+        packets.iter().map(action_of).collect::<Vec<u8>>(),
+        vec![0, 1, 2]
+    );
+
+    // 重复数据包不再交付但仍然确认
+    let (packets, acks) = server.receive(&d1, now);
+    assert!(packets.is_empty());
+    assert_eq!(acks.len(), 1);
+
+    for ack in acks0.iter().chain(acks1.iter()).chain(acks2.iter()) {
+        client.receive(ack, now);
+    }
+    assert_eq!(client.pending_len(), 0);
+}
+
+#[test]
+fn test_reliable_resend() {
+    let now = Instant::now();
+    let mut client = Connection::new(FEATURE_RELIABLE, 0);
+    let mut server = Connection::new(FEATURE_RELIABLE, 0);
+
+    // 第一次发送丢失
+    let _lost = client.send(&control_packet(1), now).unwrap();
+    assert!(client.resend(now).is_empty());
+    let datagrams = client.resend(now + RESEND_INTERVAL);
+    assert_eq!(datagrams.len(), 1);
+
+    let (packets, acks) = server.receive(&datagrams[0], now);
+    assert_eq!(packets.len(), 1);
+    client.receive(&acks[0], now);
+    assert!(client.resend(now + RESEND_INTERVAL * 2).is_empty());
+
+    // 超过最大重发次数后放弃, 改为重发占位数据
+    let _lost = client.send(&control_packet(2), now).unwrap();
+    for i in 1..=MAX_RESEND + 1 {
+        client.resend(now + RESEND_INTERVAL * i);
+    }
+    assert_eq!(client.pending_len(), 1);
+    assert_eq!(client.lost, 1);
+}
+
+#[test]
+fn test_reliable_give_up() {
+    let now = Instant::now();
+    let mut client = Connection::new(FEATURE_RELIABLE, 0);
+    let mut server = Connection::new(FEATURE_RELIABLE, 0);
+
+    // 第一条消息始终丢失, 后续消息等待它按序交付
+    let _lost = client.send(&control_packet(0), now).unwrap();
+    let d1 = client.send(&control_packet(1), now).unwrap().remove(0);
+    let (packets, acks) = server.receive(&d1, now);
+    assert!(packets.is_empty());
+    client.receive(&acks[0], now);
+
+    let mut placeholder = Vec::new();
+    for i in 1..=MAX_RESEND + 1 {
+        placeholder = client.resend(now + RESEND_INTERVAL * i);
+    }
+    assert_eq!(client.lost, 1);
+    assert_eq!(placeholder.len(), 1);
+
+    // 占位数据到达后跳过丢失的消息, 继续交付后续消息
+    let (packets, acks) = server.receive(&placeholder[0], now);
+    assert_eq!(packets.iter().map(action_of).collect::<Vec<u8>>(), vec![1]);
+    // 占位数据不会被放弃, 确认后清除
+    let later = now + RESEND_INTERVAL * (MAX_RESEND * 3);
+    assert_eq!(client.resend(later).len(), 1);
+    client.receive(&acks[0], later);
+    assert_eq!(client.pending_len(), 0);
+
+    let d2 = client.send(&control_packet(2), later).unwrap().remove(0);
+    let (packets, _) = server.receive(&d2, later);
+    assert_eq!(packets.len(), 1);
+}
+
+#[test]
+fn test_unreliable_passthrough() {
+    use crate::data::update_data::UpdateData;
+    let now = Instant::now();
+    let mut client = Connection::new(FEATURE_RELIABLE, 0);
+    let mut server = Connection::new(0, 0);
+
+    // 状态同步走不可靠通道
+    let packet = Packet::Game(GameRoute::Update(UpdateData {
+        frame: 1,
+        states: Vec::new(),
+        input: Default::default(),
+    }));
+    let datagrams = server.send(&packet, now).unwrap();
+    assert_eq!(datagrams, vec![bincode::serialize(&packet).unwrap()]);
+    let (packets, acks) = client.receive(&datagrams[0], now);
+    assert_eq!(packets.len(), 1);
+    assert!(acks.is_empty());
+
+    // 对端不支持可靠通道时原样发送
+    let datagrams = server.send(&control_packet(1), now).unwrap();
+    assert_eq!(
+        datagrams,
+        vec![bincode::serialize(&control_packet(1)).unwrap()]
+    );
+    assert_eq!(server.pending_len(), 0);
+
+    // 对端不支持分片时超过单个分片负载的数据包被丢弃
+    assert!(server.send(&tile_map_packet(100), now).is_err());
+    assert!(encode(&tile_map_packet(100)).is_err());
+    assert_eq!(
+        encode(&control_packet(1)),
+        Ok(bincode::serialize(&control_packet(1)).unwrap())
+    );
+}
+
+#[test]
+fn test_unknown_channel() {
+    let now = Instant::now();
+    let mut server = Connection::new(FEATURE_RELIABLE, 0);
+    let payload = bincode::serialize(&control_packet(1)).unwrap();
+
+    // 未知通道和不可靠通道的可靠数据直接丢弃, 不回复确认
+    for channel in [0u8, 4, u8::MAX].iter() {
+        let datagram = bincode::serialize(&Packet::Channel(ChannelRoute::Data(ChannelData {
+            channel: *channel,
+            seq: 0,
+            payload: payload.clone(),
+        })))
+        .unwrap();
+        let (packets, acks) = server.receive(&datagram, now);
+        assert!(packets.is_empty());
+        assert!(acks.is_empty());
+    }
+    assert_eq!(server.dropped, 3);
+    assert_eq!(Channel::try_from(3), Ok(Channel::Game));
+    assert_eq!(Channel::try_from(4), Err(4));
+}
+
+#[cfg(test)]
+fn tile_map_packet(len: usize) -> Packet {
+    use crate::data::tile_map_data::{TileCollider, TileMapData, TileState};
+    Packet::Game(GameRoute::TileMap(TileMapData {
+        map_id: 0,
+        tiles: (0..len as i32)
+            .map(|i| TileState {
+                point: (i, i, 1),
+                filename: "0-tileset_30.png".to_string(),
+                collider: TileCollider::None,
+            })
+            .collect(),
+    }))
+}
+
+#[test]
+fn test_fragment() {
+    let now = Instant::now();
+    let features = FEATURE_RELIABLE | FEATURE_FRAGMENT;
+    let mut client = Connection::new(features, 0);
+    let mut server = Connection::new(features, 0);
+
+    let packet = tile_map_packet(500);
+    let mut datagrams = server.send(&packet, now).unwrap();
+    assert!(datagrams.len() > 1);
+    assert!(datagrams
+        .iter()
+        .all(|datagram| datagram.len() <= FRAGMENT_PAYLOAD + 32));
+
+    // 分片乱序到达, 全部到齐后交付
+    datagrams.reverse();
+    let last = datagrams.pop().unwrap();
+    for datagram in &datagrams {
+        let (packets, acks) = client.receive(datagram, now);
+        assert!(packets.is_empty());
+        assert!(acks.is_empty());
+    }
+    let (packets, acks) = client.receive(&last, now);
+    assert_eq!(packets.len(), 1);
+    assert_eq!(acks.len(), 1);
+    match &packets[0] {
+        Packet::Game(GameRoute::TileMap(tile_map_data)) => {
+            assert_eq!(tile_map_data.tiles.len(), 500)
+        }
+        _ => panic!("重组结果错误"),
+    }
+
+    // 可靠通道重发时重发全部分片
+    let resend = server.resend(now + RESEND_INTERVAL);
+    assert_eq!(resend.len(), datagrams.len() + 1);
+    server.receive(&acks[0], now);
+    assert_eq!(server.pending_len(), 0);
+}
+
+#[test]
+fn test_fragment_limit() {
+    let now = Instant::now();
+    let mut client = Connection::new(FEATURE_FRAGMENT, 0);
+    let mut server = Connection::new(FEATURE_FRAGMENT, 0);
+
+    // 超过最大分片数的消息直接丢弃
+    assert!(matches!(
+        server.send(&tile_map_packet(5000), now),
+        Err(SendError::TooLarge(_))
+    ));
+    assert_eq!(server.dropped, 1);
+
+    // 不完整的消息超时后丢弃
+    let datagrams = server.send(&tile_map_packet(100), now).unwrap();
+    assert!(datagrams.len() > 1);
+    client.receive(&datagrams[0], now);
+    client.resend(now + FRAGMENT_TIMEOUT);
+    assert_eq!(client.dropped, 1);
+    let (packets, _) = client.receive(&datagrams[1], now + FRAGMENT_TIMEOUT);
+    assert!(packets.is_empty());
+
+    // 非法分片
+    let fragment = bincode::serialize(&Packet::Channel(ChannelRoute::Fragment(FragmentData {
+        id: 99,
+        index: 3,
+        count: 2,
+        payload: Vec::new(),
+    })))
+    .unwrap();
+    let (packets, _) = client.receive(&fragment, now);
+    assert!(packets.is_empty());
+    assert_eq!(client.dropped, 2);
+}
+
+#[test]
+fn test_too_large_keeps_seq() {
+    let now = Instant::now();
+    let features = FEATURE_RELIABLE | FEATURE_FRAGMENT;
+    let mut client = Connection::new(features, 0);
+    let mut server = Connection::new(features, 0);
+
+    // 过大的可靠消息不占用序号, 后续消息不会等待它
+    assert!(server.send(&tile_map_packet(5000), now).is_err());
+    assert_eq!(server.pending_len(), 0);
+    let datagrams = server.send(&tile_map_packet(1), now).unwrap();
+    let (packets, _) = client.receive(&datagrams[0], now);
+    assert_eq!(packets.len(), 1);
+}
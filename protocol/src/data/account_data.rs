@@ -1,4 +1,11 @@
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+// 用户名长度限制
+pub const USERNAME_MIN_LEN: usize = 3;
+pub const USERNAME_MAX_LEN: usize = 16;
+// 密码最短长度(客户端校验)
+pub const PASSWORD_MIN_LEN: usize = 6;
 
 // 状态同步数据
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -9,3 +16,84 @@ pub struct AccountData {
     pub group: u32,
 }
 
+// 注册/登录凭证
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CredentialData {
+    pub username: String,
+    // SHA-256(用户名 + 密码), 明文密码不离开客户端
+    pub password: [u8; 32],
+    pub group: u32,
+}
+
+impl CredentialData {
+    pub fn new(username: &str, password: &str, group: u32) -> Self {
+        let mut hasher = Sha256::new();
+        hasher.update(username.as_bytes());
+        hasher.update(b":");
+        hasher.update(password.as_bytes());
+        let mut digest = [0u8; 32];
+        digest.copy_from_slice(&hasher.finalize());
+        CredentialData {
+            username: username.to_string(),
+            password: digest,
+            group,
+        }
+    }
+}
+
+/// 用户名只允许字母, 数字和下划线
+pub fn is_valid_username(username: &str) -> bool {
+    (USERNAME_MIN_LEN..=USERNAME_MAX_LEN).contains(&username.len())
+        && username
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// 注册/登录失败原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AccountFailure {
+    // 用户名格式错误
+    InvalidUsername,
+    // 用户名已被注册
+    UsernameTaken,
+    // 账号不存在
+    UnknownUser,
+    // 密码错误
+    WrongPassword,
+    // 服务器存储异常
+    ServerError,
+    // 房间不存在
+    UnknownRoom,
+    // 登录或注册过于频繁
+    TooManyAttempts,
+}
+
+impl AccountFailure {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            AccountFailure::InvalidUsername => "用户名需为3-16位字母, 数字或下划线",
+            AccountFailure::UsernameTaken => "用户名已被注册",
+            AccountFailure::UnknownUser => "账号不存在",
+            AccountFailure::WrongPassword => "密码错误",
+            AccountFailure::ServerError => "服务器异常, 请稍后重试",
+            AccountFailure::UnknownRoom => "房间不存在",
+            AccountFailure::TooManyAttempts => "尝试次数过多, 请稍后重试",
+        }
+    }
+}
+
+#[test]
+fn test_credential() {
+    let a = CredentialData::new("player_1", "secret", 0);
+    let b = CredentialData::new("player_1", "secret", 0);
+    assert_eq!(a.password, b.password);
+    // 不同用户相同密码的摘要不同
+    let c = CredentialData::new("player_2", "secret", 0);
+    assert_ne!(a.password, c.password);
+
+    assert!(is_valid_username("player_1"));
+    assert!(!is_valid_username("ab"));
+    assert!(!is_valid_username("玩家一号"));
+    assert!(!is_valid_username("a b c"));
+    assert!(!is_valid_username(&"a".repeat(USERNAME_MAX_LEN + 1)));
+}
@@ -0,0 +1,34 @@
+use serde::{Deserialize, Serialize};
+
+// 可靠通道数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelData {
+    // 1b[0] 通道
+    pub channel: u8,
+    // 4b[1..4] 通道内序号
+    pub seq: u32,
+    // 序列化后的Packet
+    pub payload: Vec<u8>,
+}
+
+// 可靠通道确认
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct AckData {
+    // 1b[0] 通道
+    pub channel: u8,
+    // 4b[1..4] 已收到的序号
+    pub seq: u32,
+}
+
+// 分片数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FragmentData {
+    // 4b[0..3] 消息编号
+    pub id: u32,
+    // 2b[4..5] 分片序号
+    pub index: u16,
+    // 2b[6..7] 分片总数
+    pub count: u16,
+    // 分片负载
+    pub payload: Vec<u8>,
+}
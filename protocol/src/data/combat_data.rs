@@ -0,0 +1,32 @@
+use serde::{Deserialize, Serialize};
+
+use super::skill_data::SkillType;
+
+/// 伤害来源
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum DamageSource {
+    Skill(SkillType),
+    Trap,
+}
+
+// 击杀通知, 发送给房间内所有玩家
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct KillData {
+    // 死亡的玩家
+    pub victim: u32,
+    // 击杀者, 陷阱或自己造成的死亡为None
+    pub attacker: Option<u32>,
+    pub source: DamageSource,
+    // 复活倒计时(毫秒)
+    pub respawn_in: u32,
+}
+
+// 复活通知, 发送给房间内所有玩家
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct RespawnData {
+    pub uid: u32,
+    // 复活点
+    pub position: (f32, f32),
+    // 复活后的无敌时长(毫秒)
+    pub invulnerable: u32,
+}
@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
 
+/// 行走速度
+pub const WALK_SPEED: f32 = 100.;
+/// 奔跑速度
+pub const RUN_SPEED: f32 = 150.;
+
 // 状态同步数据
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
-// 13b
+// 17b
 pub struct ControlData {
     // 4b[0..3]
     pub uid: u32,
@@ -10,4 +15,50 @@ pub struct ControlData {
     pub direction: (f32, f32),
     // 1b[12] 动作 0停止, 1移动, 2跳跃
     pub action: u8,
+    // 4b[13..16] 输入序号, 客户端每次输入递增, 服务器在快照中回传最后处理的序号
+    pub seq: u32,
+}
+
+impl ControlData {
+    /// 输入对应的速度, 服务器与客户端预测使用同一算法
+    pub fn velocity(&self) -> (f32, f32) {
+        let (x, y) = self.direction;
+        let length = (x * x + y * y).sqrt();
+        let speed = match self.action {
+            1 => WALK_SPEED,
+            2 => RUN_SPEED,
+            _ => 0.,
+        };
+        if length == 0. || speed == 0. {
+            (0., 0.)
+        } else {
+            (x / length * speed, y / length * speed)
+        }
+    }
+}
+
+/// 服务器最后处理的玩家输入, 随快照下发用于客户端校正预测
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputAckData {
+    // 4b 最后处理的输入序号, 0为尚未处理任何输入
+    pub seq: u32,
+    // 4b 处理该输入后经过的模拟时间(毫秒)
+    pub elapsed: u32,
+}
+
+#[test]
+fn test_velocity() {
+    let control = |direction, action| ControlData {
+        uid: 0,
+        direction,
+        action,
+        seq: 0,
+    };
+    assert_eq!(control((0., 5.), 1).velocity(), (0., WALK_SPEED));
+    // 斜向移动速度与直线相同
+    let (x, y) = control((3., 4.), 1).velocity();
+    assert!(((x * x + y * y).sqrt() - WALK_SPEED).abs() < 0.001);
+    assert_eq!(control((0., -2.), 2).velocity(), (0., -RUN_SPEED));
+    assert_eq!(control((1., 0.), 0).velocity(), (0., 0.));
+    assert_eq!(control((0., 0.), 1).velocity(), (0., 0.));
 }
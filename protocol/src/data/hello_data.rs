@@ -0,0 +1,181 @@
+use serde::{Deserialize, Serialize};
+
+/// 当前协议版本, 修改Packet/路由/数据结构的序列化格式时必须递增
+pub const PROTOCOL_VERSION: u32 = 14;
+/// 服务器可兼容的最低协议版本, 低于14的客户端无法解析登录频率限制的失败原因
+pub const MIN_PROTOCOL_VERSION: u32 = 14;
+/// 功能: 可靠有序通道
+pub const FEATURE_RELIABLE: u32 = 1 << 0;
+/// 功能: 数据包分片与重组
+pub const FEATURE_FRAGMENT: u32 = 1 << 1;
+/// 功能: 增量快照
+pub const FEATURE_DELTA: u32 = 1 << 2;
+/// 本端支持的功能标志位(按位或)
+pub const SUPPORTED_FEATURES: u32 = FEATURE_RELIABLE | FEATURE_FRAGMENT | FEATURE_DELTA;
+/// 必需的功能, 不支持分片的客户端无法接收超过单个数据报的世界状态
+pub const REQUIRED_FEATURES: u32 = FEATURE_FRAGMENT;
+
+// 握手数据
+// 固定位于Packet::Heartbeat(HeartbeatRoute::In(..))[0,0], 保证任意版本都能解析出version
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub struct HelloData {
+    // 4b[0..3] 协议版本
+    pub version: u32,
+    // 4b[4..7] 功能标志位
+    pub features: u32,
+    // 握手结果
+    pub result: HelloResult,
+    // 连接标识, 客户端每次启动随机生成, 用于区分同一地址上的新旧连接
+    // 新字段只能追加在末尾, 保证旧版本能解析出前面的字段
+    pub nonce: u32,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum HelloResult {
+    // 客户端请求
+    Request,
+    // 版本与功能完全一致
+    Accept,
+    // 版本兼容, 仅启用双方都支持的功能
+    Downgrade,
+    // 版本不兼容或缺少必需功能, 需要更新客户端
+    Reject,
+}
+
+impl HelloData {
+    pub fn request(nonce: u32) -> Self {
+        HelloData {
+            version: PROTOCOL_VERSION,
+            features: SUPPORTED_FEATURES,
+            result: HelloResult::Request,
+            nonce,
+        }
+    }
+
+    pub fn reject() -> Self {
+        HelloData {
+            version: PROTOCOL_VERSION,
+            features: 0,
+            result: HelloResult::Reject,
+            nonce: 0,
+        }
+    }
+
+    /// 从无法解析的握手包中读取协议版本
+    pub fn peek_version(datagram: &[u8]) -> Option<u32> {
+        if datagram.len() >= 12 && datagram[0..8] == [0u8; 8] {
+            let mut version = [0u8; 4];
+            version.copy_from_slice(&datagram[8..12]);
+            return Some(u32::from_le_bytes(version));
+        }
+        None
+    }
+
+    /// 服务器根据客户端握手请求协商版本与功能
+    pub fn negotiate(&self) -> HelloData {
+        if self.version < MIN_PROTOCOL_VERSION
+            || self.version > PROTOCOL_VERSION
+            || !self.has_feature(REQUIRED_FEATURES)
+        {
+            return HelloData::reject();
+        }
+        let features = self.features & SUPPORTED_FEATURES;
+        let result = if self.version == PROTOCOL_VERSION && features == self.features {
+            HelloResult::Accept
+        } else {
+            HelloResult::Downgrade
+        };
+        HelloData {
+            version: self.version,
+            features,
+            result,
+            nonce: self.nonce,
+        }
+    }
+
+    pub fn is_accepted(&self) -> bool {
+        self.result == HelloResult::Accept || self.result == HelloResult::Downgrade
+    }
+
+    pub fn has_feature(&self, feature: u32) -> bool {
+        self.features & feature == feature
+    }
+}
+
+#[test]
+fn test_negotiate() {
+    let hello = HelloData::request(7).negotiate();
+    assert_eq!(hello.result, HelloResult::Accept);
+    assert_eq!(hello.features, SUPPORTED_FEATURES);
+    assert_eq!(hello.nonce, 7);
+
+    let hello = HelloData {
+        version: PROTOCOL_VERSION + 1,
+        features: SUPPORTED_FEATURES,
+        result: HelloResult::Request,
+        nonce: 0,
+    }
+    .negotiate();
+    assert_eq!(hello.result, HelloResult::Reject);
+
+    let hello = HelloData {
+        version: MIN_PROTOCOL_VERSION - 1,
+        features: 0,
+        result: HelloResult::Request,
+        nonce: 0,
+    }
+    .negotiate();
+    assert_eq!(hello.result, HelloResult::Reject);
+
+    // 最低兼容版本的客户端只启用其支持的功能
+    let hello = HelloData {
+        version: MIN_PROTOCOL_VERSION,
+        features: FEATURE_RELIABLE | FEATURE_FRAGMENT,
+        result: HelloResult::Request,
+        nonce: 0,
+    }
+    .negotiate();
+    assert!(hello.is_accepted());
+    assert_eq!(hello.version, MIN_PROTOCOL_VERSION);
+    assert_eq!(hello.features, FEATURE_RELIABLE | FEATURE_FRAGMENT);
+
+    // 不支持分片的客户端被拒绝
+    let hello = HelloData {
+        version: PROTOCOL_VERSION,
+        features: FEATURE_RELIABLE | FEATURE_DELTA,
+        result: HelloResult::Request,
+        nonce: 0,
+    }
+    .negotiate();
+    assert_eq!(hello.result, HelloResult::Reject);
+
+    // 未知功能被剔除
+    let hello = HelloData {
+        version: PROTOCOL_VERSION,
+        features: SUPPORTED_FEATURES | 1 << 31,
+        result: HelloResult::Request,
+        nonce: 0,
+    }
+    .negotiate();
+    assert_eq!(hello.result, HelloResult::Downgrade);
+    assert_eq!(hello.features, SUPPORTED_FEATURES);
+}
+
+#[test]
+fn test_hello_prefix() {
+    use crate::{packet::Packet, route::HeartbeatRoute};
+    // 握手包前缀必须稳定: Heartbeat(0) + In(0) + version
+    let packet = bincode::serialize(&Packet::Heartbeat(HeartbeatRoute::In(HelloData::request(
+        0,
+    ))))
+    .unwrap();
+    assert_eq!(&packet[0..8], &[0u8; 8]);
+    assert_eq!(&packet[8..12], &PROTOCOL_VERSION.to_le_bytes());
+    assert_eq!(HelloData::peek_version(&packet), Some(PROTOCOL_VERSION));
+    // 旧版本握手包(无nonce)只能读取版本
+    assert!(bincode::deserialize::<Packet>(&packet[..packet.len() - 4]).is_err());
+    assert_eq!(
+        HelloData::peek_version(&packet[..packet.len() - 4]),
+        Some(PROTOCOL_VERSION)
+    );
+}
@@ -0,0 +1,14 @@
+use serde::{Deserialize, Serialize};
+
+use super::update_data::EntityType;
+
+// 视野变化通知
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterestData {
+    // 16b 物理帧号
+    pub frame: u128,
+    // 进入视野的实体
+    pub entered: Vec<(EntityType, u64)>,
+    // 离开视野(或已被移除)的实体
+    pub left: Vec<(EntityType, u64)>,
+}
@@ -4,3 +4,9 @@ pub mod control_data;
 pub mod tile_map_data;
 pub mod player_data;
 pub mod skill_data;
+pub mod hello_data;
+pub mod channel_data;
+pub mod snapshot_data;
+pub mod interest_data;
+pub mod session_data;
+pub mod combat_data;
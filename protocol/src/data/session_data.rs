@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+// 登录成功后服务器下发的会话令牌
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SessionData {
+    pub uid: u32,
+    pub token: u128,
+}
+
+// 携带会话校验的数据报
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedData {
+    pub uid: u32,
+    // 发送计数, 用于防重放
+    pub counter: u64,
+    // HMAC-SHA256(令牌, uid + counter + payload) 前16字节
+    pub mac: [u8; 16],
+    // 原始数据报
+    pub payload: Vec<u8>,
+}
@@ -1,3 +1,5 @@
+use std::convert::TryFrom;
+
 use serde::{Deserialize, Serialize};
 
 // 状态同步数据
@@ -7,19 +9,71 @@ pub struct SkillData {
     pub uid: u32,
     pub direction: (f32, f32),
     pub skill_type: SkillType,
+    // 贴图由服务器按技能类型决定, 客户端填写的值被忽略
     pub texture: (u32, u8, u8),
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum SkillType {
+    // 射击: 飞行的弹道
     Shot = 0,
+    // 斩击: 前方扇形范围的近战攻击
+    MeleeArc = 1,
+    // 爆破: 前方一点的圆形范围伤害
+    AreaBlast = 2,
+    // 冲刺: 向指定方向快速位移
+    Dash = 3,
+}
+
+impl SkillType {
+    pub const ALL: [SkillType; 4] = [
+        SkillType::Shot,
+        SkillType::MeleeArc,
+        SkillType::AreaBlast,
+        SkillType::Dash,
+    ];
+}
+
+impl TryFrom<u8> for SkillType {
+    type Error = u8;
+
+    fn try_from(num: u8) -> Result<Self, Self::Error> {
+        SkillType::ALL
+            .iter()
+            .find(|skill_type| **skill_type as u8 == num)
+            .cloned()
+            .ok_or(num)
+    }
+}
+
+/// 施法结果
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+pub enum CastResult {
+    // 释放成功, 开始冷却
+    Cast,
+    // 冷却中
+    Cooldown,
+    // 魔力不足
+    NotEnoughMp,
+}
+
+// 技能冷却状态, 每次施法后下发给施法者, 用于界面显示
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct CooldownData {
+    pub skill_type: SkillType,
+    pub result: CastResult,
+    // 剩余冷却(毫秒)
+    pub remaining: u32,
+    // 冷却总时长(毫秒)
+    pub cooldown: u32,
+    // 施法后的魔力值
+    pub mp: u32,
 }
 
-impl From<u8> for SkillType {
-    fn from(num: u8) -> Self {
-        match num {
-            0 => SkillType::Shot,
-            _ => SkillType::Shot,
-        }
+#[test]
+fn test_skill_type_from_u8() {
+    for skill_type in SkillType::ALL.iter() {
+        assert_eq!(SkillType::try_from(*skill_type as u8), Ok(*skill_type));
     }
+    assert_eq!(SkillType::try_from(200), Err(200));
 }
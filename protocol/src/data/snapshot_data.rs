@@ -0,0 +1,37 @@
+use serde::{Deserialize, Serialize};
+
+use super::{control_data::InputAckData, update_data::EntityType};
+
+// 增量快照数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotData {
+    // 4b[0..3] 快照序号(每个客户端独立递增)
+    pub seq: u32,
+    // 基准快照序号, None为完整快照
+    pub baseline: Option<u32>,
+    // 16b 物理帧号
+    pub frame: u128,
+    // 相对基准快照有变化的实体
+    pub entities: Vec<EntityDelta>,
+    // 相对基准快照被移除的实体
+    pub removed: Vec<(EntityType, u64)>,
+    // 接收方玩家最后处理的输入
+    pub input: InputAckData,
+}
+
+// 实体变化字段, None表示与基准快照相同
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct EntityDelta {
+    pub id: u64,
+    pub entity_type: EntityType,
+    // 量化坐标
+    pub translation: Option<(i32, i32)>,
+    // 量化角度
+    pub rotation: Option<u16>,
+    // 量化线速度
+    pub linvel: Option<(i16, i16)>,
+    // 量化角速度
+    pub angvel: Option<i16>,
+    pub texture: Option<(u32, u8, u8)>,
+    pub animate: Option<u8>,
+}
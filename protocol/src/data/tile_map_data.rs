@@ -100,4 +100,6 @@ pub struct TileMap {
     pub chunk_size: UVec3,
     pub map_size: UVec3,
     pub slot_map: HashMap<IVec3, Slot>,
+    // 坍缩使用的随机种子, 相同种子和相同已生成地块得到相同地图
+    pub seed: u64,
 }
@@ -1,5 +1,7 @@
 use serde::{Deserialize, Serialize};
 
+use super::control_data::InputAckData;
+
 // 状态同步数据
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdateData {
@@ -7,6 +9,8 @@ pub struct UpdateData {
     pub frame: u128,
     // x40b[16..]
     pub states: Vec<EntityState>,
+    // 8b 接收方玩家最后处理的输入
+    pub input: InputAckData,
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
@@ -29,45 +33,7 @@ pub struct EntityState {
     pub animate: u8,
 }
 
-impl EntityState {
-    pub fn make_up_data(&mut self, user_data: u128) {
-        let data: [u8; 16] = user_data.to_le_bytes();
-
-        let ptr: *const u8 = data[0..7].as_ptr();
-        let ptr: *const u64 = ptr as *const u64;
-        let id = unsafe { *ptr };
-
-        let ptr: *const u8 = data[8..11].as_ptr();
-        let ptr: *const u32 = ptr as *const u32;
-        let texture0 = unsafe { *ptr };
-
-        let texture1 = data[12];
-
-        let texture2 = data[13];
-
-        let entity_type = data[14];
-        let animate = data[15];
-
-        self.id = id;
-        self.texture = (texture0, texture1, texture2);
-        self.entity_type = EntityType::from(entity_type);
-        self.animate = animate;
-    }
-    pub fn get_data(&self) -> u128 {
-        let mut data = self.id.to_le_bytes().to_vec();
-        data.append(&mut self.texture.0.to_le_bytes().to_vec());
-        data.append(&mut self.texture.1.to_le_bytes().to_vec());
-        data.append(&mut self.texture.2.to_le_bytes().to_vec());
-        data.append(&mut (self.entity_type as u8).to_le_bytes().to_vec());
-        data.append(&mut self.animate.to_le_bytes().to_vec());
-        data.append(&mut [0u8; 2].to_vec());
-        let ptr: *const u8 = data[0..15].as_ptr();
-        let ptr: *const u128 = ptr as *const u128;
-        unsafe { *ptr }
-    }
-}
-
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum EntityType {
     Static = 0,
     Moveable = 1,
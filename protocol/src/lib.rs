@@ -3,4 +3,8 @@
 pub mod data;
 pub mod route;
 pub mod packet;
+pub mod channel;
+pub mod snapshot;
+pub mod session;
 
+pub mod account;
@@ -1,4 +1,7 @@
-use crate::route::{AccountRoute, GameRoute, HeartbeatRoute};
+use crate::{
+    data::session_data::SealedData,
+    route::{AccountRoute, ChannelRoute, GameRoute, HeartbeatRoute},
+};
 use serde::{Deserialize, Serialize};
 // 数据包一级路由[0]
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -6,6 +9,9 @@ pub enum Packet {
     Heartbeat(HeartbeatRoute),
     Account(AccountRoute),
     Game(GameRoute),
+    Channel(ChannelRoute),
+    // 登录后客户端发出的数据报都经过会话校验
+    Session(SealedData),
 }
 
 #[test]
@@ -28,6 +34,7 @@ fn test_bincode() {
         uid: 0,
         direction: (-1., 0.11),
         action: 1,
+        seq: 1,
     }));
     let packet = bincode::serialize(&packet).unwrap();
     println!("{}", packet.len());
@@ -1,8 +1,14 @@
 use crate::data::{
-    account_data::AccountData,
+    account_data::{AccountData, AccountFailure, CredentialData},
+    channel_data::{AckData, ChannelData, FragmentData},
+    combat_data::{KillData, RespawnData},
     control_data::ControlData,
+    hello_data::HelloData,
+    interest_data::InterestData,
     player_data::{PlayerData, PlayerListData},
-    skill_data::SkillData,
+    session_data::SessionData,
+    skill_data::{CooldownData, SkillData},
+    snapshot_data::SnapshotData,
     tile_map_data::{TileMapData, TileState},
     update_data::UpdateData,
 };
@@ -11,16 +17,28 @@ use serde::{Deserialize, Serialize};
 // 心跳包路由
 #[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum HeartbeatRoute {
-    In,
+    // 握手: 协议版本与功能协商, 必须保持为第一个变体
+    In(HelloData),
     Out,
     Keep(u128),
 }
 // 账号中心路由
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AccountRoute {
-    Login(AccountData),
+    // 账号密码登录
+    Login(CredentialData),
     Logout(AccountData),
     GetInfo(AccountData),
+    // 登录成功, 下发会话令牌
+    Session(SessionData),
+    // 注册账号, 成功后直接登录
+    Register(CredentialData),
+    // 登录成功, 玩家进入游戏
+    Enter(AccountData),
+    // 注册/登录失败
+    Failed(AccountFailure),
+    // 切换房间(group)
+    ChangeRoom(AccountData),
 }
 // 游戏路由
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -32,4 +50,25 @@ pub enum GameRoute {
     Player(PlayerData),
     PlayerList(PlayerListData),
     Skill(SkillData),
+    // 增量快照
+    Snapshot(SnapshotData),
+    // 客户端确认已收到的快照序号
+    SnapshotAck(u32),
+    // 实体进入/离开视野
+    Interest(InterestData),
+    // 玩家离线(登出或超时), 立即清除其实体
+    PlayerLeft(u32),
+    // 技能冷却状态
+    Cooldown(CooldownData),
+    // 玩家死亡
+    Kill(KillData),
+    // 玩家复活
+    Respawn(RespawnData),
+}
+// 可靠通道路由
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum ChannelRoute {
+    Data(ChannelData),
+    Ack(AckData),
+    Fragment(FragmentData),
 }
@@ -0,0 +1,162 @@
+use sha2::{Digest, Sha256};
+
+use crate::{data::session_data::SealedData, packet::Packet};
+
+// SHA-256分块长度
+const BLOCK_LEN: usize = 64;
+// 防重放窗口(最近收到的计数)
+pub const REPLAY_WINDOW: u64 = 64;
+
+/// HMAC-SHA256
+pub fn hmac(key: &[u8], message: &[&[u8]]) -> [u8; 32] {
+    let mut block = [0u8; BLOCK_LEN];
+    if key.len() > BLOCK_LEN {
+        block[..32].copy_from_slice(&Sha256::digest(key));
+    } else {
+        block[..key.len()].copy_from_slice(key);
+    }
+    let mut inner = Sha256::new();
+    inner.update(block.iter().map(|b| b ^ 0x36).collect::<Vec<u8>>());
+    for part in message {
+        inner.update(part);
+    }
+    let mut outer = Sha256::new();
+    outer.update(block.iter().map(|b| b ^ 0x5c).collect::<Vec<u8>>());
+    outer.update(inner.finalize());
+    let mut mac = [0u8; 32];
+    mac.copy_from_slice(&outer.finalize());
+    mac
+}
+
+/// 登录会话: 客户端用令牌签名发出的数据报, 服务器校验签名与计数
+#[derive(Debug, Clone)]
+pub struct Session {
+    pub uid: u32,
+    token: u128,
+    // 已发送的最大计数
+    send_counter: u64,
+    // 已接收的最大计数
+    recv_counter: u64,
+    // 最近REPLAY_WINDOW个计数的接收记录, 第n位对应recv_counter - n
+    recv_window: u64,
+}
+
+impl Session {
+    pub fn new(uid: u32, token: u128) -> Self {
+        Session {
+            uid,
+            token,
+            send_counter: 0,
+            recv_counter: 0,
+            recv_window: 0,
+        }
+    }
+
+    pub fn token(&self) -> u128 {
+        self.token
+    }
+
+    fn mac(&self, counter: u64, payload: &[u8]) -> [u8; 16] {
+        let full = hmac(
+            &self.token.to_le_bytes(),
+            &[&self.uid.to_le_bytes(), &counter.to_le_bytes(), payload],
+        );
+        let mut mac = [0u8; 16];
+        mac.copy_from_slice(&full[..16]);
+        mac
+    }
+
+    /// 签名数据报
+    pub fn seal(&mut self, datagram: Vec<u8>) -> Vec<u8> {
+        self.send_counter += 1;
+        let sealed = SealedData {
+            uid: self.uid,
+            counter: self.send_counter,
+            mac: self.mac(self.send_counter, &datagram),
+            payload: datagram,
+        };
+        bincode::serialize(&Packet::Session(sealed)).unwrap()
+    }
+
+    /// 校验签名与计数, 通过后返回原始数据报
+    pub fn open<'a>(&mut self, sealed: &'a SealedData) -> Option<&'a [u8]> {
+        if sealed.uid != self.uid || sealed.counter == 0 {
+            return None;
+        }
+        // 逐字节比较全部字节, 避免按匹配长度泄露耗时
+        let mac = self.mac(sealed.counter, &sealed.payload);
+        let diff = mac
+            .iter()
+            .zip(sealed.mac.iter())
+            .fold(0u8, |diff, (a, b)| diff | (a ^ b));
+        if diff != 0 {
+            return None;
+        }
+        if sealed.counter > self.recv_counter {
+            let shift = sealed.counter - self.recv_counter;
+            self.recv_window = if shift >= REPLAY_WINDOW {
+                0
+            } else {
+                self.recv_window << shift
+            };
+            self.recv_window |= 1;
+            self.recv_counter = sealed.counter;
+        } else {
+            let offset = self.recv_counter - sealed.counter;
+            if offset >= REPLAY_WINDOW || self.recv_window & (1 << offset) != 0 {
+                return None;
+            }
+            self.recv_window |= 1 << offset;
+        }
+        Some(&sealed.payload)
+    }
+}
+
+#[cfg(test)]
+fn unseal(datagram: &[u8]) -> SealedData {
+    match bincode::deserialize(datagram).unwrap() {
+        Packet::Session(sealed) => sealed,
+        _ => panic!("not sealed"),
+    }
+}
+
+#[test]
+fn test_hmac() {
+    // RFC 4231 测试用例2
+    let mac = hmac(b"Jefe", &[b"what do ya want ", b"for nothing?"]);
+    assert_eq!(mac[..8], [0x5b, 0xdc, 0xc1, 0x46, 0xbf, 0x60, 0x75, 0x4e]);
+}
+
+#[test]
+fn test_seal_open() {
+    let mut client = Session::new(7, 0x1234_5678_9abc_def0);
+    let mut server = Session::new(7, 0x1234_5678_9abc_def0);
+
+    let first = unseal(&client.seal(vec![1, 2, 3]));
+    let second = unseal(&client.seal(vec![4, 5, 6]));
+    // 乱序到达可以接受
+    assert_eq!(server.open(&second), Some(&[4u8, 5, 6][..]));
+    assert_eq!(server.open(&first), Some(&[1u8, 2, 3][..]));
+    // 重放被拒绝
+    assert_eq!(server.open(&first), None);
+    assert_eq!(server.open(&second), None);
+
+    // 篡改内容或冒用uid被拒绝
+    let mut tampered = unseal(&client.seal(vec![7]));
+    tampered.payload[0] = 8;
+    assert_eq!(server.open(&tampered), None);
+    let mut other = Session::new(8, 0x1234_5678_9abc_def0);
+    assert_eq!(server.open(&unseal(&other.seal(vec![7]))), None);
+
+    // 错误令牌被拒绝
+    let mut forged = Session::new(7, 1);
+    assert_eq!(server.open(&unseal(&forged.seal(vec![7]))), None);
+
+    // 超出窗口的旧计数被拒绝
+    let old = unseal(&client.seal(vec![9]));
+    for _ in 0..REPLAY_WINDOW {
+        let sealed = unseal(&client.seal(vec![0]));
+        assert!(server.open(&sealed).is_some());
+    }
+    assert_eq!(server.open(&old), None);
+}
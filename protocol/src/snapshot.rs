@@ -0,0 +1,392 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::f32::consts::PI;
+
+use crate::data::{
+    control_data::InputAckData,
+    snapshot_data::{EntityDelta, SnapshotData},
+    update_data::{EntityState, EntityType, UpdateData},
+};
+
+// 坐标量化精度(1/100单位)
+pub const TRANSLATION_SCALE: f32 = 100.0;
+// 线速度量化精度(1/10单位)
+pub const LINVEL_SCALE: f32 = 10.0;
+// 角速度量化精度(1/100弧度)
+pub const ANGVEL_SCALE: f32 = 100.0;
+// 每个对端保留的历史快照数量
+pub const SNAPSHOT_HISTORY: usize = 32;
+
+// 量化后的实体状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizedState {
+    pub translation: (i32, i32),
+    pub rotation: u16,
+    pub linvel: (i16, i16),
+    pub angvel: i16,
+    pub texture: (u32, u8, u8),
+    pub animate: u8,
+}
+
+impl From<&EntityState> for QuantizedState {
+    fn from(state: &EntityState) -> Self {
+        QuantizedState {
+            translation: (
+                (state.translation.0 * TRANSLATION_SCALE).round() as i32,
+                (state.translation.1 * TRANSLATION_SCALE).round() as i32,
+            ),
+            rotation: quantize_angle(state.rotation),
+            linvel: (
+                (state.linvel.0 * LINVEL_SCALE).round() as i16,
+                (state.linvel.1 * LINVEL_SCALE).round() as i16,
+            ),
+            angvel: (state.angvel.0 * ANGVEL_SCALE).round() as i16,
+            texture: state.texture,
+            animate: state.animate,
+        }
+    }
+}
+
+impl QuantizedState {
+    pub fn to_entity_state(&self, id: u64, entity_type: EntityType) -> EntityState {
+        let angvel = self.angvel as f32 / ANGVEL_SCALE;
+        EntityState {
+            id,
+            translation: (
+                self.translation.0 as f32 / TRANSLATION_SCALE,
+                self.translation.1 as f32 / TRANSLATION_SCALE,
+            ),
+            rotation: dequantize_angle(self.rotation),
+            linvel: (
+                self.linvel.0 as f32 / LINVEL_SCALE,
+                self.linvel.1 as f32 / LINVEL_SCALE,
+            ),
+            angvel: (angvel, angvel),
+            texture: self.texture,
+            entity_type,
+            animate: self.animate,
+        }
+    }
+}
+
+// 角度[-PI, PI]映射到u16
+fn quantize_angle(angle: f32) -> u16 {
+    let angle = angle.rem_euclid(2.0 * PI);
+    ((angle / (2.0 * PI)) * 65536.0).round() as u32 as u16
+}
+
+fn dequantize_angle(angle: u16) -> f32 {
+    let angle = angle as f32 / 65536.0 * 2.0 * PI;
+    if angle > PI {
+        angle - 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+// 字段与基准相同时不发送
+fn changed<T: PartialEq + Copy>(old: Option<T>, new: T) -> Option<T> {
+    if old == Some(new) {
+        None
+    } else {
+        Some(new)
+    }
+}
+
+// 一帧的完整量化快照
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Snapshot {
+    pub frame: u128,
+    // (实体类型, id) -> 状态
+    pub entities: BTreeMap<(u8, u64), QuantizedState>,
+    pub input: InputAckData,
+}
+
+impl From<&UpdateData> for Snapshot {
+    fn from(update_data: &UpdateData) -> Self {
+        let entities = update_data
+            .states
+            .iter()
+            .map(|state| {
+                (
+                    (state.entity_type as u8, state.id),
+                    QuantizedState::from(state),
+                )
+            })
+            .collect();
+        Snapshot {
+            frame: update_data.frame,
+            entities,
+            input: update_data.input,
+        }
+    }
+}
+
+impl Snapshot {
+    pub fn to_update_data(&self) -> UpdateData {
+        let states = self
+            .entities
+            .iter()
+            .map(|((entity_type, id), state)| {
+                state.to_entity_state(*id, EntityType::from(*entity_type))
+            })
+            .collect();
+        UpdateData {
+            frame: self.frame,
+            states,
+            input: self.input,
+        }
+    }
+
+    // 计算相对基准快照的变化, 基准为None时生成完整快照
+    pub fn delta(&self, seq: u32, baseline: Option<(u32, &Snapshot)>) -> SnapshotData {
+        let empty = BTreeMap::new();
+        let base = baseline.map_or(&empty, |(_, snapshot)| &snapshot.entities);
+        let mut entities = Vec::new();
+        for ((entity_type, id), state) in self.entities.iter() {
+            let old = base.get(&(*entity_type, *id));
+            let delta = EntityDelta {
+                id: *id,
+                entity_type: EntityType::from(*entity_type),
+                translation: changed(old.map(|old| old.translation), state.translation),
+                rotation: changed(old.map(|old| old.rotation), state.rotation),
+                linvel: changed(old.map(|old| old.linvel), state.linvel),
+                angvel: changed(old.map(|old| old.angvel), state.angvel),
+                texture: changed(old.map(|old| old.texture), state.texture),
+                animate: changed(old.map(|old| old.animate), state.animate),
+            };
+            if old != Some(state) {
+                entities.push(delta);
+            }
+        }
+        let removed = base
+            .keys()
+            .filter(|key| !self.entities.contains_key(key))
+            .map(|(entity_type, id)| (EntityType::from(*entity_type), *id))
+            .collect();
+        SnapshotData {
+            seq,
+            baseline: baseline.map(|(seq, _)| seq),
+            frame: self.frame,
+            entities,
+            removed,
+            input: self.input,
+        }
+    }
+
+    // 在基准快照上应用变化
+    pub fn apply(baseline: Option<&Snapshot>, data: &SnapshotData) -> Snapshot {
+        let mut entities =
+            baseline.map_or_else(BTreeMap::new, |snapshot| snapshot.entities.clone());
+        for (entity_type, id) in data.removed.iter() {
+            entities.remove(&(*entity_type as u8, *id));
+        }
+        for delta in data.entities.iter() {
+            let state = entities
+                .entry((delta.entity_type as u8, delta.id))
+                .or_insert(QuantizedState {
+                    translation: (0, 0),
+                    rotation: 0,
+                    linvel: (0, 0),
+                    angvel: 0,
+                    texture: (0, 0, 0),
+                    animate: 0,
+                });
+            if let Some(translation) = delta.translation {
+                state.translation = translation;
+            }
+            if let Some(rotation) = delta.rotation {
+                state.rotation = rotation;
+            }
+            if let Some(linvel) = delta.linvel {
+                state.linvel = linvel;
+            }
+            if let Some(angvel) = delta.angvel {
+                state.angvel = angvel;
+            }
+            if let Some(texture) = delta.texture {
+                state.texture = texture;
+            }
+            if let Some(animate) = delta.animate {
+                state.animate = animate;
+            }
+        }
+        Snapshot {
+            frame: data.frame,
+            entities,
+            input: data.input,
+        }
+    }
+}
+
+// 单个对端的快照历史
+// 服务端: 记录已发送快照和客户端确认的序号, 以确认的快照为基准编码
+// 客户端: 记录已还原的快照, 作为解码基准
+#[derive(Debug, Default)]
+pub struct SnapshotHistory {
+    next_seq: u32,
+    acked: Option<u32>,
+    history: VecDeque<(u32, Snapshot)>,
+}
+
+impl SnapshotHistory {
+    fn find(&self, seq: u32) -> Option<&Snapshot> {
+        self.history
+            .iter()
+            .find(|(s, _)| *s == seq)
+            .map(|(_, snapshot)| snapshot)
+    }
+
+    fn push(&mut self, seq: u32, snapshot: Snapshot) {
+        self.history.push_back((seq, snapshot));
+        while self.history.len() > SNAPSHOT_HISTORY {
+            self.history.pop_front();
+        }
+    }
+
+    // 服务端: 编码快照, 确认的基准已过期则发送完整快照
+    pub fn encode(&mut self, snapshot: Snapshot) -> SnapshotData {
+        let seq = self.next_seq;
+        self.next_seq = self.next_seq.wrapping_add(1);
+        let baseline = self
+            .acked
+            .and_then(|acked| self.find(acked).map(|base| (acked, base)));
+        let snapshot_data = snapshot.delta(seq, baseline);
+        self.push(seq, snapshot);
+        snapshot_data
+    }
+
+    // 服务端: 客户端确认快照, 序号回绕后仍只接受更新的确认
+    pub fn ack(&mut self, seq: u32) {
+        let newer = match self.acked {
+            Some(acked) => (seq.wrapping_sub(acked) as i32) > 0,
+            None => true,
+        };
+        if newer && self.find(seq).is_some() {
+            self.acked = Some(seq);
+        }
+    }
+
+    // 客户端: 还原快照, 缺少基准时返回None
+    pub fn decode(&mut self, snapshot_data: &SnapshotData) -> Option<Snapshot> {
+        let snapshot = match snapshot_data.baseline {
+            Some(baseline) => Snapshot::apply(Some(self.find(baseline)?), snapshot_data),
+            None => Snapshot::apply(None, snapshot_data),
+        };
+        self.push(snapshot_data.seq, snapshot.clone());
+        Some(snapshot)
+    }
+}
+
+#[cfg(test)]
+fn update_data(frame: u128, translation: f32) -> UpdateData {
+    let state = |id, entity_type, x: f32| EntityState {
+        id,
+        translation: (x, -x),
+        rotation: -1.5,
+        linvel: (12.3, -4.5),
+        angvel: (0.25, 0.25),
+        texture: (id as u32, 1, 1),
+        entity_type,
+        animate: 0,
+    };
+    UpdateData {
+        frame,
+        states: vec![
+            state(1, EntityType::Player, translation),
+            state(1, EntityType::Static, 100.0),
+            state(2, EntityType::Static, 200.0),
+        ],
+        input: InputAckData {
+            seq: frame as u32,
+            elapsed: 16,
+        },
+    }
+}
+
+#[test]
+fn test_quantize() {
+    let data = update_data(1, 123.456);
+    let restored = Snapshot::from(&data).to_update_data();
+    assert_eq!(data.states.len(), restored.states.len());
+    for a in data.states.iter() {
+        let b = restored
+            .states
+            .iter()
+            .find(|b| b.id == a.id && b.entity_type == a.entity_type)
+            .unwrap();
+        assert!((a.translation.0 - b.translation.0).abs() <= 0.01);
+        assert!((a.rotation - b.rotation).abs() <= 0.001);
+        assert!((a.linvel.0 - b.linvel.0).abs() <= 0.1);
+        assert!((a.angvel.1 - b.angvel.1).abs() <= 0.01);
+        assert_eq!(a.texture, b.texture);
+    }
+    assert!((dequantize_angle(quantize_angle(PI)) - PI).abs() <= 0.001);
+}
+
+#[test]
+fn test_delta() {
+    let mut server = SnapshotHistory::default();
+    let mut client = SnapshotHistory::default();
+
+    // 未确认时发送完整快照
+    let full = server.encode(Snapshot::from(&update_data(1, 10.0)));
+    assert_eq!(full.baseline, None);
+    assert_eq!(full.entities.len(), 3);
+    let snapshot = client.decode(&full).unwrap();
+    server.ack(full.seq);
+
+    // 确认后只发送变化的字段
+    let mut next = update_data(2, 11.0);
+    next.states.pop();
+    let delta = server.encode(Snapshot::from(&next));
+    assert_eq!(delta.baseline, Some(full.seq));
+    assert_eq!(delta.entities.len(), 1);
+    assert!(delta.entities[0].translation.is_some());
+    assert_eq!(delta.entities[0].rotation, None);
+    assert_eq!(delta.removed, vec![(EntityType::Static, 2)]);
+
+    let restored = client.decode(&delta).unwrap();
+    assert_eq!(restored, Snapshot::from(&next));
+    assert_ne!(restored, snapshot);
+    assert_eq!(restored.to_update_data().states.len(), 2);
+
+    // 缺少基准时无法还原
+    let mut lost = SnapshotHistory::default();
+    assert!(lost.decode(&delta).is_none());
+
+    // 未发送过的序号不能作为基准
+    server.ack(1000);
+    let delta = server.encode(Snapshot::from(&next));
+    assert_eq!(delta.baseline, Some(full.seq));
+}
+
+#[test]
+fn test_history_expire() {
+    let mut server = SnapshotHistory::default();
+    let first = server.encode(Snapshot::from(&update_data(0, 0.0)));
+    server.ack(first.seq);
+    for frame in 1..=SNAPSHOT_HISTORY as u128 {
+        server.encode(Snapshot::from(&update_data(frame, frame as f32)));
+    }
+    // 基准已移出历史, 回退为完整快照
+    let full = server.encode(Snapshot::from(&update_data(100, 0.0)));
+    assert_eq!(full.baseline, None);
+    assert_eq!(full.entities.len(), 3);
+}
+
+#[test]
+fn test_ack_wrap() {
+    let mut server = SnapshotHistory {
+        next_seq: u32::MAX - 1,
+        ..Default::default()
+    };
+    let before = server.encode(Snapshot::from(&update_data(0, 0.0)));
+    server.encode(Snapshot::from(&update_data(1, 1.0)));
+    let after = server.encode(Snapshot::from(&update_data(2, 2.0)));
+    assert_eq!(after.seq, 0);
+    server.ack(after.seq);
+    // 回绕前的旧确认不会覆盖回绕后的确认
+    server.ack(before.seq);
+    let delta = server.encode(Snapshot::from(&update_data(3, 3.0)));
+    assert_eq!(delta.baseline, Some(after.seq));
+}
@@ -28,11 +28,19 @@ impl Cli {
                         }
                         None => match params[0] {
                             "new" => println!("new"),
-                            "init_tile_map" => common::tile_map::create_init_map(),
+                            // init_tile_map [房间] [种子]
+                            "init_tile_map" => common::tile_map::create_init_map(
+                                params.get(1).and_then(|p| p.parse().ok()).unwrap_or(0),
+                                config::get().map_chunks,
+                                params.get(2).and_then(|p| p.parse().ok()),
+                            ),
                             "db_show_all" => {
-                                data::sled_db::SledDB::show_all(config::DB_PATH_SERVER)
+                                data::sled_db::SledDB::show_all(&config::get().db_path_server)
                             }
-                            "db_all_tile" => data::server_db::all_tile(config::DB_PATH_SERVER),
+                            "db_all_tile" => data::server_db::all_tile(
+                                &config::get().db_path_server,
+                                params.get(1).and_then(|p| p.parse().ok()).unwrap_or(0),
+                            ),
                             "quit" | "q!" => break,
                             "help" | "h" => println!("Print help command"),
                             "" => continue,
@@ -0,0 +1,122 @@
+use std::collections::HashSet;
+
+use common::tile_collider::{chunk_of, TILE_SIZE};
+
+/// 区块加载与卸载的变化
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ChunkChanges {
+    // 按离玩家由近到远排列
+    pub load: Vec<(i32, i32)>,
+    pub unload: Vec<(i32, i32)>,
+}
+
+/// 世界坐标所在的区块
+pub fn chunk_at(position: (f32, f32)) -> (i32, i32) {
+    let x = (position.0 / TILE_SIZE).round() as i32;
+    let y = (position.1 / TILE_SIZE).round() as i32;
+    chunk_of((x, y, 0))
+}
+
+/// 区块间的距离(区块数), 斜向相邻也为1
+fn chunk_distance(a: (i32, i32), b: (i32, i32)) -> i32 {
+    (a.0 - b.0).abs().max((a.1 - b.1).abs())
+}
+
+/// 按玩家位置决定加载哪些区块
+/// 卸载半径大于加载半径, 避免玩家在区块边缘来回走动时反复加载卸载
+pub struct ChunkStreamer {
+    load_radius: i32,
+    unload_radius: i32,
+    // 已加载或正在加载的区块
+    loaded: HashSet<(i32, i32)>,
+}
+
+impl ChunkStreamer {
+    pub fn new(load_radius: i32, unload_radius: i32) -> Self {
+        ChunkStreamer {
+            load_radius,
+            unload_radius: unload_radius.max(load_radius),
+            loaded: HashSet::new(),
+        }
+    }
+
+    /// 玩家附近未加载的区块加载, 离所有玩家都超过卸载半径的区块卸载
+    pub fn update(&mut self, players: &[(f32, f32)]) -> ChunkChanges {
+        let centers: Vec<(i32, i32)> = players.iter().map(|position| chunk_at(*position)).collect();
+        let nearest = |chunk: (i32, i32)| {
+            centers
+                .iter()
+                .map(|center| chunk_distance(*center, chunk))
+                .min()
+                .unwrap_or(i32::MAX)
+        };
+
+        let mut load = Vec::new();
+        for center in centers.iter() {
+            for x in center.0 - self.load_radius..=center.0 + self.load_radius {
+                for y in center.1 - self.load_radius..=center.1 + self.load_radius {
+                    if self.loaded.insert((x, y)) {
+                        load.push((x, y));
+                    }
+                }
+            }
+        }
+        load.sort_by_key(|chunk| (nearest(*chunk), *chunk));
+
+        let mut unload: Vec<(i32, i32)> = self
+            .loaded
+            .iter()
+            .filter(|chunk| nearest(**chunk) > self.unload_radius)
+            .cloned()
+            .collect();
+        unload.sort();
+        for chunk in unload.iter() {
+            self.loaded.remove(chunk);
+        }
+        ChunkChanges { load, unload }
+    }
+
+    /// 区块是否仍需要加载, 加载完成前已卸载的区块丢弃加载结果
+    pub fn is_loaded(&self, chunk: (i32, i32)) -> bool {
+        self.loaded.contains(&chunk)
+    }
+}
+
+#[test]
+fn test_chunk_at() {
+    assert_eq!(chunk_at((0., 0.)), (0, 0));
+    // 地块0覆盖[-32, 32), 区块0从地块0到地块15
+    assert_eq!(chunk_at((-40., 15. * 64.)), (-1, 0));
+    assert_eq!(chunk_at((16. * 64., -1.)), (1, 0));
+}
+
+#[test]
+fn test_chunk_streamer() {
+    let mut streamer = ChunkStreamer::new(1, 2);
+    let changes = streamer.update(&[(0., 0.)]);
+    assert_eq!(changes.load.len(), 9);
+    // 玩家所在的区块最先加载
+    assert_eq!(changes.load[0], (0, 0));
+    assert!(changes.unload.is_empty());
+    // 已加载的区块不重复加载
+    assert_eq!(streamer.update(&[(0., 0.)]), ChunkChanges::default());
+
+    // 移动一个区块, 只加载新进入范围的一列, 卸载半径内的区块保留
+    let changes = streamer.update(&[(1024., 0.)]);
+    assert_eq!(changes.load, vec![(2, -1), (2, 0), (2, 1)]);
+    assert!(changes.unload.is_empty());
+    // 远离后卸载
+    let changes = streamer.update(&[(3. * 1024., 0.)]);
+    assert_eq!(
+        changes.unload,
+        vec![(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 0), (0, 1)]
+    );
+    assert!(!streamer.is_loaded((-1, 0)));
+    assert!(streamer.is_loaded((4, 0)));
+
+    // 没有玩家时全部卸载
+    let changes = streamer.update(&[]);
+    assert!(changes.load.is_empty());
+    assert_eq!(changes.unload.len(), 12);
+    assert!(!streamer.is_loaded((3, 0)));
+}
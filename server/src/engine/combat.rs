@@ -0,0 +1,250 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use protocol::data::{
+    combat_data::{DamageSource, KillData},
+    player_data::PlayerData,
+};
+
+use super::{player_store::PlayerStore, team::Teams};
+
+/// 一次伤害
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DamageEvent {
+    pub target: u32,
+    // 造成伤害的玩家(技能的施法者), 陷阱为None
+    pub attacker: Option<u32>,
+    pub source: DamageSource,
+    pub damage: u32,
+}
+
+/// 房间内的战斗状态: 队伍, 玩家血量, 死亡玩家的复活倒计时和复活后的无敌时间
+pub struct Combat {
+    pub teams: Teams,
+    pub players: PlayerStore,
+    respawn_delay: Duration,
+    invulnerability: Duration,
+    // 死亡玩家 -> 复活时刻
+    respawns: HashMap<u32, Instant>,
+    // 复活玩家 -> 无敌结束时刻
+    invulnerable: HashMap<u32, Instant>,
+    // 待发送的击杀通知
+    kills: Vec<KillData>,
+}
+
+impl Combat {
+    pub fn new(teams: Teams, respawn_delay: Duration, invulnerability: Duration) -> Self {
+        Combat {
+            teams,
+            players: PlayerStore::new(),
+            respawn_delay,
+            invulnerability,
+            respawns: HashMap::new(),
+            invulnerable: HashMap::new(),
+            kills: Vec::new(),
+        }
+    }
+
+    pub fn is_invulnerable(&self, uid: u32, now: Instant) -> bool {
+        self.invulnerable
+            .get(&uid)
+            .is_some_and(|until| *until > now)
+    }
+
+    /// 结算伤害, 血量变化时返回true
+    /// 致死时记录击杀通知并开始复活倒计时
+    /// 已死亡或无敌的玩家, 以及施法者自己和不允许伤害的队友不受伤害
+    pub fn apply(&mut self, player: &mut PlayerData, event: DamageEvent, now: Instant) -> bool {
+        if event.damage == 0
+            || player.hp == 0
+            || self.is_invulnerable(player.uid, now)
+            || !self.teams.can_damage(event.attacker, player.uid)
+        {
+            return false;
+        }
+        player.hp = player.hp.saturating_sub(event.damage);
+        if player.hp == 0 {
+            self.respawns.insert(player.uid, now + self.respawn_delay);
+            self.kills.push(KillData {
+                victim: player.uid,
+                attacker: event.attacker,
+                source: event.source,
+                respawn_in: self.respawn_delay.as_millis() as u32,
+            });
+        }
+        true
+    }
+
+    /// 安排复活, 用于死亡状态下离线后重新进入的玩家
+    pub fn schedule_respawn(&mut self, uid: u32, at: Instant) {
+        self.respawns.insert(uid, at);
+    }
+
+    /// 取出待发送的击杀通知
+    pub fn take_kills(&mut self) -> Vec<KillData> {
+        std::mem::take(&mut self.kills)
+    }
+
+    /// 取出复活时间已到的玩家
+    pub fn due_respawns(&mut self, now: Instant) -> Vec<u32> {
+        let due: Vec<u32> = self
+            .respawns
+            .iter()
+            .filter(|(_, at)| **at <= now)
+            .map(|(uid, _)| *uid)
+            .collect();
+        for uid in due.iter() {
+            self.respawns.remove(uid);
+        }
+        self.invulnerable.retain(|_, until| *until > now);
+        due
+    }
+
+    /// 复活: 恢复血量和魔力值, 开始无敌
+    pub fn respawn(&mut self, player: &mut PlayerData, now: Instant) {
+        player.hp = player.max_hp;
+        player.mp = player.max_mp;
+        self.invulnerable
+            .insert(player.uid, now + self.invulnerability);
+    }
+
+    /// 复活后的无敌时长(毫秒)
+    pub fn invulnerability(&self) -> u32 {
+        self.invulnerability.as_millis() as u32
+    }
+
+    /// 玩家离开房间时清除状态, 死亡的玩家重新进入时再安排复活
+    pub fn remove_player(&mut self, uid: u32) {
+        self.teams.leave(uid);
+        self.respawns.remove(&uid);
+        self.invulnerable.remove(&uid);
+    }
+}
+
+/// 选择离其他玩家最远的复活点, 避免复活后立即被击杀
+pub fn choose_spawn_point(points: &[(f32, f32)], others: &[(f32, f32)]) -> Option<(f32, f32)> {
+    let nearest = |point: &(f32, f32)| {
+        others
+            .iter()
+            .map(|other| (other.0 - point.0).powi(2) + (other.1 - point.1).powi(2))
+            .fold(f32::INFINITY, f32::min)
+    };
+    points
+        .iter()
+        .map(|point| (*point, nearest(point)))
+        .fold(
+            None,
+            |best: Option<((f32, f32), f32)>, (point, distance)| match best {
+                Some((_, best_distance)) if best_distance >= distance => best,
+                _ => Some((point, distance)),
+            },
+        )
+        .map(|(point, _)| point)
+}
+
+#[cfg(test)]
+use protocol::data::skill_data::SkillType;
+
+#[cfg(test)]
+fn player(uid: u32, hp: u32) -> PlayerData {
+    PlayerData {
+        uid,
+        hp,
+        mp: 0,
+        max_hp: 100,
+        max_mp: 100,
+    }
+}
+
+#[test]
+fn test_combat() {
+    let mut combat = Combat::new(
+        Teams::new(0, false),
+        Duration::from_secs(5),
+        Duration::from_secs(3),
+    );
+    let now = Instant::now();
+    let mut victim = player(1, 20);
+    let hit = |damage| DamageEvent {
+        target: 1,
+        attacker: Some(2),
+        source: DamageSource::Skill(SkillType::Shot),
+        damage,
+    };
+
+    assert!(combat.apply(&mut victim, hit(15), now));
+    assert_eq!(victim.hp, 5);
+    assert!(combat.take_kills().is_empty());
+    // 致死伤害记录击杀者
+    assert!(combat.apply(&mut victim, hit(15), now));
+    assert_eq!(victim.hp, 0);
+    assert_eq!(
+        combat.take_kills(),
+        vec![KillData {
+            victim: 1,
+            attacker: Some(2),
+            source: DamageSource::Skill(SkillType::Shot),
+            respawn_in: 5000,
+        }]
+    );
+    // 死亡后不再受伤害, 不重复记录击杀
+    assert!(!combat.apply(&mut victim, hit(15), now));
+    assert!(combat.take_kills().is_empty());
+
+    // 复活倒计时结束前不复活
+    assert!(combat.due_respawns(now + Duration::from_secs(4)).is_empty());
+    let later = now + Duration::from_secs(5);
+    assert_eq!(combat.due_respawns(later), vec![1]);
+    combat.respawn(&mut victim, later);
+    assert_eq!((victim.hp, victim.mp), (100, 100));
+    // 复活后的无敌时间内不受伤害
+    assert!(!combat.apply(&mut victim, hit(15), later + Duration::from_secs(1)));
+    assert!(combat.apply(&mut victim, hit(15), later + Duration::from_secs(3)));
+    assert_eq!(victim.hp, 85);
+}
+
+#[test]
+fn test_friendly_fire() {
+    let mut combat = Combat::new(
+        Teams::new(2, false),
+        Duration::from_secs(5),
+        Duration::from_secs(3),
+    );
+    let now = Instant::now();
+    for uid in 1..=3 {
+        combat.teams.join(uid);
+    }
+    let mut victim = player(3, 10);
+    let hit = |attacker| DamageEvent {
+        target: 3,
+        attacker,
+        source: DamageSource::Skill(SkillType::AreaBlast),
+        damage: 20,
+    };
+    // 不伤害自己和队友
+    assert!(!combat.apply(&mut victim, hit(Some(3)), now));
+    assert!(!combat.apply(&mut victim, hit(Some(1)), now));
+    assert_eq!(victim.hp, 10);
+    assert!(combat.apply(&mut victim, hit(Some(2)), now));
+    assert_eq!(combat.take_kills()[0].attacker, Some(2));
+
+    // 离开房间后清除复活倒计时, 重新进入时再安排
+    combat.remove_player(3);
+    assert!(combat
+        .due_respawns(now + Duration::from_secs(10))
+        .is_empty());
+    combat.schedule_respawn(3, now);
+    assert_eq!(combat.due_respawns(now), vec![3]);
+}
+
+#[test]
+fn test_choose_spawn_point() {
+    let points = [(0., 0.), (100., 0.), (-100., 0.)];
+    assert_eq!(choose_spawn_point(&points, &[]), Some((0., 0.)));
+    assert_eq!(choose_spawn_point(&points, &[(90., 0.)]), Some((-100., 0.)));
+    assert_eq!(
+        choose_spawn_point(&points, &[(-100., 0.), (100., 0.)]),
+        Some((0., 0.))
+    );
+    assert_eq!(choose_spawn_point(&[], &[(0., 0.)]), None);
+}
@@ -1,15 +1,25 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
+use common::{
+    skill::{SkillDefinition, SkillShape},
+    tile_map::load_or_create_chunk,
+};
 use data::server_db::{self, find_player, next_entity_id, save_player, GameData};
-use glam::{IVec3, Vec2};
 use protocol::{
     data::{
+        combat_data::{DamageSource, RespawnData},
+        control_data::InputAckData,
+        interest_data::InterestData,
         player_data::PlayerListData,
-        tile_map_data::TileState,
-        update_data::{EntityState, EntityType, UpdateData},
+        skill_data::CastResult,
+        update_data::{EntityType, UpdateData},
     },
     packet::Packet,
-    route::GameRoute,
+    route::{AccountRoute, GameRoute},
 };
 use rand::Rng;
 use rapier2d::prelude::*;
@@ -23,8 +33,98 @@ type RigidBodySetState = Arc<Mutex<RigidBodySet>>;
 type JointSetState = Arc<Mutex<JointSet>>;
 type IslandState = Arc<Mutex<IslandManager>>;
 type PlayerHandleMapState = Arc<Mutex<HashMap<u32, RigidBodyHandle>>>;
+type EntityRegistryState = Arc<Mutex<EntityRegistry>>;
+type InputAckState = Arc<Mutex<HashMap<u32, InputAck>>>;
+type SkillEffectState = Arc<Mutex<SkillEffects<RigidBodyHandle>>>;
+type CombatState = Arc<Mutex<Combat>>;
+
+use super::{
+    chunk_stream::ChunkStreamer,
+    combat::{choose_spawn_point, Combat, DamageEvent},
+    entity_registry::{EntityMeta, EntityRegistry},
+    interest::InterestManager,
+    player_store::PlayerStore,
+    skill::{in_arc, in_radius, regenerate_mp, SkillCooldowns, SkillEffects, SkillEntity},
+    team::{Teams, SKILL_GROUP},
+    terrain::TerrainColliders,
+    tick::{FixedTimestep, SnapshotSchedule, TickStats},
+    validation::{validate_control, validate_skill, InputValidator},
+};
+use crate::net::net_server::{LeaveReason, NetMessage, PlayerLeft};
+
+/// 引擎发往网络模块的消息
+#[derive(Debug)]
+pub enum EngineMessage {
+    /// 发送给分组内所有玩家
+    Group(u32, Packet),
+    /// 只发送给指定uid的玩家
+    Player(u32, Packet),
+}
+
+/// 玩家最后处理的输入, frame为输入开始生效的模拟帧
+#[derive(Debug, Clone, Copy, Default)]
+pub struct InputAck {
+    pub seq: u32,
+    pub frame: Option<u128>,
+}
 
-pub async fn engine_start(net_rx: Receiver<Packet>, engine_tx: Sender<Packet>) {
+pub async fn engine_start(net_rx: Receiver<NetMessage>, engine_tx: Sender<EngineMessage>) {
+    // 每个房间一个独立的世界
+    let mut rooms = HashMap::new();
+    for group in config::get().rooms.iter() {
+        let (room_tx, room_rx) = tokio::sync::mpsc::channel::<NetMessage>(100);
+        rooms.insert(*group, room_tx);
+        tokio::spawn(world_start(*group, room_rx, engine_tx.clone()));
+    }
+    route_net(net_rx, rooms).await;
+}
+
+/// 按玩家所在房间把网络消息转发给对应的世界
+async fn route_net(mut net_rx: Receiver<NetMessage>, rooms: HashMap<u32, Sender<NetMessage>>) {
+    // 玩家uid -> 所在房间
+    let mut player_room: HashMap<u32, u32> = HashMap::new();
+    loop {
+        if let Some(net_message) = net_rx.recv().await {
+            let group = match &net_message {
+                NetMessage::Packet(Packet::Account(AccountRoute::Enter(account_data))) => {
+                    let previous = player_room.insert(account_data.uid, account_data.group);
+                    // 重复登录到其他房间时, 和切换房间一样先离开旧房间
+                    if let Some(previous) = previous.filter(|group| *group != account_data.group) {
+                        if let Some(room_tx) = rooms.get(&previous) {
+                            let _ = room_tx
+                                .send(NetMessage::PlayerLeft(PlayerLeft {
+                                    uid: account_data.uid,
+                                    group: previous,
+                                    reason: LeaveReason::Relogin,
+                                }))
+                                .await;
+                        }
+                    }
+                    Some(account_data.group)
+                }
+                NetMessage::Packet(Packet::Game(GameRoute::Control(control_data))) => {
+                    player_room.get(&control_data.uid).cloned()
+                }
+                NetMessage::Packet(Packet::Game(GameRoute::Skill(skill_data))) => {
+                    player_room.get(&skill_data.uid).cloned()
+                }
+                NetMessage::PlayerLeft(player_left) => {
+                    if player_room.get(&player_left.uid) == Some(&player_left.group) {
+                        player_room.remove(&player_left.uid);
+                    }
+                    Some(player_left.group)
+                }
+                _ => None,
+            };
+            if let Some(room_tx) = group.and_then(|group| rooms.get(&group)) {
+                let _ = room_tx.send(net_message).await;
+            }
+        }
+    }
+}
+
+/// 单个房间的世界: 独立的物理集合, 帧计数和地形
+async fn world_start(group: u32, net_rx: Receiver<NetMessage>, engine_tx: Sender<EngineMessage>) {
     let rigid_body_state = Arc::new(Mutex::new(RigidBodySet::new()));
     let collider_state = Arc::new(Mutex::new(ColliderSet::new()));
     let joint_state = Arc::new(Mutex::new(JointSet::new()));
@@ -32,12 +132,26 @@ pub async fn engine_start(net_rx: Receiver<Packet>, engine_tx: Sender<Packet>) {
 
     let player_handle_map: HashMap<u32, RigidBodyHandle> = HashMap::new();
     let player_handle_state = Arc::new(Mutex::new(player_handle_map));
+    // 实体注册表
+    let registry_state = Arc::new(Mutex::new(EntityRegistry::new()));
+    // 玩家输入确认
+    let input_ack_state = Arc::new(Mutex::new(HashMap::new()));
+    // 技能实体存在时长和玩家冲刺
+    let skill_effect_state = Arc::new(Mutex::new(SkillEffects::new()));
+    // 死亡与复活
+    let combat_state = Arc::new(Mutex::new(Combat::new(
+        Teams::new(config::get().team_count, config::get().friendly_fire),
+        Duration::from_millis(config::get().respawn_delay as u64),
+        Duration::from_millis(config::get().respawn_invulnerability as u64),
+    )));
 
     let clean_body_future = clean_body(
         rigid_body_state.clone(),
         collider_state.clone(),
         joint_state.clone(),
         island_state.clone(),
+        player_handle_state.clone(),
+        registry_state.clone(),
     );
     tokio::spawn(clean_body_future);
 
@@ -48,35 +162,57 @@ pub async fn engine_start(net_rx: Receiver<Packet>, engine_tx: Sender<Packet>) {
         rigid_body_state.clone(),
         collider_state.clone(),
         joint_state.clone(),
-        player_handle_state,
+        island_state.clone(),
+        player_handle_state.clone(),
+        registry_state.clone(),
+        input_ack_state.clone(),
+        skill_effect_state.clone(),
+        combat_state.clone(),
     );
     tokio::spawn(net_future);
 
     // 物理引擎主循环
     let engine_future = engine_main_loop(
+        group,
         engine_tx,
         rigid_body_state.clone(),
         collider_state.clone(),
         joint_state.clone(),
         island_state.clone(),
+        player_handle_state,
+        registry_state,
+        input_ack_state,
+        skill_effect_state,
+        combat_state,
     );
     engine_future.await;
 }
 
 pub async fn engine_main_loop(
-    engine_tx: Sender<Packet>,
+    group: u32,
+    engine_tx: Sender<EngineMessage>,
     rigid_body_state: RigidBodySetState,
     collider_state: ColliderSetState,
     joint_state: JointSetState,
     island_state: IslandState,
+    player_handle_state: PlayerHandleMapState,
+    registry_state: EntityRegistryState,
+    input_ack_state: InputAckState,
+    skill_effect_state: SkillEffectState,
+    combat_state: CombatState,
 ) {
-    println!("物理引擎已启动!");
+    println!("物理引擎已启动: 房间{}", group);
     // 物理引擎初始化配置
     let mut pipeline = PhysicsPipeline::new();
     // 世界重力
     let gravity = vector![0.0, 0.0];
-    //
-    let integration_parameters = IntegrationParameters::default();
+    // 积分步长与模拟频率一致
+    let mut timestep =
+        FixedTimestep::new(config::get().tick_rate, config::get().max_catch_up_ticks);
+    let integration_parameters = IntegrationParameters {
+        dt: timestep.step().as_secs_f32(),
+        ..IntegrationParameters::default()
+    };
     //
     let mut broad_phase = BroadPhase::new();
     let mut narrow_phase = NarrowPhase::new();
@@ -91,61 +227,341 @@ pub async fn engine_main_loop(
     let (contact_send, contact_recv) = crossbeam::channel::unbounded();
     let (intersection_send, intersection_recv) = crossbeam::channel::unbounded();
     let event_handler = ChannelEventCollector::new(intersection_send, contact_send);
+    // 视野管理
+    let mut interest_manager = InterestManager::new(config::get().view_radius);
+    // 按区块合并的地形碰撞体, 玩家附近的区块按需生成和加载, 远离的区块卸载
+    let mut terrain = TerrainColliders::new();
+    let mut chunk_streamer = ChunkStreamer::new(
+        config::get().chunk_load_radius,
+        config::get().chunk_unload_radius,
+    );
+    let (chunk_send, chunk_recv) = crossbeam::channel::unbounded();
 
     // 世界初始化物体
-    create_object(rigid_body_state.clone(), collider_state.clone()).await;
+    create_object(
+        rigid_body_state.clone(),
+        collider_state.clone(),
+        registry_state.clone(),
+    )
+    .await;
 
-    // 物理引擎主循环
-    // let start_time = Instant::now();
-    let mut interval = tokio::time::interval(tokio::time::Duration::from_secs_f64(60f64 / 60f64));
+    // 物理引擎主循环, 固定步长推进, 落后时按累积时间补帧
+    let mut interval = tokio::time::interval(timestep.step());
+    let mut snapshot_schedule =
+        SnapshotSchedule::new(config::get().tick_rate, config::get().snapshot_rate);
+    let mut tick_stats = TickStats::default();
+    let mut last_time = Instant::now();
+    let mut last_report = Instant::now();
+    let mut last_regen = Instant::now();
     let mut frame_no: u128 = 0;
     loop {
-        // println!("{}", &frame_no);
         interval.tick().await;
-        let mut bodies = &mut rigid_body_state.lock().await;
-        let mut colliders = &mut collider_state.lock().await;
-        let mut joints = &mut joint_state.lock().await;
-        let mut islands = &mut island_state.lock().await;
-        // 运行物理引擎计算世界
-        pipeline.step(
-            &gravity,
-            &integration_parameters,
-            &mut islands,
-            &mut broad_phase,
-            &mut narrow_phase,
-            &mut bodies,
-            &mut colliders,
-            &mut joints,
-            &mut ccd_solver,
-            &physics_hooks,
-            &event_handler,
-        );
-
-        while let Ok(intersection_event) = intersection_recv.try_recv() {
-            println!("交叉事件: {:?}", intersection_event);
+        let now = Instant::now();
+        let (steps, dropped) = timestep.advance(now - last_time);
+        last_time = now;
+        tick_stats.record_schedule(steps, dropped);
+        if steps == 0 {
+            continue;
         }
+        // 世界锁内只生成要发送的消息和有变化的玩家数据, 释放锁后再发送和写入数据库
+        let (messages, dirty_players) = {
+            let mut bodies = &mut rigid_body_state.lock().await;
+            let mut colliders = &mut collider_state.lock().await;
+            let mut joints = &mut joint_state.lock().await;
+            let mut islands = &mut island_state.lock().await;
+            let player_handle_map = &player_handle_state.lock().await;
+            let registry = &mut registry_state.lock().await;
+            let input_acks = &mut input_ack_state.lock().await;
+            let effects = &mut skill_effect_state.lock().await;
+            let combat = &mut combat_state.lock().await;
+            let mut messages = Vec::new();
+            // 玩家附近的区块在后台读取或生成, 完成后加入地形
+            let positions: Vec<(f32, f32)> = player_handle_map
+                .values()
+                .filter_map(|handle| bodies.get(*handle))
+                .map(|body| (body.translation().x, body.translation().y))
+                .collect();
+            let changes = chunk_streamer.update(&positions);
+            for chunk in changes.load {
+                let chunk_send = chunk_send.clone();
+                tokio::task::spawn_blocking(move || {
+                    let _ = chunk_send.send((chunk, load_or_create_chunk(group, chunk)));
+                });
+            }
+            for chunk in changes.unload {
+                terrain.unload_chunk(chunk);
+            }
+            while let Ok((chunk, tiles)) = chunk_recv.try_recv() {
+                // 加载期间已远离所有玩家的区块不再加入
+                if chunk_streamer.is_loaded(chunk) {
+                    for tile in tiles {
+                        terrain.set_tile(tile.point, tile.collider);
+                    }
+                }
+            }
+            // 重建地块有变化的区块
+            let rebuilt = terrain.rebuild(bodies, colliders, islands, joints);
+            if rebuilt > 0 {
+                println!(
+                    "重建地形碰撞体: {}个区块, 共{}个刚体",
+                    rebuilt,
+                    terrain.body_count()
+                );
+            }
+            // 新处理的输入从本帧开始生效
+            for input_ack in input_acks.values_mut() {
+                if input_ack.frame.is_none() {
+                    input_ack.frame = Some(frame_no);
+                }
+            }
+            for _ in 0..steps {
+                let tick_start = Instant::now();
+                // 运行物理引擎计算世界
+                pipeline.step(
+                    &gravity,
+                    &integration_parameters,
+                    &mut islands,
+                    &mut broad_phase,
+                    &mut narrow_phase,
+                    &mut bodies,
+                    &mut colliders,
+                    &mut joints,
+                    &mut ccd_solver,
+                    &physics_hooks,
+                    &event_handler,
+                );
 
-        while let Ok(contact_event) = contact_recv.try_recv() {
-            // println!("接触事件: {:?}", contact_event);
-            // 处理碰撞事件
-            tokio::join!(handle_contact(
-                contact_event,
-                colliders,
-                bodies,
-                joints,
-                islands
-            ));
-        }
+                while let Ok(intersection_event) = intersection_recv.try_recv() {
+                    handle_intersection(
+                        intersection_event,
+                        colliders,
+                        bodies,
+                        registry,
+                        player_handle_map,
+                        effects,
+                        combat,
+                    );
+                }
 
-        // 处理运行后结果世界状态
-        tokio::join!(send_aync(colliders, bodies, frame_no, engine_tx.clone()));
+                while let Ok(contact_event) = contact_recv.try_recv() {
+                    // println!("接触事件: {:?}", contact_event);
+                    // 处理碰撞事件
+                    tokio::join!(handle_contact(
+                        contact_event,
+                        colliders,
+                        bodies,
+                        joints,
+                        islands,
+                        registry,
+                        player_handle_map,
+                        effects,
+                        combat
+                    ));
+                }
+                tick_stats.record(tick_start.elapsed(), timestep.step());
+                frame_no += 1;
+            }
+
+            // 移除到期或超出飞行距离的技能实体, 结束冲刺的玩家恢复冲刺前的移动
+            let now = Instant::now();
+            let expired = effects.expired_entities(now, |handle| {
+                bodies
+                    .get(handle)
+                    .map(|body| (body.translation().x, body.translation().y))
+            });
+            for handle in expired {
+                registry.remove_body(handle, bodies, islands, colliders, joints);
+            }
+            for (uid, (x, y)) in effects.finished_dashes(now) {
+                if let Some(body) = player_handle_map
+                    .get(&uid)
+                    .and_then(|handle| bodies.get_mut(*handle))
+                {
+                    body.set_linvel(vector![x, y], true);
+                }
+            }
 
-        frame_no += 1;
+            // 通知房间内玩家击杀, 死亡的玩家停止移动
+            for kill in combat.take_kills() {
+                effects.remove_player(kill.victim);
+                if let Some(body) = player_handle_map
+                    .get(&kill.victim)
+                    .and_then(|handle| bodies.get_mut(*handle))
+                {
+                    body.set_linvel(vector![0.0, 0.0], true);
+                }
+                messages.push(EngineMessage::Group(
+                    group,
+                    Packet::Game(GameRoute::Kill(kill)),
+                ));
+            }
+            // 复活倒计时结束的玩家回到复活点
+            for uid in combat.due_respawns(now) {
+                let handle = match player_handle_map.get(&uid) {
+                    Some(handle) => *handle,
+                    None => continue,
+                };
+                let mut player = match combat.players.find(uid) {
+                    Some(player) => player,
+                    None => continue,
+                };
+                let others: Vec<(f32, f32)> = player_handle_map
+                    .iter()
+                    .filter(|(other, _)| **other != uid)
+                    .filter_map(|(_, handle)| bodies.get(*handle))
+                    .map(|body| (body.translation().x, body.translation().y))
+                    .collect();
+                let position = choose_spawn_point(&config::get().spawn_points, &others)
+                    .unwrap_or_else(random_spawn_point);
+                combat.respawn(&mut player, now);
+                combat.players.save(player);
+                if let Some(body) = bodies.get_mut(handle) {
+                    body.set_translation(vector![position.0, position.1], true);
+                    body.set_linvel(vector![0.0, 0.0], true);
+                }
+                let respawn = RespawnData {
+                    uid,
+                    position,
+                    invulnerable: combat.invulnerability(),
+                };
+                messages.push(EngineMessage::Group(
+                    group,
+                    Packet::Game(GameRoute::Respawn(respawn)),
+                ));
+            }
+
+            // 在线玩家每秒恢复魔力
+            if last_regen.elapsed() >= Duration::from_secs(1) {
+                last_regen += Duration::from_secs(1);
+                for uid in player_handle_map.keys() {
+                    if let Some(mut player) = combat.players.find(*uid) {
+                        // 死亡的玩家复活时恢复
+                        if player.hp > 0 && regenerate_mp(&mut player, config::get().mp_regen) {
+                            combat.players.save(player);
+                        }
+                    }
+                }
+            }
+
+            // 按快照频率同步世界状态, 补帧时只发送最新状态
+            if snapshot_schedule.due(frame_no) {
+                messages.extend(sync_messages(
+                    colliders,
+                    bodies,
+                    player_handle_map,
+                    registry,
+                    input_acks,
+                    &combat.players,
+                    &mut interest_manager,
+                    frame_no,
+                ));
+            }
+            (messages, combat.players.take_dirty())
+        };
+        for message in messages {
+            let _ = engine_tx.send(message).await;
+        }
+        // 血量/魔力值的变化在模拟之外批量写入
+        if !dirty_players.is_empty() {
+            let _ = tokio::task::spawn_blocking(move || {
+                for player in dirty_players {
+                    let _ = save_player(player);
+                }
+            })
+            .await;
+        }
+
+        if last_report.elapsed().as_secs() >= config::get().tick_stats_interval {
+            last_report = Instant::now();
+            let behind = tick_stats.is_behind();
+            let report = tick_stats.report();
+            if behind {
+                println!("房间{}模拟落后: {}", group, report);
+            } else {
+                println!("房间{}模拟统计: {}", group, report);
+            }
+        }
     }
     // let time = start_time.elapsed().as_secs_f64();
     // println!("{}", time);
 }
 
+/// 陷阱伤害
+const TRAP_DAMAGE: u32 = 5;
+
+/// 碰撞体所属的刚体
+fn collider_body(handle: ColliderHandle, colliders: &ColliderSet) -> Option<RigidBodyHandle> {
+    colliders.get(handle).and_then(|collider| collider.parent())
+}
+
+/// 结算伤害, 击杀通知和血量变化由主循环在释放世界锁后发送和写入
+fn apply_damage(event: DamageEvent, combat: &mut Combat, now: Instant) {
+    if let Some(mut player) = combat.players.find(event.target) {
+        if combat.apply(&mut player, event, now) {
+            combat.players.save(player);
+        }
+    }
+}
+
+/// 范围内的玩家
+fn players_in<F: Fn((f32, f32)) -> bool>(
+    player_handle_map: &HashMap<u32, RigidBodyHandle>,
+    bodies: &RigidBodySet,
+    contains: F,
+) -> Vec<u32> {
+    player_handle_map
+        .iter()
+        .filter(|(_, handle)| {
+            bodies
+                .get(**handle)
+                .is_some_and(|body| contains((body.translation().x, body.translation().y)))
+        })
+        .map(|(uid, _)| *uid)
+        .collect()
+}
+
+/// 弹道命中, target为被直接命中的玩家, 有范围伤害时伤害命中点周围的玩家
+fn skill_hit(
+    skill_handle: RigidBodyHandle,
+    target: Option<u32>,
+    bodies: &RigidBodySet,
+    player_handle_map: &HashMap<u32, RigidBodyHandle>,
+    effects: &SkillEffects<RigidBodyHandle>,
+    combat: &mut Combat,
+) {
+    let entity = match effects.entity(skill_handle) {
+        Some(entity) => entity,
+        None => return,
+    };
+    let skill = match common::skill::get().get(entity.skill_type) {
+        Some(skill) => skill,
+        None => return,
+    };
+    // 扇形和爆破在释放时结算伤害, 生成的实体只是特效
+    if !matches!(skill.shape, SkillShape::Projectile { .. }) {
+        return;
+    }
+    let hit = |target| DamageEvent {
+        target,
+        attacker: Some(entity.owner),
+        source: DamageSource::Skill(entity.skill_type),
+        damage: skill.damage,
+    };
+    let now = Instant::now();
+    if skill.area_radius > 0. {
+        if let Some(body) = bodies.get(skill_handle) {
+            let center = (body.translation().x, body.translation().y);
+            for uid in players_in(player_handle_map, bodies, |position| {
+                in_radius(center, position, skill.area_radius)
+            }) {
+                apply_damage(hit(uid), combat, now);
+            }
+        }
+    } else if let Some(uid) = target {
+        apply_damage(hit(uid), combat, now);
+    }
+}
+
 /// 处理碰撞事件
 async fn handle_contact(
     contact_event: rapier2d::geometry::ContactEvent,
@@ -153,168 +569,306 @@ async fn handle_contact(
     bodies: &mut tokio::sync::MutexGuard<'_, RigidBodySet>,
     joints: &mut tokio::sync::MutexGuard<'_, JointSet>,
     islands: &mut tokio::sync::MutexGuard<'_, IslandManager>,
+    registry: &mut tokio::sync::MutexGuard<'_, EntityRegistry>,
+    player_handle_map: &HashMap<u32, RigidBodyHandle>,
+    effects: &mut tokio::sync::MutexGuard<'_, SkillEffects<RigidBodyHandle>>,
+    combat: &mut tokio::sync::MutexGuard<'_, Combat>,
 ) {
     match contact_event {
         rapier2d::geometry::ContactEvent::Started(ch1, ch2) => {
-            let mut entity_state1 = EntityState {
-                id: 0u64,
-                translation: (0., 0.),
-                rotation: 0.,
-                linvel: (0., 0.),
-                angvel: (0., 0.),
-                texture: (0, 0, 0),
-                entity_type: EntityType::Static,
-                animate: 0,
-            };
-            let mut entity_state2 = EntityState {
-                id: 0u64,
-                translation: (0., 0.),
-                rotation: 0.,
-                linvel: (0., 0.),
-                angvel: (0., 0.),
-                texture: (0, 0, 0),
-                entity_type: EntityType::Static,
-                animate: 0,
-            };
-            if let Some(collider1) = colliders.get(ch1) {
-                if let Some(body1) = bodies.get(collider1.parent().unwrap()) {
-                    entity_state1 = EntityState {
-                        id: body1.user_data as u64,
-                        translation: (
-                            collider1.position().translation.x,
-                            collider1.position().translation.y,
-                        ),
-                        rotation: collider1.position().rotation.angle(),
-                        linvel: (body1.linvel().x, body1.linvel().y),
-                        angvel: (body1.angvel(), body1.angvel()),
-                        texture: (0, 0, 0),
-                        entity_type: EntityType::Moveable,
-                        animate: 0,
-                    };
-                    entity_state1.make_up_data(body1.user_data);
-                }
-            }
-            if let Some(collider2) = colliders.get(ch2) {
-                if let Some(body2) = bodies.get(collider2.parent().unwrap()) {
-                    entity_state2 = EntityState {
-                        id: body2.user_data as u64,
-                        translation: (
-                            collider2.position().translation.x,
-                            collider2.position().translation.y,
+            let body1 = collider_body(ch1, colliders);
+            let body2 = collider_body(ch2, colliders);
+            // 玩家被技能或陷阱击中, 弹道撞到地形时也会触发范围伤害
+            for (target, source) in [(body1, body2), (body2, body1)].iter() {
+                if let Some(source) = source {
+                    let target_uid = target
+                        .and_then(|target| registry.meta(bodies, target))
+                        .filter(|meta| meta.entity_type == EntityType::Player)
+                        .map(|meta| meta.id as u32);
+                    match registry.meta(bodies, *source).map(|meta| meta.entity_type) {
+                        Some(EntityType::Trap) => {
+                            if let Some(uid) = target_uid {
+                                let event = DamageEvent {
+                                    target: uid,
+                                    attacker: None,
+                                    source: DamageSource::Trap,
+                                    damage: TRAP_DAMAGE,
+                                };
+                                apply_damage(event, combat, Instant::now());
+                            }
+                        }
+                        Some(EntityType::Skill) => skill_hit(
+                            *source,
+                            target_uid,
+                            bodies,
+                            player_handle_map,
+                            effects,
+                            combat,
                         ),
-                        rotation: collider2.position().rotation.angle(),
-                        linvel: (body2.linvel().x, body2.linvel().y),
-                        angvel: (body2.angvel(), body2.angvel()),
-                        texture: (0, 0, 0),
-                        entity_type: EntityType::Moveable,
-                        animate: 0,
-                    };
-                    entity_state2.make_up_data(body2.user_data);
-                }
-            }
-            // println!("{:?}", entity_state1);
-            // println!("{:?}", entity_state2);
-            if entity_state1.entity_type == EntityType::Player
-                && (entity_state2.entity_type == EntityType::Skill
-                    || entity_state2.entity_type == EntityType::Trap)
-            {
-                if let Ok(mut player) = find_player(entity_state1.id as u32) {
-                    if player.hp >= 5 {
-                        player.hp -= 5;
-                        let _ = save_player(player);
-                    }
-                }
-            }
-            if entity_state2.entity_type == EntityType::Player
-                && (entity_state1.entity_type == EntityType::Skill
-                    || entity_state1.entity_type == EntityType::Trap)
-            {
-                if let Ok(mut player) = find_player(entity_state2.id as u32) {
-                    if player.hp >= 5 {
-                        player.hp -= 5;
-                        let _ = save_player(player);
+                        _ => {}
                     }
                 }
             }
         }
         rapier2d::geometry::ContactEvent::Stopped(ch1, ch2) => {
-            if let Some(collider1) = colliders.get(ch1) {
-                if let Some(body1) = bodies.get(collider1.parent().unwrap()) {
-                    let mut entity_state1 = EntityState {
-                        id: body1.user_data as u64,
-                        translation: (
-                            collider1.position().translation.x,
-                            collider1.position().translation.y,
-                        ),
-                        rotation: collider1.position().rotation.angle(),
-                        linvel: (body1.linvel().x, body1.linvel().y),
-                        angvel: (body1.angvel(), body1.angvel()),
-                        texture: (0, 0, 0),
-                        entity_type: EntityType::Moveable,
-                        animate: 0,
-                    };
-                    entity_state1.make_up_data(body1.user_data);
-                    if entity_state1.entity_type == EntityType::Skill {
-                        bodies.remove(collider1.parent().unwrap(), islands, colliders, joints);
+            // 技能碰撞结束后移除, 穿透弹道是传感器, 不会产生接触事件
+            for collider_handle in [ch1, ch2].iter() {
+                if let Some(body_handle) = collider_body(*collider_handle, colliders) {
+                    if let Some(meta) = registry.meta(bodies, body_handle) {
+                        if meta.entity_type == EntityType::Skill {
+                            effects.remove_entity(body_handle);
+                            registry.remove_body(body_handle, bodies, islands, colliders, joints);
+                        }
                     }
                 }
             }
-            if let Some(collider2) = colliders.get(ch2) {
-                if let Some(body2) = bodies.get(collider2.parent().unwrap()) {
-                    let mut entity_state2 = EntityState {
-                        id: body2.user_data as u64,
-                        translation: (
-                            collider2.position().translation.x,
-                            collider2.position().translation.y,
-                        ),
-                        rotation: collider2.position().rotation.angle(),
-                        linvel: (body2.linvel().x, body2.linvel().y),
-                        angvel: (body2.angvel(), body2.angvel()),
-                        texture: (0, 0, 0),
-                        entity_type: EntityType::Moveable,
-                        animate: 0,
-                    };
-                    entity_state2.make_up_data(body2.user_data);
-                    if entity_state2.entity_type == EntityType::Skill {
-                        bodies.remove(collider2.parent().unwrap(), islands, colliders, joints);
-                    }
+        }
+    }
+}
+
+/// 处理交叉事件: 穿透弹道穿过玩家
+fn handle_intersection(
+    intersection_event: IntersectionEvent,
+    colliders: &ColliderSet,
+    bodies: &RigidBodySet,
+    registry: &EntityRegistry,
+    player_handle_map: &HashMap<u32, RigidBodyHandle>,
+    effects: &SkillEffects<RigidBodyHandle>,
+    combat: &mut Combat,
+) {
+    if !intersection_event.intersecting {
+        return;
+    }
+    let body1 = collider_body(intersection_event.collider1, colliders);
+    let body2 = collider_body(intersection_event.collider2, colliders);
+    for (target, source) in [(body1, body2), (body2, body1)].iter() {
+        if let (Some(target), Some(source)) = (target, source) {
+            let target_meta = registry.meta(bodies, *target);
+            let source_meta = registry.meta(bodies, *source);
+            if let (Some(target_meta), Some(source_meta)) = (target_meta, source_meta) {
+                if target_meta.entity_type == EntityType::Player
+                    && source_meta.entity_type == EntityType::Skill
+                {
+                    skill_hit(
+                        *source,
+                        Some(target_meta.id as u32),
+                        bodies,
+                        player_handle_map,
+                        effects,
+                        combat,
+                    );
                 }
             }
         }
     }
 }
 
-/// 更新状态并同步给客户端
-async fn send_aync(
+/// 生成技能实体并记录施法者和存在时长, lifetime为0时不生成
+fn spawn_skill_entity(
+    skill: &SkillDefinition,
+    entity: SkillEntity,
+    rigid_body: RigidBody,
+    collider: Collider,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    registry: &mut EntityRegistry,
+    effects: &mut SkillEffects<RigidBodyHandle>,
+) {
+    if skill.lifetime == 0 {
+        return;
+    }
+    let entity_id = match next_entity_id(EntityType::Skill as u8) {
+        Ok(entity_id) => entity_id,
+        Err(_) => return,
+    };
+    let rb_meta = EntityMeta {
+        id: entity_id,
+        texture: skill.texture,
+        entity_type: EntityType::Skill,
+        animate: 1,
+    };
+    let rb_handle = registry.insert_body(bodies, rigid_body, rb_meta);
+    colliders.insert_with_parent(collider, rb_handle, bodies);
+    effects.insert_entity(rb_handle, entity);
+}
+
+/// 静止的特效实体, 传感器不参与碰撞
+fn spawn_skill_effect(
+    skill: &SkillDefinition,
+    entity: SkillEntity,
+    radius: f32,
+    groups: InteractionGroups,
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    registry: &mut EntityRegistry,
+    effects: &mut SkillEffects<RigidBodyHandle>,
+) {
+    let rigid_body = RigidBodyBuilder::new(RigidBodyType::Dynamic)
+        .translation(vector![entity.origin.0, entity.origin.1])
+        .gravity_scale(0.0)
+        .build();
+    let collider = ColliderBuilder::new(SharedShape::ball(radius))
+        .density(0.1)
+        .sensor(true)
+        .collision_groups(groups)
+        .build();
+    spawn_skill_entity(
+        skill, entity, rigid_body, collider, bodies, colliders, registry, effects,
+    );
+}
+
+/// 范围技能伤害范围内除施法者外的玩家
+fn damage_targets(
+    uid: u32,
+    skill: &SkillDefinition,
+    targets: Vec<u32>,
+    combat: &mut Combat,
+    now: Instant,
+) {
+    for target in targets.into_iter().filter(|target| *target != uid) {
+        let event = DamageEvent {
+            target,
+            attacker: Some(uid),
+            source: DamageSource::Skill(skill.skill_type),
+            damage: skill.damage,
+        };
+        apply_damage(event, combat, now);
+    }
+}
+
+/// 按技能定义释放技能, direction为单位向量
+fn cast_skill(
+    uid: u32,
+    skill: &SkillDefinition,
+    direction: (f32, f32),
+    bodies: &mut RigidBodySet,
+    colliders: &mut ColliderSet,
+    registry: &mut EntityRegistry,
+    player_handle_map: &HashMap<u32, RigidBodyHandle>,
+    effects: &mut SkillEffects<RigidBodyHandle>,
+    combat: &mut Combat,
+    now: Instant,
+) {
+    let caster = match player_handle_map.get(&uid) {
+        Some(handle) => *handle,
+        None => return,
+    };
+    let (origin, linvel) = match bodies.get(caster) {
+        Some(body) => (
+            (body.translation().x, body.translation().y),
+            (body.linvel().x, body.linvel().y),
+        ),
+        None => return,
+    };
+    let (dx, dy) = direction;
+    let team = combat.teams.team(uid).unwrap_or(0);
+    // 技能实体不与施法者和不能伤害的队友碰撞
+    let groups = InteractionGroups::new(SKILL_GROUP, combat.teams.skill_filter(uid));
+    match skill.shape {
+        SkillShape::Projectile {
+            radius,
+            speed,
+            spin,
+            ..
+        } => {
+            // 在施法者前方生成, 避免与施法者重叠
+            let position = (origin.0 + dx * 40., origin.1 + dy * 40.);
+            let rigid_body = RigidBodyBuilder::new(RigidBodyType::Dynamic)
+                .translation(vector![position.0, position.1])
+                // 线速度
+                .linvel(vector![dx * speed, dy * speed])
+                // 角速度
+                .angvel(spin)
+                // 重力
+                .gravity_scale(1.0)
+                .build();
+            let collider = ColliderBuilder::new(SharedShape::ball(radius))
+                // 密度
+                .density(1.0)
+                // 摩擦
+                .friction(0.0)
+                // 穿透弹道为传感器, 穿过玩家时产生交叉事件
+                .sensor(skill.piercing)
+                .collision_groups(groups)
+                .build();
+            let entity = SkillEntity::new(skill, uid, team, position, now);
+            spawn_skill_entity(
+                skill, entity, rigid_body, collider, bodies, colliders, registry, effects,
+            );
+        }
+        SkillShape::MeleeArc { range, angle } => {
+            let targets = players_in(player_handle_map, bodies, |position| {
+                in_arc(origin, direction, position, range, angle)
+            });
+            damage_targets(uid, skill, targets, combat, now);
+            let position = (origin.0 + dx * range / 2., origin.1 + dy * range / 2.);
+            let entity = SkillEntity::new(skill, uid, team, position, now);
+            spawn_skill_effect(
+                skill,
+                entity,
+                range / 2.,
+                groups,
+                bodies,
+                colliders,
+                registry,
+                effects,
+            );
+        }
+        SkillShape::AreaBlast { range } => {
+            let center = (origin.0 + dx * range, origin.1 + dy * range);
+            let targets = players_in(player_handle_map, bodies, |position| {
+                in_radius(center, position, skill.area_radius)
+            });
+            damage_targets(uid, skill, targets, combat, now);
+            let entity = SkillEntity::new(skill, uid, team, center, now);
+            spawn_skill_effect(
+                skill,
+                entity,
+                skill.area_radius,
+                groups,
+                bodies,
+                colliders,
+                registry,
+                effects,
+            );
+        }
+        SkillShape::Dash { speed, .. } => {
+            if let Some(body) = bodies.get_mut(caster) {
+                body.set_linvel(vector![dx * speed, dy * speed], true);
+            }
+            // 冲刺结束后恢复冲刺前的移动
+            effects.start_dash(uid, skill.dash_duration().unwrap_or(0), linvel, now);
+        }
+    }
+}
+
+/// 更新状态并按视野生成同步给客户端的消息, 由主循环释放世界锁后发送
+fn sync_messages(
     colliders: &mut tokio::sync::MutexGuard<'_, ColliderSet>,
     bodies: &mut tokio::sync::MutexGuard<'_, RigidBodySet>,
+    player_handle_map: &HashMap<u32, RigidBodyHandle>,
+    registry: &EntityRegistry,
+    input_acks: &HashMap<u32, InputAck>,
+    player_store: &PlayerStore,
+    interest_manager: &mut InterestManager,
     frame_no: u128,
-    engine_tx: Sender<Packet>,
-) {
+) -> Vec<EngineMessage> {
+    let mut messages = Vec::new();
     let mut states = Vec::new();
     let mut players = Vec::new();
     for (_colloder_handle, collider) in colliders.iter() {
-        if let Some(body) = bodies.get(collider.parent().unwrap()) {
+        let body_handle = collider.parent().unwrap();
+        if let Some(body) = bodies.get(body_handle) {
             // 更新所有动态物体
             if body.is_dynamic()
             // 只更新在运动的物体
             // if body.is_moving()
             //     && (body.linvel().amax().abs() >= 0.0001f32 || body.angvel().abs() >= 0.0001f32)
             {
-                let mut state = EntityState {
-                    id: body.user_data as u64,
-                    translation: (
-                        collider.position().translation.x,
-                        collider.position().translation.y,
-                    ),
-                    rotation: collider.position().rotation.angle(),
-                    linvel: (body.linvel().x, body.linvel().y),
-                    angvel: (body.angvel(), body.angvel()),
-                    texture: (0, 0, 0),
-                    entity_type: EntityType::Moveable,
-                    animate: 0,
+                let mut state = match registry.get(body_handle, body.user_data) {
+                    Some(meta) => meta.state(collider, body),
+                    None => continue,
                 };
-                state.make_up_data(body.user_data);
                 if state.entity_type == EntityType::Player {
                     let l = body.linvel().norm();
                     if l > 0.0001f32 {
@@ -350,7 +904,7 @@ async fn send_aync(
                     } else {
                         state.animate = 0;
                     }
-                    if let Ok(player) = find_player(state.id as u32) {
+                    if let Some(player) = player_store.find(state.id as u32) {
                         // if frame_no % 120 == 0 && player.hp >= 5 {
                         //     player.hp -= 5;
                         //     let _ = save_player(player);
@@ -363,24 +917,53 @@ async fn send_aync(
         }
     }
     // println!("同步包: {:?}", &states);
-    let mut states_iter = states.chunks(40);
-    while let Some(s) = states_iter.next() {
+    // 在线玩家坐标
+    let viewers: Vec<(u32, (f32, f32))> = player_handle_map
+        .iter()
+        .filter_map(|(uid, handle)| {
+            bodies
+                .get(*handle)
+                .map(|body| (*uid, (body.translation().x, body.translation().y)))
+        })
+        .collect();
+    // 每个玩家只同步视野内的实体和玩家, 超出单个数据报的部分由网络模块分片发送
+    for interest in interest_manager.update(&states, &viewers) {
+        if !interest.entered.is_empty() || !interest.left.is_empty() {
+            let packet = Packet::Game(GameRoute::Interest(InterestData {
+                frame: frame_no,
+                entered: interest.entered.clone(),
+                left: interest.left.clone(),
+            }));
+            messages.push(EngineMessage::Player(interest.uid, packet));
+        }
+        let visible_players = players
+            .iter()
+            .filter(|player| interest.is_visible(EntityType::Player, player.uid as u64))
+            .cloned()
+            .collect();
+        // 回传该玩家最后处理的输入, 供客户端校正预测
+        let input = input_acks
+            .get(&interest.uid)
+            .map_or_else(InputAckData::default, |input_ack| InputAckData {
+                seq: input_ack.seq,
+                elapsed: input_ack.frame.map_or(0, |frame| {
+                    ((frame_no - frame) * 1000 / config::get().tick_rate.max(1) as u128) as u32
+                }),
+            });
         let packet = Packet::Game(GameRoute::Update(UpdateData {
             frame: frame_no,
-            states: s.to_vec(),
+            states: interest.states,
+            input,
         }));
-        let _ = engine_tx.send(packet).await;
-        // println!("同步包: {:?}", packet);
-    }
-    // println!("同步包大小: {:?}", states.len());
-    let mut players_iter = players.chunks(40);
-    while let Some(p) = players_iter.next() {
+        messages.push(EngineMessage::Player(interest.uid, packet));
+        // println!("同步包大小: {:?}", states.len());
         let packet = Packet::Game(GameRoute::PlayerList(PlayerListData {
             frame: frame_no,
-            players: p.to_vec(),
+            players: visible_players,
         }));
-        let _ = engine_tx.send(packet).await;
+        messages.push(EngineMessage::Player(interest.uid, packet));
     }
+    messages
 }
 
 pub async fn clean_body(
@@ -388,6 +971,8 @@ pub async fn clean_body(
     collider_state: ColliderSetState,
     joint_state: JointSetState,
     island_state: IslandState,
+    player_handle_state: PlayerHandleMapState,
+    registry_state: EntityRegistryState,
 ) {
     let mut interval = tokio::time::interval(tokio::time::Duration::from_secs_f64(1f64));
     loop {
@@ -396,76 +981,68 @@ pub async fn clean_body(
         let colliders = &mut collider_state.lock().await;
         let joints = &mut joint_state.lock().await;
         let islands = &mut island_state.lock().await;
+        let player_handle_map = &player_handle_state.lock().await;
+        let registry = &mut registry_state.lock().await;
 
+        let world_size = config::get().world_size;
         let mut handles_for_remove = Vec::new();
+        let mut players_outside = Vec::new();
 
-        'bodies_iter: for (body_handle, body) in bodies.iter_mut() {
-            if body.position().translation.x.abs() > 9999f32
-                || body.position().translation.y.abs() > 9999f32
-            {
-                handles_for_remove.push(body_handle);
-                continue;
-            }
-            // 判断玩家实体是否还在线
-            let mut entity_state = EntityState {
-                id: 0,
-                translation: (0., 0.),
-                rotation: 0.,
-                linvel: (0., 0.),
-                angvel: (0., 0.),
-                texture: (0, 0, 0),
-                entity_type: EntityType::Static,
-                animate: 0,
-            };
-            entity_state.make_up_data(body.user_data);
-
-            if entity_state.entity_type == EntityType::Player {
-                match server_db::find(GameData::player_online(None)) {
-                    Ok(data) => {
-                        if data.len() > 0 {
-                            let uid_list: Vec<&str> = data.split(",").collect();
-                            for index in 0..uid_list.len() {
-                                if uid_list[index].eq(&entity_state.id.to_string()) {
-                                    continue 'bodies_iter;
-                                }
-                            }
-                        }
-                        handles_for_remove.push(body_handle);
-                    }
-                    Err(_) => {}
+        // 离线玩家由PlayerLeft消息移除, 这里只清理过界实体
+        // 玩家的句柄仍在player_handle_map中, 不能清除, 过界时拉回边界
+        for (body_handle, body) in bodies.iter() {
+            if body.translation().x.abs() > world_size || body.translation().y.abs() > world_size {
+                if player_handle_map
+                    .values()
+                    .any(|player_handle| *player_handle == body_handle)
+                {
+                    players_outside.push(body_handle);
+                } else {
+                    handles_for_remove.push(body_handle);
                 }
             }
         }
 
+        for handle in players_outside {
+            if let Some(body) = bodies.get_mut(handle) {
+                let x = body.translation().x.clamp(-world_size, world_size);
+                let y = body.translation().y.clamp(-world_size, world_size);
+                body.set_translation(vector![x, y], true);
+                body.set_linvel(vector![0.0, 0.0], true);
+            }
+        }
+
         for handle in handles_for_remove {
-            println!("清除(过界/离线)实体: {:?}", &handle);
-            bodies.remove(handle, islands, colliders, joints);
+            println!("清除过界实体: {:?}", &handle);
+            registry.remove_body(handle, bodies, islands, colliders, joints);
             println!("剩余实体: {:?}", &bodies.len());
         }
     }
 }
 
-async fn create_object(rigid_body_state: RigidBodySetState, collider_state: ColliderSetState) {
+async fn create_object(
+    rigid_body_state: RigidBodySetState,
+    collider_state: ColliderSetState,
+    registry_state: EntityRegistryState,
+) {
     let bodies = &mut rigid_body_state.lock().await;
     let colliders = &mut collider_state.lock().await;
+    let registry = &mut registry_state.lock().await;
 
     // 旋转体
     // 刚体类型
-    for _ in 0..100 {
-        let rb_state = EntityState {
+    let spread = config::get().trap_spread;
+    for _ in 0..config::get().trap_count {
+        let rb_meta = EntityMeta {
             id: next_entity_id(EntityType::Trap as u8).unwrap(),
-            translation: (0., 0.),
-            rotation: 0.,
-            linvel: (0., 0.),
-            angvel: (0., 0.),
             texture: (2, 1, 1),
             entity_type: EntityType::Trap,
             animate: 1,
         };
         let rigid_body = RigidBodyBuilder::new(RigidBodyType::Dynamic)
             .translation(vector![
-                rand::thread_rng().gen_range(-1000..1000) as f32,
-                rand::thread_rng().gen_range(-1000..1000) as f32
+                rand::thread_rng().gen_range(-spread..=spread) as f32,
+                rand::thread_rng().gen_range(-spread..=spread) as f32
             ])
             // .rotation(0.0)
             // .position(Isometry2::new(Vector2::new(1.0, 5.0), 0.0))
@@ -476,7 +1053,6 @@ async fn create_object(rigid_body_state: RigidBodySetState, collider_state: Coll
             // 重力
             .gravity_scale(0.0)
             // .can_sleep(true)
-            .user_data(rb_state.get_data())
             .build();
         // 碰撞体类型
         let collider = ColliderBuilder::new(SharedShape::ball(30.0))
@@ -487,304 +1063,314 @@ async fn create_object(rigid_body_state: RigidBodySetState, collider_state: Coll
             // 是否为传感器
             // .sensor(true)
             .build();
-        let rb_handle = bodies.insert(rigid_body);
+        let rb_handle = registry.insert_body(bodies, rigid_body, rb_meta);
         colliders.insert_with_parent(collider, rb_handle, bodies);
     }
+}
 
-    // 加载地形
-    let db = &data::sled_db::SledDB::open(config::DB_PATH_SERVER)
-        .unwrap()
-        .db;
-    for iter in db.scan_prefix("tile_map-") {
-        match iter {
-            Ok((k, v)) => {
-                if let Ok(tile) = bincode::deserialize::<TileState>(&v) {
-                    match tile.collider {
-                        protocol::data::tile_map_data::TileCollider::Full => {
-                            let mut k_str = String::from_utf8(k.to_vec()).unwrap();
-                            // println!("{}", k_str);
-                            k_str = k_str
-                                .replace("tile_map-(", "")
-                                .replace(")", "")
-                                .replace(" ", "");
-                            let mut point_str = k_str.split(",").take(3);
-                            let point = IVec3::new(
-                                point_str.next().unwrap().parse().unwrap(),
-                                point_str.next().unwrap().parse().unwrap(),
-                                0,
-                            );
-                            // println!("生成碰撞体: {}", point);
-                            let point = point.as_f32() * 64.0;
-                            let rigid_body = RigidBodyBuilder::new(RigidBodyType::Static)
-                                .translation(vector![point.x, point.y])
-                                // .rotation(0.0)
-                                // .position(Isometry2::new(Vector2::new(1.0, 5.0), 0.0))
+/// 玩家离线: 记录离线坐标, 移除玩家实体并立即通知同组玩家
+async fn remove_player(
+    player_left: PlayerLeft,
+    engine_tx: &Sender<EngineMessage>,
+    rigid_body_state: &RigidBodySetState,
+    collider_state: &ColliderSetState,
+    joint_state: &JointSetState,
+    island_state: &IslandState,
+    player_handle_state: &PlayerHandleMapState,
+    registry_state: &EntityRegistryState,
+    input_ack_state: &InputAckState,
+) {
+    input_ack_state.lock().await.remove(&player_left.uid);
+    {
+        let bodies = &mut rigid_body_state.lock().await;
+        let colliders = &mut collider_state.lock().await;
+        let joints = &mut joint_state.lock().await;
+        let islands = &mut island_state.lock().await;
+        let player_handle_map = &mut player_handle_state.lock().await;
+        let registry = &mut registry_state.lock().await;
+        let handle = match player_handle_map.remove(&player_left.uid) {
+            Some(handle) => handle,
+            None => return,
+        };
+        if let Some(body) = bodies.get(handle) {
+            let _ = server_db::save(GameData::player_position(
+                player_left.uid,
+                Some(format!("{},{}", body.translation().x, body.translation().y)),
+            ));
+        }
+        registry.remove_body(handle, bodies, islands, colliders, joints);
+        println!("玩家离开: {} {:?}", player_left.uid, player_left.reason);
+    }
+    let _ = engine_tx
+        .send(EngineMessage::Group(
+            player_left.group,
+            Packet::Game(GameRoute::PlayerLeft(player_left.uid)),
+        ))
+        .await;
+}
+
+/// 出生范围内的随机位置
+fn random_spawn_point() -> (f32, f32) {
+    let spread = config::get().spawn_spread;
+    (
+        rand::thread_rng().gen_range(-spread..=spread) as f32,
+        rand::thread_rng().gen_range(-spread..=spread) as f32,
+    )
+}
+
+/// 玩家上次离线的坐标
+fn find_position(uid: u32) -> Option<(f32, f32)> {
+    let data = server_db::find(GameData::player_position(uid, None)).ok()?;
+    let mut data = data.split(",");
+    match (data.next()?.parse(), data.next()?.parse()) {
+        (Ok(x), Ok(y)) => Some((x, y)),
+        _ => None,
+    }
+}
+
+pub async fn wait_for_net(
+    engine_tx: Sender<EngineMessage>,
+    mut net_rx: Receiver<NetMessage>,
+    rigid_body_state: RigidBodySetState,
+    collider_state: ColliderSetState,
+    joint_state: JointSetState,
+    island_state: IslandState,
+    player_handle_state: PlayerHandleMapState,
+    registry_state: EntityRegistryState,
+    input_ack_state: InputAckState,
+    skill_effect_state: SkillEffectState,
+    combat_state: CombatState,
+) {
+    // 客户端输入只在这里处理, 统计和技能冷却由本房间独占
+    let mut validator = InputValidator::new();
+    let mut cooldowns = SkillCooldowns::new();
+    loop {
+        if let Some(net_message) = net_rx.recv().await {
+            let game_event = match net_message {
+                NetMessage::Packet(packet) => packet,
+                NetMessage::PlayerLeft(player_left) => {
+                    cooldowns.remove(player_left.uid);
+                    skill_effect_state
+                        .lock()
+                        .await
+                        .remove_player(player_left.uid);
+                    let player = {
+                        let combat = &mut combat_state.lock().await;
+                        combat.remove_player(player_left.uid);
+                        combat.players.remove(player_left.uid)
+                    };
+                    remove_player(
+                        player_left,
+                        &engine_tx,
+                        &rigid_body_state,
+                        &collider_state,
+                        &joint_state,
+                        &island_state,
+                        &player_handle_state,
+                        &registry_state,
+                        &input_ack_state,
+                    )
+                    .await;
+                    // 离线时保存最新的血量/魔力值
+                    if let Some(player) = player {
+                        let _ = save_player(player);
+                    }
+                    continue;
+                }
+            };
+            // 进入房间的玩家数据在加锁前读取
+            let entering = match &game_event {
+                Packet::Account(AccountRoute::Enter(login_data)) => {
+                    find_player(login_data.uid).ok()
+                }
+                _ => None,
+            };
+            // 世界锁内只生成要发送的消息, 释放锁后再发送
+            let messages = {
+                let bodies = &mut rigid_body_state.lock().await;
+                let colliders = &mut collider_state.lock().await;
+                let joints = &mut joint_state.lock().await;
+                let islands = &mut island_state.lock().await;
+                let player_handle_map = &mut player_handle_state.lock().await;
+                let registry = &mut registry_state.lock().await;
+                let input_acks = &mut input_ack_state.lock().await;
+                let effects = &mut skill_effect_state.lock().await;
+                let combat = &mut combat_state.lock().await;
+                let mut messages = Vec::new();
+                match game_event {
+                    // 玩家登录生成角色
+                    Packet::Account(account_route) => match account_route {
+                        protocol::route::AccountRoute::Enter(login_data) => {
+                            println!("玩家加入: {}", &login_data.uid);
+                            // 新的客户端从头计数输入序号
+                            input_acks.remove(&login_data.uid);
+                            // 玩家
+                            let player_texture_index: u32 = rand::thread_rng().gen_range(1..24);
+                            let rb_meta = EntityMeta {
+                                id: login_data.uid as u64,
+                                texture: (player_texture_index, 4, 3),
+                                entity_type: EntityType::Player,
+                                animate: 1,
+                            };
+                            // 回到上次离线的位置, 新玩家随机出生
+                            let (x, y) =
+                                find_position(login_data.uid).unwrap_or_else(random_spawn_point);
+                            let rigid_body = RigidBodyBuilder::new(RigidBodyType::Dynamic)
+                                .translation(vector![x, y])
                                 // 线速度
                                 .linvel(vector![0.0, 0.0])
                                 // 角速度
                                 .angvel(0.0)
                                 // 重力
-                                .gravity_scale(0.0)
-                                // .can_sleep(true)
+                                .gravity_scale(1.0)
+                                .lock_rotations()
                                 .build();
+                            // 分配队伍和碰撞分组位
+                            let team = combat.teams.join(login_data.uid);
+                            println!("玩家{}加入队伍{}", login_data.uid, team);
                             // 碰撞体类型
-                            let collider = ColliderBuilder::new(SharedShape::cuboid(32.0, 32.0))
+                            let collider = ColliderBuilder::capsule_y(8.0, 20.0)
                                 // 密度
                                 .density(0.1)
                                 // 摩擦
                                 .friction(0.0)
-                                // 是否为传感器
-                                // .sensor(true)
+                                .collision_groups(InteractionGroups::new(
+                                    combat.teams.player_membership(login_data.uid),
+                                    u32::MAX,
+                                ))
                                 .build();
-                            let rb_handle = bodies.insert(rigid_body);
+                            // 重复登录时旧实体应已由PlayerLeft移除, 这里再确保不残留旧刚体
+                            registry.remove_player(
+                                player_handle_map,
+                                login_data.uid,
+                                bodies,
+                                islands,
+                                colliders,
+                                joints,
+                            );
+                            let rb_handle = registry.insert_body(bodies, rigid_body, rb_meta);
                             colliders.insert_with_parent(collider, rb_handle, bodies);
-                        }
-                        _ => {}
-                    }
-                }
-            }
-            Err(_e) => {}
-        }
-    }
-    println!("加载地形碰撞体: 完成");
-
-    // 加载边界碰撞体
-    for side_x in -58..=58 {
-        for side_y in -58..=58 {
-            if side_y == -58 || side_y == 58 || side_x == -58 || side_x == 58 {
-                let point = IVec3::new(side_x, side_y, 0);
-                // println!("生成边界: {}", point);
-                let point = point.as_f32() * 64.0;
-                let rigid_body = RigidBodyBuilder::new(RigidBodyType::Static)
-                    .translation(vector![point.x, point.y])
-                    // .rotation(0.0)
-                    // .position(Isometry2::new(Vector2::new(1.0, 5.0), 0.0))
-                    // 线速度
-                    .linvel(vector![0.0, 0.0])
-                    // 角速度
-                    .angvel(0.0)
-                    // 重力
-                    .gravity_scale(0.0)
-                    // .can_sleep(true)
-                    .build();
-                // 碰撞体类型
-                let collider = ColliderBuilder::new(SharedShape::cuboid(32.0, 32.0))
-                    // 密度
-                    .density(0.1)
-                    // 摩擦
-                    .friction(0.0)
-                    // 是否为传感器
-                    // .sensor(true)
-                    .build();
-                let rb_handle = bodies.insert(rigid_body);
-                colliders.insert_with_parent(collider, rb_handle, bodies);
-            }
-        }
-    }
-    println!("生成边界: 完成");
-}
-
-pub async fn wait_for_net(
-    engine_tx: Sender<Packet>,
-    mut net_rx: Receiver<Packet>,
-    rigid_body_state: RigidBodySetState,
-    collider_state: ColliderSetState,
-    _joint_state: JointSetState,
-    player_handle_state: PlayerHandleMapState,
-) {
-    loop {
-        if let Some(game_event) = net_rx.recv().await {
-            let bodies = &mut rigid_body_state.lock().await;
-            let colliders = &mut collider_state.lock().await;
-            let player_handle_map = &mut player_handle_state.lock().await;
-            match game_event {
-                // 玩家登录生成角色
-                Packet::Account(account_route) => match account_route {
-                    protocol::route::AccountRoute::Login(login_data) => {
-                        println!("玩家加入: {}", &login_data.uid);
-                        // 玩家
-                        let player_texture_index: u32 = rand::thread_rng().gen_range(1..24);
-                        let rb_state = EntityState {
-                            id: login_data.uid as u64,
-                            translation: (0., 0.),
-                            rotation: 0.,
-                            linvel: (0., 0.),
-                            angvel: (0., 0.),
-                            texture: (player_texture_index, 4, 3),
-                            entity_type: EntityType::Player,
-                            animate: 1,
-                        };
-                        let x = rand::thread_rng().gen_range(-500..500) as f32;
-                        let y = rand::thread_rng().gen_range(-500..500) as f32;
-                        let rigid_body = RigidBodyBuilder::new(RigidBodyType::Dynamic)
-                            .translation(vector![x, y])
-                            // 线速度
-                            .linvel(vector![0.0, 0.0])
-                            // 角速度
-                            .angvel(0.0)
-                            // 重力
-                            .gravity_scale(1.0)
-                            .lock_rotations()
-                            .user_data(rb_state.get_data())
-                            .build();
-                        // 碰撞体类型
-                        let collider = ColliderBuilder::capsule_y(8.0, 20.0)
-                            // 密度
-                            .density(0.1)
-                            // 摩擦
-                            .friction(0.0)
-                            .build();
-                        let rb_handle = bodies.insert(rigid_body);
-                        colliders.insert_with_parent(collider, rb_handle, bodies);
-                        player_handle_map.insert(login_data.uid, rb_handle);
-                        // println!("{:?}", player_handle_map);
-                        // entity_id += 1;
-
-                        // 发送当前所有可移动实体状态给新登录玩家
-                        let mut states = Vec::new();
-                        for (_colloder_handle, collider) in colliders.iter() {
-                            if let Some(body) = bodies.get(collider.parent().unwrap()) {
-                                // 只更新可运动的物体
-                                if body.is_dynamic() {
-                                    let mut state = EntityState {
-                                        id: body.user_data as u64,
-                                        translation: (
-                                            collider.position().translation.x,
-                                            collider.position().translation.y,
-                                        ),
-                                        rotation: collider.position().rotation.angle(),
-                                        linvel: (body.linvel().x, body.linvel().y),
-                                        angvel: (body.angvel(), body.angvel()),
-                                        texture: (0, 0, 0),
-                                        entity_type: EntityType::Static,
-                                        animate: 0,
-                                    };
-                                    state.make_up_data(body.user_data);
-                                    states.push(state);
+                            player_handle_map.insert(login_data.uid, rb_handle);
+                            // 死亡状态下离线的玩家进入后立即复活
+                            if let Some(player) = entering {
+                                if player.hp == 0 {
+                                    combat.schedule_respawn(login_data.uid, Instant::now());
                                 }
+                                combat.players.load(player);
                             }
+                            // println!("{:?}", player_handle_map);
+                            // entity_id += 1;
+
+                            // 视野内的实体状态随下一帧同步发送给新登录玩家
                         }
-                        let packet =
-                            Packet::Game(GameRoute::Update(UpdateData { frame: 0, states }));
-                        let _ = engine_tx.send(packet).await;
-                    }
-                    protocol::route::AccountRoute::Logout(_) => {}
-                    protocol::route::AccountRoute::GetInfo(_) => {}
-                },
-                // 玩家控制
-                Packet::Game(game_route) => match game_route {
-                    protocol::route::GameRoute::Control(control_data) => {
-                        if check_player_health(control_data.uid) {
-                            if let Some(handle) = player_handle_map.get(&control_data.uid) {
-                                if let Some(body) = bodies.get_mut(*handle) {
-                                    let s = Vec2::new(
-                                        control_data.direction.0,
-                                        control_data.direction.1,
-                                    )
-                                    .length();
-                                    if s == 0. {
-                                        body.set_linvel(vector![0., 0.], true);
-                                    } else {
-                                        if control_data.action == 1 {
-                                            body.set_linvel(
-                                                vector![
-                                                    control_data.direction.0,
-                                                    control_data.direction.1
-                                                ] / s
-                                                    * 100.,
-                                                true,
-                                            );
-                                        }
-                                        if control_data.action == 2 {
-                                            body.set_linvel(
-                                                vector![
-                                                    control_data.direction.0,
-                                                    control_data.direction.1
-                                                ] / s
-                                                    * 150.,
-                                                true,
-                                            );
+                        protocol::route::AccountRoute::Logout(_) => {}
+                        protocol::route::AccountRoute::GetInfo(_) => {}
+                        protocol::route::AccountRoute::Session(_) => {}
+                        protocol::route::AccountRoute::Login(_) => {}
+                        protocol::route::AccountRoute::Register(_) => {}
+                        protocol::route::AccountRoute::Failed(_) => {}
+                        protocol::route::AccountRoute::ChangeRoom(_) => {}
+                    },
+                    // 玩家控制
+                    Packet::Game(game_route) => match game_route {
+                        protocol::route::GameRoute::Control(control_data) => {
+                            let uid = control_data.uid;
+                            let now = Instant::now();
+                            let alive =
+                                combat.players.find(uid).is_some_and(|player| player.hp > 0);
+                            let validated = validator
+                                .check_rate(uid, now)
+                                .and_then(|_| validate_control(control_data, alive));
+                            match validated {
+                                Ok(control_data) => {
+                                    let (x, y) = control_data.velocity();
+                                    // 冲刺中的移动输入在冲刺结束后生效
+                                    if !effects.defer_input(uid, (x, y)) {
+                                        if let Some(handle) = player_handle_map.get(&uid) {
+                                            if let Some(body) = bodies.get_mut(*handle) {
+                                                body.set_linvel(vector![x, y], true);
+                                                // println!("速度: {}", body.linvel().norm());
+                                            }
                                         }
                                     }
-                                    // println!("速度: {}", body.linvel().norm());
                                 }
+                                Err(violation) => {
+                                    validator.record(uid, violation, now);
+                                }
+                            }
+                            // 记录最后处理的输入序号, 被拒绝的输入也确认, 客户端据此回到权威位置
+                            let input_ack = input_acks.entry(uid).or_default();
+                            if control_data.seq > input_ack.seq {
+                                *input_ack = InputAck {
+                                    seq: control_data.seq,
+                                    frame: None,
+                                };
                             }
                         }
-                    }
-                    GameRoute::Skill(skill_data) => {
-                        if check_player_health(skill_data.uid) {
-                            if let Some(handle) = player_handle_map.get(&skill_data.uid) {
-                                if let Some(body) = bodies.get_mut(*handle) {
-                                    let translation = Vec2::new(
-                                        body.position().translation.x,
-                                        body.position().translation.y,
-                                    ) + (Vec2::new(
-                                        skill_data.direction.0,
-                                        skill_data.direction.1,
-                                    )
-                                    .normalize()
-                                        * 40.);
-                                    let linvel =
-                                        Vec2::new(skill_data.direction.0, skill_data.direction.1)
-                                            * 1000.;
-                                    let entity_id =
-                                        next_entity_id(EntityType::Skill as u8).unwrap();
-                                    // println!("entity_id: {}", entity_id);
-                                    let rb_state = EntityState {
-                                        id: entity_id,
-                                        translation: (0., 0.),
-                                        rotation: 0.,
-                                        linvel: (0., 0.),
-                                        angvel: (0., 0.),
-                                        texture: skill_data.texture,
-                                        entity_type: EntityType::Skill,
-                                        animate: 1,
-                                    };
-                                    let rigid_body = RigidBodyBuilder::new(RigidBodyType::Dynamic)
-                                        .translation(vector![translation.x, translation.y])
-                                        // .rotation(0.0)
-                                        // .position(Isometry2::new(Vector2::new(1.0, 5.0), 0.0))
-                                        // 线速度
-                                        .linvel(vector![linvel.x, linvel.y])
-                                        // 角速度
-                                        .angvel(60.)
-                                        // 重力
-                                        .gravity_scale(1.0)
-                                        // .can_sleep(true)
-                                        .user_data(rb_state.get_data())
-                                        .build();
-                                    // 碰撞体类型
-                                    let collider = ColliderBuilder::new(SharedShape::ball(5.0))
-                                        // 密度
-                                        .density(1.0)
-                                        // 摩擦
-                                        .friction(0.0)
-                                        // 是否为传感器
-                                        // .sensor(true)
-                                        .build();
-                                    let rb_handle = bodies.insert(rigid_body);
-                                    colliders.insert_with_parent(collider, rb_handle, bodies);
+                        GameRoute::Skill(skill_data) => {
+                            let uid = skill_data.uid;
+                            let now = Instant::now();
+                            let mut player = match combat.players.find(uid) {
+                                Some(player) if player_handle_map.contains_key(&uid) => player,
+                                _ => continue,
+                            };
+                            // 方向已归一化, 贴图由服务器决定
+                            let skill_book = common::skill::get();
+                            let validated = validator.check_rate(uid, now).and_then(|_| {
+                                validate_skill(skill_data, player.hp > 0, skill_book)
+                            });
+                            let (skill_data, skill) = match validated {
+                                Ok(skill_data) => match skill_book.get(skill_data.skill_type) {
+                                    Some(skill) => (skill_data, skill),
+                                    None => continue,
+                                },
+                                Err(violation) => {
+                                    validator.record(uid, violation, now);
+                                    continue;
                                 }
+                            };
+                            // 冷却和魔力由服务器判定, 结果回传给施法者用于显示
+                            let cooldown = cooldowns.try_cast(&mut player, skill, now);
+                            messages.push(EngineMessage::Player(
+                                uid,
+                                Packet::Game(GameRoute::Cooldown(cooldown)),
+                            ));
+                            if cooldown.result == CastResult::Cast {
+                                combat.players.save(player);
+                                cast_skill(
+                                    uid,
+                                    skill,
+                                    skill_data.direction,
+                                    bodies,
+                                    colliders,
+                                    registry,
+                                    player_handle_map,
+                                    effects,
+                                    combat,
+                                    now,
+                                );
                             }
                         }
-                    }
-                    GameRoute::Update(_) => {}
-                    GameRoute::TileMap(_) => {}
-                    GameRoute::Tile(_) => {}
-                    GameRoute::Player(_) => {}
-                    GameRoute::PlayerList(_) => {}
-                },
-                _ => {}
+                        GameRoute::Update(_) => {}
+                        GameRoute::TileMap(_) => {}
+                        GameRoute::Tile(_) => {}
+                        GameRoute::Player(_) => {}
+                        GameRoute::PlayerList(_) => {}
+                        GameRoute::Snapshot(_) => {}
+                        GameRoute::SnapshotAck(_) => {}
+                        GameRoute::Interest(_) => {}
+                        GameRoute::PlayerLeft(_) => {}
+                        GameRoute::Cooldown(_) => {}
+                        GameRoute::Kill(_) => {}
+                        GameRoute::Respawn(_) => {}
+                    },
+                    _ => {}
+                }
+                messages
+            };
+            for message in messages {
+                let _ = engine_tx.send(message).await;
             }
         }
     }
 }
-
-fn check_player_health(uid: u32) -> bool {
-    if let Ok(player_data) = find_player(uid) {
-        if player_data.hp > 0 {
-            return true;
-        }
-    }
-    return false;
-}
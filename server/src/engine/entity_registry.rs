@@ -0,0 +1,268 @@
+use protocol::data::update_data::{EntityState, EntityType};
+use rapier2d::prelude::*;
+use std::collections::HashMap;
+
+/// 实体元数据, 刚体的user_data只保存其在注册表中的下标
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EntityMeta {
+    pub id: u64,
+    pub entity_type: EntityType,
+    pub texture: (u32, u8, u8),
+    pub animate: u8,
+}
+
+impl EntityMeta {
+    /// 结合碰撞体与刚体的物理状态生成同步数据
+    pub fn state(&self, collider: &Collider, body: &RigidBody) -> EntityState {
+        EntityState {
+            id: self.id,
+            translation: (
+                collider.position().translation.x,
+                collider.position().translation.y,
+            ),
+            rotation: collider.position().rotation.angle(),
+            linvel: (body.linvel().x, body.linvel().y),
+            angvel: (body.angvel(), body.angvel()),
+            texture: self.texture,
+            entity_type: self.entity_type,
+            animate: self.animate,
+        }
+    }
+}
+
+/// 服务端实体注册表
+/// user_data = 下标 + 1, 0表示未注册的刚体(地形, 边界)
+#[derive(Debug, Default)]
+pub struct EntityRegistry {
+    entries: Vec<Option<(RigidBodyHandle, EntityMeta)>>,
+    free: Vec<usize>,
+}
+
+impl EntityRegistry {
+    pub fn new() -> Self {
+        EntityRegistry::default()
+    }
+
+    /// 注册刚体, 返回需要写入刚体user_data的值
+    pub fn insert(&mut self, handle: RigidBodyHandle, meta: EntityMeta) -> u128 {
+        let index = match self.free.pop() {
+            Some(index) => {
+                self.entries[index] = Some((handle, meta));
+                index
+            }
+            None => {
+                self.entries.push(Some((handle, meta)));
+                self.entries.len() - 1
+            }
+        };
+        index as u128 + 1
+    }
+
+    /// 插入刚体并注册
+    pub fn insert_body(
+        &mut self,
+        bodies: &mut RigidBodySet,
+        rigid_body: RigidBody,
+        meta: EntityMeta,
+    ) -> RigidBodyHandle {
+        let handle = bodies.insert(rigid_body);
+        let user_data = self.insert(handle, meta);
+        if let Some(body) = bodies.get_mut(handle) {
+            body.user_data = user_data;
+        }
+        handle
+    }
+
+    fn index(&self, handle: RigidBodyHandle, user_data: u128) -> Option<usize> {
+        let index = (user_data as usize).checked_sub(1)?;
+        match self.entries.get(index) {
+            // 校验句柄, 防止复用的下标指向已移除的刚体
+            Some(Some((entry_handle, _))) if *entry_handle == handle => Some(index),
+            _ => None,
+        }
+    }
+
+    pub fn get(&self, handle: RigidBodyHandle, user_data: u128) -> Option<&EntityMeta> {
+        let index = self.index(handle, user_data)?;
+        self.entries[index].as_ref().map(|(_, meta)| meta)
+    }
+
+    pub fn get_mut(&mut self, handle: RigidBodyHandle, user_data: u128) -> Option<&mut EntityMeta> {
+        let index = self.index(handle, user_data)?;
+        self.entries[index].as_mut().map(|(_, meta)| meta)
+    }
+
+    /// 查找刚体对应的元数据
+    pub fn meta(&self, bodies: &RigidBodySet, handle: RigidBodyHandle) -> Option<EntityMeta> {
+        bodies
+            .get(handle)
+            .and_then(|body| self.get(handle, body.user_data))
+            .cloned()
+    }
+
+    pub fn remove(&mut self, handle: RigidBodyHandle, user_data: u128) -> Option<EntityMeta> {
+        let index = self.index(handle, user_data)?;
+        self.free.push(index);
+        self.entries[index].take().map(|(_, meta)| meta)
+    }
+
+    /// 移除刚体及其注册信息
+    pub fn remove_body(
+        &mut self,
+        handle: RigidBodyHandle,
+        bodies: &mut RigidBodySet,
+        islands: &mut IslandManager,
+        colliders: &mut ColliderSet,
+        joints: &mut JointSet,
+    ) -> Option<EntityMeta> {
+        let body = bodies.remove(handle, islands, colliders, joints)?;
+        self.remove(handle, body.user_data)
+    }
+
+    /// 移除玩家的刚体, 同一账号重复登录时先移除旧实体, 每个玩家只保留一个刚体
+    pub fn remove_player(
+        &mut self,
+        player_handle_map: &mut HashMap<u32, RigidBodyHandle>,
+        uid: u32,
+        bodies: &mut RigidBodySet,
+        islands: &mut IslandManager,
+        colliders: &mut ColliderSet,
+        joints: &mut JointSet,
+    ) -> Option<EntityMeta> {
+        let handle = player_handle_map.remove(&uid)?;
+        self.remove_body(handle, bodies, islands, colliders, joints)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+fn meta_of(entity_type: EntityType) -> EntityMeta {
+    EntityMeta {
+        id: u64::MAX - entity_type as u64,
+        entity_type,
+        texture: (u32::MAX, 4, 3),
+        animate: 8,
+    }
+}
+
+#[test]
+fn test_round_trip() {
+    let mut bodies = RigidBodySet::new();
+    let mut colliders = ColliderSet::new();
+    let mut registry = EntityRegistry::new();
+    for entity_type in [
+        EntityType::Static,
+        EntityType::Moveable,
+        EntityType::Player,
+        EntityType::Trap,
+        EntityType::Skill,
+    ]
+    .iter()
+    {
+        let meta = meta_of(*entity_type);
+        let rigid_body = RigidBodyBuilder::new_dynamic()
+            .translation(vector![12.0, -34.0])
+            .linvel(vector![5.0, 6.0])
+            .build();
+        let handle = registry.insert_body(&mut bodies, rigid_body, meta);
+        colliders.insert_with_parent(ColliderBuilder::ball(5.0).build(), handle, &mut bodies);
+
+        assert_eq!(registry.meta(&bodies, handle), Some(meta));
+        let body = &bodies[handle];
+        let collider = &colliders[body.colliders()[0]];
+        let state = registry
+            .get(handle, body.user_data)
+            .unwrap()
+            .state(collider, body);
+        assert_eq!(state.id, meta.id);
+        assert_eq!(state.entity_type, *entity_type);
+        assert_eq!(state.texture, meta.texture);
+        assert_eq!(state.animate, meta.animate);
+        assert_eq!(state.translation, (12.0, -34.0));
+        assert_eq!(state.linvel, (5.0, 6.0));
+    }
+    assert_eq!(registry.len(), 5);
+}
+
+#[test]
+fn test_remove_and_reuse() {
+    let mut bodies = RigidBodySet::new();
+    let mut colliders = ColliderSet::new();
+    let mut joints = JointSet::new();
+    let mut islands = IslandManager::new();
+    let mut registry = EntityRegistry::new();
+
+    let first = registry.insert_body(
+        &mut bodies,
+        RigidBodyBuilder::new_dynamic().build(),
+        meta_of(EntityType::Skill),
+    );
+    let user_data = bodies[first].user_data;
+    assert_eq!(
+        registry.remove_body(
+            first,
+            &mut bodies,
+            &mut islands,
+            &mut colliders,
+            &mut joints
+        ),
+        Some(meta_of(EntityType::Skill))
+    );
+    assert!(registry.is_empty());
+
+    // 下标被复用后, 旧句柄不能再取到新实体
+    let second = registry.insert_body(
+        &mut bodies,
+        RigidBodyBuilder::new_dynamic().build(),
+        meta_of(EntityType::Trap),
+    );
+    assert_eq!(bodies[second].user_data, user_data);
+    assert_eq!(registry.get(first, user_data), None);
+    assert_eq!(
+        registry.get(second, user_data),
+        Some(&meta_of(EntityType::Trap))
+    );
+
+    // 未注册的刚体(地形)
+    let terrain = bodies.insert(RigidBodyBuilder::new_static().build());
+    assert_eq!(registry.meta(&bodies, terrain), None);
+}
+
+#[test]
+fn test_enter_twice() {
+    let mut bodies = RigidBodySet::new();
+    let mut colliders = ColliderSet::new();
+    let mut joints = JointSet::new();
+    let mut islands = IslandManager::new();
+    let mut registry = EntityRegistry::new();
+    let mut player_handle_map = HashMap::new();
+    let mut meta = meta_of(EntityType::Player);
+    meta.id = 7;
+
+    // 同一uid登录两次
+    for _ in 0..2 {
+        registry.remove_player(
+            &mut player_handle_map,
+            7,
+            &mut bodies,
+            &mut islands,
+            &mut colliders,
+            &mut joints,
+        );
+        let handle =
+            registry.insert_body(&mut bodies, RigidBodyBuilder::new_dynamic().build(), meta);
+        colliders.insert_with_parent(ColliderBuilder::ball(5.0).build(), handle, &mut bodies);
+        player_handle_map.insert(7, handle);
+    }
+    assert_eq!(bodies.len(), 1);
+    assert_eq!(colliders.len(), 1);
+    assert_eq!(registry.len(), 1);
+    assert_eq!(registry.meta(&bodies, player_handle_map[&7]), Some(meta));
+}
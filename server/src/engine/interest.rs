@@ -0,0 +1,177 @@
+use std::collections::{HashMap, HashSet};
+
+use protocol::data::update_data::{EntityState, EntityType};
+
+/// 空间网格单元边长
+pub const INTEREST_CELL: f32 = 512.0;
+
+/// 按坐标划分的空间网格, 存放实体在状态列表中的下标
+pub struct SpatialGrid {
+    cell: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell: f32, states: &[EntityState]) -> Self {
+        let mut cells: HashMap<(i32, i32), Vec<usize>> = HashMap::new();
+        for (index, state) in states.iter().enumerate() {
+            let key = (
+                (state.translation.0 / cell).floor() as i32,
+                (state.translation.1 / cell).floor() as i32,
+            );
+            cells.entry(key).or_default().push(index);
+        }
+        SpatialGrid { cell, cells }
+    }
+
+    /// 查找圆形范围内的实体下标(升序)
+    pub fn query(&self, states: &[EntityState], center: (f32, f32), radius: f32) -> Vec<usize> {
+        let min_x = ((center.0 - radius) / self.cell).floor() as i32;
+        let max_x = ((center.0 + radius) / self.cell).floor() as i32;
+        let min_y = ((center.1 - radius) / self.cell).floor() as i32;
+        let max_y = ((center.1 + radius) / self.cell).floor() as i32;
+        let mut result = Vec::new();
+        for x in min_x..=max_x {
+            for y in min_y..=max_y {
+                if let Some(indices) = self.cells.get(&(x, y)) {
+                    for index in indices {
+                        let state = &states[*index];
+                        let dx = state.translation.0 - center.0;
+                        let dy = state.translation.1 - center.1;
+                        if dx * dx + dy * dy <= radius * radius {
+                            result.push(*index);
+                        }
+                    }
+                }
+            }
+        }
+        result.sort_unstable();
+        result
+    }
+}
+
+/// 单个玩家本帧的可见结果
+pub struct Interest {
+    pub uid: u32,
+    pub states: Vec<EntityState>,
+    pub entered: Vec<(EntityType, u64)>,
+    pub left: Vec<(EntityType, u64)>,
+}
+
+impl Interest {
+    pub fn is_visible(&self, entity_type: EntityType, id: u64) -> bool {
+        self.states
+            .iter()
+            .any(|state| state.entity_type == entity_type && state.id == id)
+    }
+}
+
+/// 视野管理: 记录每个玩家上一帧可见的实体, 计算进入/离开视野的变化
+pub struct InterestManager {
+    pub radius: f32,
+    visible: HashMap<u32, HashSet<(EntityType, u64)>>,
+}
+
+impl InterestManager {
+    pub fn new(radius: f32) -> Self {
+        InterestManager {
+            radius,
+            visible: HashMap::new(),
+        }
+    }
+
+    /// viewers: 在线玩家uid及其坐标, 不在列表中的玩家清除视野记录
+    pub fn update(
+        &mut self,
+        states: &[EntityState],
+        viewers: &[(u32, (f32, f32))],
+    ) -> Vec<Interest> {
+        let grid = SpatialGrid::new(INTEREST_CELL, states);
+        let mut result = Vec::new();
+        for (uid, center) in viewers {
+            let states: Vec<EntityState> = grid
+                .query(states, *center, self.radius)
+                .into_iter()
+                .map(|index| states[index])
+                .collect();
+            let now: HashSet<(EntityType, u64)> = states
+                .iter()
+                .map(|state| (state.entity_type, state.id))
+                .collect();
+            let old = self.visible.remove(uid).unwrap_or_default();
+            let entered = now.difference(&old).cloned().collect();
+            let left = old.difference(&now).cloned().collect();
+            self.visible.insert(*uid, now);
+            result.push(Interest {
+                uid: *uid,
+                states,
+                entered,
+                left,
+            });
+        }
+        self.visible
+            .retain(|uid, _| viewers.iter().any(|(viewer, _)| viewer == uid));
+        result
+    }
+}
+
+#[cfg(test)]
+fn entity(id: u64, entity_type: EntityType, x: f32, y: f32) -> EntityState {
+    EntityState {
+        id,
+        translation: (x, y),
+        rotation: 0.,
+        linvel: (0., 0.),
+        angvel: (0., 0.),
+        texture: (0, 0, 0),
+        entity_type,
+        animate: 0,
+    }
+}
+
+#[test]
+fn test_grid_query() {
+    let states = vec![
+        entity(1, EntityType::Trap, 0., 0.),
+        entity(2, EntityType::Trap, 600., 0.),
+        entity(3, EntityType::Trap, -700., -700.),
+        entity(4, EntityType::Trap, 3000., 3000.),
+    ];
+    let grid = SpatialGrid::new(INTEREST_CELL, &states);
+    assert_eq!(grid.query(&states, (0., 0.), 1000.), vec![0, 1, 2]);
+    assert_eq!(grid.query(&states, (0., 0.), 650.), vec![0, 1]);
+    assert_eq!(grid.query(&states, (3000., 2900.), 200.), vec![3]);
+}
+
+#[test]
+fn test_enter_leave() {
+    let mut manager = InterestManager::new(1000.);
+    let mut states = vec![
+        entity(1, EntityType::Player, 0., 0.),
+        entity(7, EntityType::Trap, 500., 0.),
+        entity(8, EntityType::Trap, 5000., 0.),
+    ];
+
+    let interest = manager.update(&states, &[(1, (0., 0.))]);
+    assert_eq!(interest[0].states.len(), 2);
+    assert_eq!(interest[0].entered.len(), 2);
+    assert!(interest[0].is_visible(EntityType::Trap, 7));
+    assert!(!interest[0].is_visible(EntityType::Trap, 8));
+
+    // 陷阱离开视野, 远处陷阱进入视野
+    states[1].translation = (-5000., 0.);
+    states[2].translation = (900., 0.);
+    let interest = manager.update(&states, &[(1, (0., 0.))]);
+    assert_eq!(interest[0].entered, vec![(EntityType::Trap, 8)]);
+    assert_eq!(interest[0].left, vec![(EntityType::Trap, 7)]);
+
+    // 被移除的实体同样通知离开
+    states.remove(2);
+    let interest = manager.update(&states, &[(1, (0., 0.))]);
+    assert_eq!(interest[0].left, vec![(EntityType::Trap, 8)]);
+
+    // 下线玩家的视野记录被清除, 重新上线时全部重新进入
+    manager.update(&states, &[]);
+    let interest = manager.update(&states, &[(1, (0., 0.))]);
+    assert_eq!(interest[0].entered, vec![(EntityType::Player, 1)]);
+}
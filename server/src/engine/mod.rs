@@ -1 +1,11 @@
+pub mod chunk_stream;
+pub mod combat;
 pub mod engine_server;
+pub mod entity_registry;
+pub mod interest;
+pub mod player_store;
+pub mod skill;
+pub mod team;
+pub mod terrain;
+pub mod tick;
+pub mod validation;
\ No newline at end of file
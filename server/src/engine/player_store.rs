@@ -0,0 +1,89 @@
+use std::collections::{HashMap, HashSet};
+
+use protocol::data::player_data::PlayerData;
+
+/// 房间内在线玩家的血量和魔力值
+/// 模拟帧内只读写内存, 有变化的玩家由主循环释放世界锁后批量写入数据库
+#[derive(Debug, Default)]
+pub struct PlayerStore {
+    players: HashMap<u32, PlayerData>,
+    // 有变化尚未写入数据库的玩家
+    dirty: HashSet<u32>,
+}
+
+impl PlayerStore {
+    pub fn new() -> Self {
+        PlayerStore::default()
+    }
+
+    /// 玩家进入房间时载入数据库中的数据
+    pub fn load(&mut self, player: PlayerData) {
+        self.dirty.remove(&player.uid);
+        self.players.insert(player.uid, player);
+    }
+
+    /// 玩家离开房间, 返回最新的数据由调用方写入数据库
+    pub fn remove(&mut self, uid: u32) -> Option<PlayerData> {
+        self.dirty.remove(&uid);
+        self.players.remove(&uid)
+    }
+
+    pub fn find(&self, uid: u32) -> Option<PlayerData> {
+        self.players.get(&uid).copied()
+    }
+
+    /// 更新在线玩家的数据并标记为待写入, 已离开的玩家忽略
+    pub fn save(&mut self, player: PlayerData) {
+        if let Some(old) = self.players.get_mut(&player.uid) {
+            *old = player;
+            self.dirty.insert(player.uid);
+        }
+    }
+
+    /// 取出待写入数据库的玩家数据
+    pub fn take_dirty(&mut self) -> Vec<PlayerData> {
+        let players = &self.players;
+        self.dirty
+            .drain()
+            .filter_map(|uid| players.get(&uid).copied())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+fn player(uid: u32, hp: u32) -> PlayerData {
+    PlayerData {
+        uid,
+        hp,
+        mp: 0,
+        max_hp: 100,
+        max_mp: 100,
+    }
+}
+
+#[test]
+fn test_player_store() {
+    let mut store = PlayerStore::new();
+    store.load(player(1, 100));
+    store.load(player(2, 100));
+    // 载入的数据不需要写回
+    assert!(store.take_dirty().is_empty());
+
+    let mut victim = store.find(1).unwrap();
+    victim.hp = 40;
+    store.save(victim);
+    store.save(player(1, 30));
+    assert_eq!(store.find(1).unwrap().hp, 30);
+    // 同一玩家多次变化只写入最新的数据
+    let dirty = store.take_dirty();
+    assert_eq!(dirty.len(), 1);
+    assert_eq!((dirty[0].uid, dirty[0].hp), (1, 30));
+    assert!(store.take_dirty().is_empty());
+
+    // 离开的玩家交给调用方写入, 之后的更新忽略
+    store.save(player(2, 10));
+    assert_eq!(store.remove(2).map(|player| player.hp), Some(10));
+    store.save(player(2, 0));
+    assert_eq!(store.find(2).map(|player| player.hp), None);
+    assert!(store.take_dirty().is_empty());
+}
@@ -0,0 +1,365 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+use common::skill::{SkillDefinition, SkillShape};
+use protocol::data::{
+    player_data::PlayerData,
+    skill_data::{CastResult, CooldownData, SkillType},
+};
+
+/// 冷却容差, 网络抖动导致提前到达的施法在容差内仍然接受
+pub const COOLDOWN_TOLERANCE: Duration = Duration::from_millis(50);
+
+/// 玩家技能冷却, 由服务器维护, 客户端只用于显示
+#[derive(Default)]
+pub struct SkillCooldowns {
+    // (玩家uid, 技能) -> 冷却结束时刻
+    ready_at: HashMap<(u32, SkillType), Instant>,
+}
+
+impl SkillCooldowns {
+    pub fn new() -> Self {
+        SkillCooldowns::default()
+    }
+
+    /// 剩余冷却时间
+    pub fn remaining(&self, uid: u32, skill_type: SkillType, now: Instant) -> Duration {
+        self.ready_at
+            .get(&(uid, skill_type))
+            .map_or(Duration::ZERO, |ready_at| {
+                ready_at.saturating_duration_since(now)
+            })
+    }
+
+    /// 尝试释放技能, 冷却结束且魔力足够时扣除魔力并开始冷却
+    pub fn try_cast(
+        &mut self,
+        player: &mut PlayerData,
+        skill: &SkillDefinition,
+        now: Instant,
+    ) -> CooldownData {
+        let skill_type = skill.skill_type;
+        let cooldown = Duration::from_millis(skill.cooldown as u64);
+        let remaining = self.remaining(player.uid, skill_type, now);
+        let result = if remaining > COOLDOWN_TOLERANCE {
+            CastResult::Cooldown
+        } else if player.mp < skill.mp_cost {
+            CastResult::NotEnoughMp
+        } else {
+            player.mp -= skill.mp_cost;
+            // 从上次冷却结束时刻起算, 提前到达的施法不会累积优势
+            self.ready_at
+                .insert((player.uid, skill_type), now + remaining + cooldown);
+            CastResult::Cast
+        };
+        CooldownData {
+            skill_type,
+            result,
+            remaining: self.remaining(player.uid, skill_type, now).as_millis() as u32,
+            cooldown: skill.cooldown,
+            mp: player.mp,
+        }
+    }
+
+    /// 玩家离开房间时清除冷却
+    pub fn remove(&mut self, uid: u32) {
+        self.ready_at.retain(|(id, _), _| *id != uid);
+    }
+}
+
+/// 场景中的技能实体
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkillEntity {
+    pub skill_type: SkillType,
+    // 施法者及其队伍
+    pub owner: u32,
+    pub team: u32,
+    pub expires_at: Instant,
+    // 生成位置与最大飞行距离, 非弹道不限距离
+    pub origin: (f32, f32),
+    pub range: f32,
+}
+
+impl SkillEntity {
+    pub fn new(
+        skill: &SkillDefinition,
+        owner: u32,
+        team: u32,
+        origin: (f32, f32),
+        now: Instant,
+    ) -> Self {
+        let range = match skill.shape {
+            SkillShape::Projectile { range, .. } => range,
+            _ => f32::INFINITY,
+        };
+        SkillEntity {
+            skill_type: skill.skill_type,
+            owner,
+            team,
+            expires_at: now + Duration::from_millis(skill.lifetime as u64),
+            origin,
+            range,
+        }
+    }
+
+    /// 存在时长已到或飞行超过最大距离
+    pub fn is_expired(&self, position: (f32, f32), now: Instant) -> bool {
+        let (dx, dy) = (position.0 - self.origin.0, position.1 - self.origin.1);
+        self.expires_at <= now || dx * dx + dy * dy > self.range * self.range
+    }
+}
+
+/// 冲刺中的玩家
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Dash {
+    ends_at: Instant,
+    // 冲刺结束后恢复的移动速度
+    resume: (f32, f32),
+}
+
+/// 持续一段时间的技能效果: 技能实体的存在时长和玩家冲刺
+/// K为技能实体的标识, 引擎中为刚体句柄
+pub struct SkillEffects<K> {
+    entities: HashMap<K, SkillEntity>,
+    dashes: HashMap<u32, Dash>,
+}
+
+impl<K> Default for SkillEffects<K> {
+    fn default() -> Self {
+        SkillEffects {
+            entities: HashMap::new(),
+            dashes: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Hash + Eq + Copy> SkillEffects<K> {
+    pub fn new() -> Self {
+        SkillEffects::default()
+    }
+
+    pub fn insert_entity(&mut self, key: K, entity: SkillEntity) {
+        self.entities.insert(key, entity);
+    }
+
+    pub fn entity(&self, key: K) -> Option<SkillEntity> {
+        self.entities.get(&key).cloned()
+    }
+
+    pub fn remove_entity(&mut self, key: K) -> Option<SkillEntity> {
+        self.entities.remove(&key)
+    }
+
+    /// 取出到期或超出飞行距离的技能实体, position为实体当前位置, 已不存在的实体也一并取出
+    pub fn expired_entities<F: Fn(K) -> Option<(f32, f32)>>(
+        &mut self,
+        now: Instant,
+        position: F,
+    ) -> Vec<K> {
+        let expired: Vec<K> = self
+            .entities
+            .iter()
+            .filter(|(key, entity)| {
+                position(**key).is_none_or(|position| entity.is_expired(position, now))
+            })
+            .map(|(key, _)| *key)
+            .collect();
+        for key in expired.iter() {
+            self.entities.remove(key);
+        }
+        expired
+    }
+
+    /// 开始冲刺, resume为冲刺前的移动速度
+    pub fn start_dash(&mut self, uid: u32, duration: u32, resume: (f32, f32), now: Instant) {
+        // 连续冲刺时恢复第一次冲刺前的速度
+        let resume = self.dashes.get(&uid).map_or(resume, |dash| dash.resume);
+        self.dashes.insert(
+            uid,
+            Dash {
+                ends_at: now + Duration::from_millis(duration as u64),
+                resume,
+            },
+        );
+    }
+
+    /// 冲刺中收到的移动输入在冲刺结束后生效, 返回true表示输入已暂存
+    pub fn defer_input(&mut self, uid: u32, velocity: (f32, f32)) -> bool {
+        match self.dashes.get_mut(&uid) {
+            Some(dash) => {
+                dash.resume = velocity;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 取出结束的冲刺, 返回(玩家uid, 恢复的移动速度)
+    pub fn finished_dashes(&mut self, now: Instant) -> Vec<(u32, (f32, f32))> {
+        let finished: Vec<(u32, (f32, f32))> = self
+            .dashes
+            .iter()
+            .filter(|(_, dash)| dash.ends_at <= now)
+            .map(|(uid, dash)| (*uid, dash.resume))
+            .collect();
+        for (uid, _) in finished.iter() {
+            self.dashes.remove(uid);
+        }
+        finished
+    }
+
+    /// 玩家离开房间时取消冲刺
+    pub fn remove_player(&mut self, uid: u32) {
+        self.dashes.remove(&uid);
+    }
+}
+
+/// 目标是否在扇形内, direction为单位向量, angle为扇形总角度(度)
+pub fn in_arc(
+    origin: (f32, f32),
+    direction: (f32, f32),
+    target: (f32, f32),
+    range: f32,
+    angle: f32,
+) -> bool {
+    let (dx, dy) = (target.0 - origin.0, target.1 - origin.1);
+    let distance = (dx * dx + dy * dy).sqrt();
+    if distance > range {
+        return false;
+    }
+    if distance == 0. {
+        return true;
+    }
+    let cos = (dx * direction.0 + dy * direction.1) / distance;
+    cos >= (angle.to_radians() / 2.).cos()
+}
+
+/// 目标是否在圆形范围内
+pub fn in_radius(center: (f32, f32), target: (f32, f32), radius: f32) -> bool {
+    let (dx, dy) = (target.0 - center.0, target.1 - center.1);
+    dx * dx + dy * dy <= radius * radius
+}
+
+/// 恢复魔力值, 不超过上限, 有变化时返回true
+pub fn regenerate_mp(player: &mut PlayerData, amount: u32) -> bool {
+    let mp = player.mp.saturating_add(amount).min(player.max_mp);
+    let changed = mp != player.mp;
+    player.mp = mp;
+    changed
+}
+
+#[cfg(test)]
+fn player(mp: u32) -> PlayerData {
+    PlayerData {
+        uid: 1,
+        hp: 100,
+        mp,
+        max_hp: 100,
+        max_mp: 100,
+    }
+}
+
+#[test]
+fn test_try_cast() {
+    let skill = common::skill::SkillBook::default()
+        .get(SkillType::Shot)
+        .unwrap()
+        .clone();
+    let cooldown = Duration::from_millis(skill.cooldown as u64);
+    let mut cooldowns = SkillCooldowns::new();
+    let mut player = player(skill.mp_cost * 2);
+    let now = Instant::now();
+
+    let cast = cooldowns.try_cast(&mut player, &skill, now);
+    assert_eq!(cast.result, CastResult::Cast);
+    assert_eq!(cast.remaining, skill.cooldown);
+    assert_eq!(player.mp, skill.mp_cost);
+    // 冷却中不扣魔力
+    let cast = cooldowns.try_cast(&mut player, &skill, now + cooldown / 2);
+    assert_eq!(cast.result, CastResult::Cooldown);
+    assert_eq!(player.mp, skill.mp_cost);
+    // 容差内提前到达仍然接受, 下次冷却从上次结束时刻起算
+    let early = now + cooldown - COOLDOWN_TOLERANCE;
+    let cast = cooldowns.try_cast(&mut player, &skill, early);
+    assert_eq!(cast.result, CastResult::Cast);
+    assert_eq!(
+        cooldowns.remaining(1, SkillType::Shot, now + cooldown),
+        cooldown
+    );
+    assert_eq!(player.mp, 0);
+    // 魔力不足
+    let cast = cooldowns.try_cast(&mut player, &skill, now + cooldown * 3);
+    assert_eq!(cast.result, CastResult::NotEnoughMp);
+    assert_eq!(cast.remaining, 0);
+
+    cooldowns.remove(1);
+    assert_eq!(cooldowns.remaining(1, SkillType::Shot, now), Duration::ZERO);
+}
+
+#[test]
+fn test_regenerate_mp() {
+    let mut player = player(95);
+    assert!(regenerate_mp(&mut player, 3));
+    assert_eq!(player.mp, 98);
+    assert!(regenerate_mp(&mut player, 3));
+    assert_eq!(player.mp, 100);
+    assert!(!regenerate_mp(&mut player, 3));
+}
+
+#[test]
+fn test_skill_effects() {
+    let book = common::skill::SkillBook::default();
+    let shot = book.get(SkillType::Shot).unwrap();
+    let melee = book.get(SkillType::MeleeArc).unwrap();
+    let mut effects = SkillEffects::new();
+    let now = Instant::now();
+    effects.insert_entity(1u32, SkillEntity::new(shot, 7, 0, (0., 0.), now));
+    effects.insert_entity(2u32, SkillEntity::new(melee, 7, 0, (0., 0.), now));
+    effects.insert_entity(3u32, SkillEntity::new(shot, 7, 0, (0., 0.), now));
+    effects.insert_entity(4u32, SkillEntity::new(shot, 7, 0, (0., 0.), now));
+    let later = now + Duration::from_millis(melee.lifetime as u64 / 2);
+    // 弹道1飞行超过最大距离, 实体4已被移除
+    let position = |key| match key {
+        1 => Some((1000., 1200.)),
+        4 => None,
+        _ => Some((100., 0.)),
+    };
+    let mut expired = effects.expired_entities(later, position);
+    expired.sort_unstable();
+    assert_eq!(expired, vec![1, 4]);
+    assert!(effects.entity(1).is_none());
+    assert_eq!(effects.entity(2).unwrap().skill_type, SkillType::MeleeArc);
+    assert_eq!(effects.entity(2).unwrap().owner, 7);
+    // 存在时长到期
+    let later = now + Duration::from_millis(melee.lifetime as u64);
+    assert_eq!(effects.expired_entities(later, position), vec![2]);
+    let later = now + Duration::from_millis(shot.lifetime as u64);
+    assert_eq!(effects.expired_entities(later, position), vec![3]);
+
+    // 冲刺期间的移动输入在冲刺结束后生效
+    assert!(!effects.defer_input(7, (1., 0.)));
+    effects.start_dash(7, 250, (0., 0.), now);
+    assert!(effects.defer_input(7, (100., 0.)));
+    let dashing = now + Duration::from_millis(200);
+    assert!(effects.finished_dashes(dashing).is_empty());
+    effects.start_dash(7, 50, (5., 0.), dashing);
+    assert_eq!(
+        effects.finished_dashes(now + Duration::from_millis(250)),
+        vec![(7, (100., 0.))]
+    );
+    assert!(!effects.defer_input(7, (1., 0.)));
+}
+
+#[test]
+fn test_skill_area() {
+    // 向右的120度扇形
+    assert!(in_arc((0., 0.), (1., 0.), (50., 0.), 80., 120.));
+    assert!(in_arc((0., 0.), (1., 0.), (30., 40.), 80., 120.));
+    assert!(!in_arc((0., 0.), (1., 0.), (-50., 0.), 80., 120.));
+    assert!(!in_arc((0., 0.), (1., 0.), (10., 50.), 80., 120.));
+    assert!(!in_arc((0., 0.), (1., 0.), (90., 0.), 80., 120.));
+    assert!(in_arc((0., 0.), (1., 0.), (-50., 0.), 80., 360.));
+    assert!(in_radius((10., 10.), (13., 14.), 5.));
+    assert!(!in_radius((10., 10.), (14., 14.), 5.));
+}
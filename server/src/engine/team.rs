@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+/// 技能实体的碰撞分组位
+pub const SKILL_GROUP: u32 = 1 << 31;
+/// 玩家碰撞分组的位数, 每个玩家占用一位, 人数超出时共用
+pub const PLAYER_SLOTS: u32 = 31;
+
+/// 房间内的玩家
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Member {
+    team: u32,
+    // 碰撞分组位
+    slot: u32,
+}
+
+/// 队伍分配与友军伤害规则
+/// 玩家的碰撞体各占一个分组位, 技能实体通过碰撞分组忽略施法者(及不允许伤害的队友)
+pub struct Teams {
+    // 0为各自为战, 所有玩家队伍为0且互为敌人
+    team_count: u32,
+    friendly_fire: bool,
+    members: HashMap<u32, Member>,
+}
+
+impl Teams {
+    pub fn new(team_count: u32, friendly_fire: bool) -> Self {
+        Teams {
+            team_count,
+            friendly_fire,
+            members: HashMap::new(),
+        }
+    }
+
+    /// 玩家加入, 分配人数最少的队伍和空闲的碰撞分组位, 返回队伍
+    pub fn join(&mut self, uid: u32) -> u32 {
+        if let Some(member) = self.members.get(&uid) {
+            return member.team;
+        }
+        let team = (0..self.team_count.max(1))
+            .min_by_key(|team| {
+                self.members
+                    .values()
+                    .filter(|member| member.team == *team)
+                    .count()
+            })
+            .unwrap_or(0);
+        let slot = (0..PLAYER_SLOTS)
+            .find(|slot| self.members.values().all(|member| member.slot != *slot))
+            .unwrap_or(uid % PLAYER_SLOTS);
+        self.members.insert(uid, Member { team, slot });
+        team
+    }
+
+    pub fn leave(&mut self, uid: u32) {
+        self.members.remove(&uid);
+    }
+
+    pub fn team(&self, uid: u32) -> Option<u32> {
+        self.members.get(&uid).map(|member| member.team)
+    }
+
+    /// 是否为队友, 各自为战时没有队友
+    pub fn is_teammate(&self, a: u32, b: u32) -> bool {
+        self.team_count > 0
+            && a != b
+            && matches!((self.team(a), self.team(b)), (Some(a), Some(b)) if a == b)
+    }
+
+    /// 攻击者能否伤害目标: 技能不伤害施法者, 不允许友军伤害时不伤害队友
+    pub fn can_damage(&self, attacker: Option<u32>, target: u32) -> bool {
+        match attacker {
+            Some(attacker) if attacker == target => false,
+            Some(attacker) => self.friendly_fire || !self.is_teammate(attacker, target),
+            None => true,
+        }
+    }
+
+    /// 玩家碰撞体所属的碰撞分组
+    pub fn player_membership(&self, uid: u32) -> u32 {
+        self.members.get(&uid).map_or(0, |member| 1 << member.slot)
+    }
+
+    /// 技能实体的碰撞过滤, 不与施法者和不能伤害的队友碰撞
+    pub fn skill_filter(&self, owner: u32) -> u32 {
+        self.members
+            .iter()
+            .filter(|(uid, _)| !self.can_damage(Some(owner), **uid))
+            .fold(u32::MAX, |filter, (_, member)| filter & !(1 << member.slot))
+    }
+}
+
+#[test]
+fn test_teams() {
+    let mut teams = Teams::new(2, false);
+    assert_eq!(teams.join(1), 0);
+    assert_eq!(teams.join(2), 1);
+    assert_eq!(teams.join(3), 0);
+    // 重复加入保持原队伍
+    assert_eq!(teams.join(1), 0);
+    assert!(teams.is_teammate(1, 3));
+    assert!(!teams.is_teammate(1, 2));
+    // 不伤害自己和队友, 陷阱伤害所有人
+    assert!(!teams.can_damage(Some(1), 1));
+    assert!(!teams.can_damage(Some(1), 3));
+    assert!(teams.can_damage(Some(1), 2));
+    assert!(teams.can_damage(None, 1));
+
+    // 技能实体不与施法者和队友碰撞
+    let filter = teams.skill_filter(1);
+    assert_eq!(filter & teams.player_membership(1), 0);
+    assert_eq!(filter & teams.player_membership(3), 0);
+    assert_ne!(filter & teams.player_membership(2), 0);
+    assert_ne!(filter & SKILL_GROUP, 0);
+
+    // 离开后释放分组位, 人数少的队伍优先
+    teams.leave(1);
+    teams.leave(3);
+    assert_eq!(teams.team(1), None);
+    assert_eq!(teams.join(4), 0);
+    assert_eq!(teams.player_membership(4), 1);
+}
+
+#[test]
+fn test_friendly_fire() {
+    let mut teams = Teams::new(2, true);
+    teams.join(1);
+    teams.join(2);
+    teams.join(3);
+    assert!(teams.can_damage(Some(1), 3));
+    assert!(!teams.can_damage(Some(1), 1));
+    let filter = teams.skill_filter(1);
+    assert_eq!(filter & teams.player_membership(1), 0);
+    assert_ne!(filter & teams.player_membership(3), 0);
+
+    // 各自为战时所有人都是敌人
+    let mut teams = Teams::new(0, false);
+    teams.join(1);
+    teams.join(2);
+    assert!(!teams.is_teammate(1, 2));
+    assert!(teams.can_damage(Some(1), 2));
+}
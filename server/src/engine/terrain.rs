@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+
+use common::tile_collider::TerrainGrid;
+use protocol::data::tile_map_data::TileCollider;
+use rapier2d::prelude::*;
+
+/// 按区块合并的地形碰撞体: 每个区块一个静态刚体, 相邻的实心区域合并为一个长方体
+/// 刚体位于原点, 碰撞体使用世界坐标, 不会被过界清理移除
+#[derive(Default)]
+pub struct TerrainColliders {
+    grid: TerrainGrid,
+    // 区块 -> 刚体
+    chunk_bodies: HashMap<(i32, i32), RigidBodyHandle>,
+}
+
+impl TerrainColliders {
+    pub fn new() -> Self {
+        TerrainColliders::default()
+    }
+
+    /// 设置地块碰撞体, 在下一次rebuild时重建所在区块
+    pub fn set_tile(&mut self, point: (i32, i32, i32), collider: TileCollider) {
+        self.grid.set(point, collider);
+    }
+
+    /// 卸载区块, 在下一次rebuild时移除区块的刚体
+    pub fn unload_chunk(&mut self, chunk: (i32, i32)) {
+        self.grid.remove_chunk(chunk);
+    }
+
+    /// 重建有变化的区块, 返回重建的区块数
+    pub fn rebuild(
+        &mut self,
+        bodies: &mut RigidBodySet,
+        colliders: &mut ColliderSet,
+        islands: &mut IslandManager,
+        joints: &mut JointSet,
+    ) -> usize {
+        let dirty = self.grid.take_dirty();
+        for chunk in dirty.iter() {
+            if let Some(handle) = self.chunk_bodies.remove(chunk) {
+                bodies.remove(handle, islands, colliders, joints);
+            }
+            let rects = self.grid.chunk_rects(*chunk);
+            if rects.is_empty() {
+                continue;
+            }
+            let handle = bodies.insert(RigidBodyBuilder::new(RigidBodyType::Static).build());
+            for rect in rects {
+                let (half_x, half_y) = rect.half_extents;
+                let collider = ColliderBuilder::new(SharedShape::cuboid(half_x, half_y))
+                    .translation(vector![rect.center.0, rect.center.1])
+                    // 密度
+                    .density(0.1)
+                    // 摩擦
+                    .friction(0.0)
+                    .build();
+                colliders.insert_with_parent(collider, handle, bodies);
+            }
+            self.chunk_bodies.insert(*chunk, handle);
+        }
+        dirty.len()
+    }
+
+    /// 地形占用的刚体数
+    pub fn body_count(&self) -> usize {
+        self.chunk_bodies.len()
+    }
+}
+
+#[cfg(test)]
+struct TestWorld {
+    bodies: RigidBodySet,
+    colliders: ColliderSet,
+    islands: IslandManager,
+    joints: JointSet,
+}
+
+#[cfg(test)]
+impl TestWorld {
+    fn new() -> Self {
+        TestWorld {
+            bodies: RigidBodySet::new(),
+            colliders: ColliderSet::new(),
+            islands: IslandManager::new(),
+            joints: JointSet::new(),
+        }
+    }
+
+    fn rebuild(&mut self, terrain: &mut TerrainColliders) -> usize {
+        terrain.rebuild(
+            &mut self.bodies,
+            &mut self.colliders,
+            &mut self.islands,
+            &mut self.joints,
+        )
+    }
+
+    /// 推进frames帧, 返回平均每帧耗时
+    fn step(&mut self, frames: u32) -> std::time::Duration {
+        let mut pipeline = PhysicsPipeline::new();
+        let mut broad_phase = BroadPhase::new();
+        let mut narrow_phase = NarrowPhase::new();
+        let mut ccd_solver = CCDSolver::new();
+        let integration_parameters = IntegrationParameters::default();
+        let start = std::time::Instant::now();
+        for _ in 0..frames {
+            pipeline.step(
+                &vector![0.0, 0.0],
+                &integration_parameters,
+                &mut self.islands,
+                &mut broad_phase,
+                &mut narrow_phase,
+                &mut self.bodies,
+                &mut self.colliders,
+                &mut self.joints,
+                &mut ccd_solver,
+                &(),
+                &(),
+            );
+        }
+        start.elapsed() / frames
+    }
+}
+
+#[test]
+fn test_rebuild_chunks() {
+    let mut world = TestWorld::new();
+    let mut terrain = TerrainColliders::new();
+    for x in 0..4 {
+        terrain.set_tile((x, 0, 1), TileCollider::Full);
+    }
+    terrain.set_tile((20, 0, 1), TileCollider::HalfTop);
+    assert_eq!(world.rebuild(&mut terrain), 2);
+    assert_eq!(terrain.body_count(), 2);
+    // 一行实心地块合并为一个碰撞体
+    assert_eq!(world.bodies.len(), 2);
+    assert_eq!(world.colliders.len(), 2);
+
+    // 没有变化时不重建
+    assert_eq!(world.rebuild(&mut terrain), 0);
+    // 只重建变化的区块, 中间断开后拆为两个碰撞体
+    terrain.set_tile((1, 0, 1), TileCollider::None);
+    assert_eq!(world.rebuild(&mut terrain), 1);
+    assert_eq!(world.bodies.len(), 2);
+    assert_eq!(world.colliders.len(), 3);
+    // 区块清空后移除刚体
+    terrain.set_tile((20, 0, 1), TileCollider::None);
+    assert_eq!(world.rebuild(&mut terrain), 1);
+    assert_eq!(terrain.body_count(), 1);
+    assert_eq!(world.colliders.len(), 2);
+    // 卸载区块后移除刚体和碰撞体
+    terrain.unload_chunk((0, 0));
+    assert_eq!(world.rebuild(&mut terrain), 1);
+    assert_eq!(terrain.body_count(), 0);
+    assert_eq!(world.bodies.len(), 0);
+    assert_eq!(world.colliders.len(), 0);
+}
+
+/// 对比逐地块生成和按区块合并的刚体数与每帧耗时
+/// cargo test -p server --release bench_terrain_colliders -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_terrain_colliders() {
+    // 边界加上棋盘式分布的墙
+    let mut tiles = Vec::new();
+    for x in -58..=58 {
+        for y in -58..=58 {
+            let border = x == -58 || x == 58 || y == -58 || y == 58;
+            let wall = (x % 8 == 0 && y % 3 != 0) || (y % 8 == 0 && x % 3 != 0);
+            if border || wall {
+                tiles.push((x, y));
+            }
+        }
+    }
+    // 在地形中放一些运动的球
+    let add_balls = |world: &mut TestWorld| {
+        for i in 0..200 {
+            let body = RigidBodyBuilder::new_dynamic()
+                .translation(vector![
+                    (i % 20) as f32 * 300. - 3000.,
+                    (i / 20) as f32 * 600. - 3000.
+                ])
+                .linvel(vector![200.0, 150.0])
+                .build();
+            let handle = world.bodies.insert(body);
+            world.colliders.insert_with_parent(
+                ColliderBuilder::ball(20.0).build(),
+                handle,
+                &mut world.bodies,
+            );
+        }
+    };
+
+    // 逐地块: 每个地块一个刚体和一个32x32的长方体
+    let mut per_tile = TestWorld::new();
+    for (x, y) in tiles.iter() {
+        let body = RigidBodyBuilder::new(RigidBodyType::Static)
+            .translation(vector![*x as f32 * 64., *y as f32 * 64.])
+            .build();
+        let handle = per_tile.bodies.insert(body);
+        per_tile.colliders.insert_with_parent(
+            ColliderBuilder::cuboid(32.0, 32.0).build(),
+            handle,
+            &mut per_tile.bodies,
+        );
+    }
+    let per_tile_counts = (per_tile.bodies.len(), per_tile.colliders.len());
+    add_balls(&mut per_tile);
+
+    // 按区块合并
+    let mut merged = TestWorld::new();
+    let mut terrain = TerrainColliders::new();
+    for (x, y) in tiles.iter() {
+        terrain.set_tile((*x, *y, 1), TileCollider::Full);
+    }
+    let start = std::time::Instant::now();
+    merged.rebuild(&mut terrain);
+    let build_time = start.elapsed();
+    let merged_counts = (merged.bodies.len(), merged.colliders.len());
+    add_balls(&mut merged);
+
+    let per_tile_step = per_tile.step(300);
+    let merged_step = merged.step(300);
+    println!(
+        "逐地块: 刚体{} 碰撞体{} 每帧{:?}",
+        per_tile_counts.0, per_tile_counts.1, per_tile_step
+    );
+    println!(
+        "按区块合并: 刚体{} 碰撞体{} 每帧{:?} 构建{:?}",
+        merged_counts.0, merged_counts.1, merged_step, build_time
+    );
+    assert!(merged_counts.0 < per_tile_counts.0);
+    assert!(merged_counts.1 < per_tile_counts.1);
+}
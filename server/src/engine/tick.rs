@@ -0,0 +1,176 @@
+use std::time::Duration;
+
+/// 固定步长调度器, 按实际流逝时间累积, 每满一个步长执行一次模拟
+pub struct FixedTimestep {
+    step: Duration,
+    accumulator: Duration,
+    // 单次最多补帧数, 超出部分丢弃, 避免落后时越补越慢
+    max_catch_up: u32,
+}
+
+impl FixedTimestep {
+    pub fn new(tick_rate: u32, max_catch_up: u32) -> Self {
+        FixedTimestep {
+            step: Duration::from_secs_f64(1f64 / tick_rate.max(1) as f64),
+            accumulator: Duration::ZERO,
+            max_catch_up: max_catch_up.max(1),
+        }
+    }
+
+    /// 单步时长
+    pub fn step(&self) -> Duration {
+        self.step
+    }
+
+    /// 累积流逝时间, 返回(本次需执行的步数, 被丢弃的步数)
+    pub fn advance(&mut self, elapsed: Duration) -> (u32, u32) {
+        self.accumulator += elapsed;
+        let mut steps = 0;
+        while self.accumulator >= self.step {
+            self.accumulator -= self.step;
+            steps += 1;
+        }
+        if steps > self.max_catch_up {
+            (self.max_catch_up, steps - self.max_catch_up)
+        } else {
+            (steps, 0)
+        }
+    }
+}
+
+/// 快照发送节奏, 每隔若干模拟帧发送一次
+pub struct SnapshotSchedule {
+    interval: u128,
+    last_frame: Option<u128>,
+}
+
+impl SnapshotSchedule {
+    pub fn new(tick_rate: u32, snapshot_rate: u32) -> Self {
+        let snapshot_rate = snapshot_rate.clamp(1, tick_rate.max(1));
+        SnapshotSchedule {
+            interval: (tick_rate.max(1) / snapshot_rate) as u128,
+            last_frame: None,
+        }
+    }
+
+    /// 到达发送间隔时返回true并记录本次帧号
+    pub fn due(&mut self, frame_no: u128) -> bool {
+        let due = match self.last_frame {
+            Some(last_frame) => frame_no >= last_frame + self.interval,
+            None => true,
+        };
+        if due {
+            self.last_frame = Some(frame_no);
+        }
+        due
+    }
+}
+
+/// 模拟帧耗时统计, 用于观察服务器是否跟不上帧率
+#[derive(Debug, Default)]
+pub struct TickStats {
+    pub ticks: u64,
+    pub total: Duration,
+    pub max: Duration,
+    // 耗时超过单步时长的帧数
+    pub overruns: u64,
+    // 一次调度中补跑多帧的次数
+    pub catch_ups: u64,
+    // 落后过多而丢弃的帧数
+    pub dropped: u64,
+}
+
+impl TickStats {
+    pub fn record(&mut self, duration: Duration, step: Duration) {
+        self.ticks += 1;
+        self.total += duration;
+        if duration > self.max {
+            self.max = duration;
+        }
+        if duration > step {
+            self.overruns += 1;
+        }
+    }
+
+    pub fn record_schedule(&mut self, steps: u32, dropped: u32) {
+        if steps > 1 {
+            self.catch_ups += 1;
+        }
+        self.dropped += dropped as u64;
+    }
+
+    pub fn average(&self) -> Duration {
+        if self.ticks == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.ticks as u32
+        }
+    }
+
+    /// 是否出现落后
+    pub fn is_behind(&self) -> bool {
+        self.overruns > 0 || self.dropped > 0
+    }
+
+    /// 输出统计并清零
+    pub fn report(&mut self) -> String {
+        let report = format!(
+            "帧数: {}, 平均耗时: {:?}, 最大耗时: {:?}, 超时: {}, 补帧: {}, 丢帧: {}",
+            self.ticks,
+            self.average(),
+            self.max,
+            self.overruns,
+            self.catch_ups,
+            self.dropped
+        );
+        *self = TickStats::default();
+        report
+    }
+}
+
+#[test]
+fn test_fixed_timestep() {
+    let mut timestep = FixedTimestep::new(50, 3);
+    assert_eq!(timestep.step(), Duration::from_millis(20));
+    // 不足一步时累积到下一次
+    assert_eq!(timestep.advance(Duration::from_millis(15)), (0, 0));
+    assert_eq!(timestep.advance(Duration::from_millis(15)), (1, 0));
+    assert_eq!(timestep.advance(Duration::from_millis(30)), (2, 0));
+    // 落后过多时只补跑上限帧数
+    assert_eq!(timestep.advance(Duration::from_millis(100)), (3, 2));
+    assert_eq!(timestep.advance(Duration::from_millis(0)), (0, 0));
+}
+
+#[test]
+fn test_snapshot_schedule() {
+    let mut schedule = SnapshotSchedule::new(60, 20);
+    let sent: Vec<u128> = (0..10).filter(|frame| schedule.due(*frame)).collect();
+    assert_eq!(sent, vec![0, 3, 6, 9]);
+    // 快照频率不能高于模拟频率
+    let mut schedule = SnapshotSchedule::new(30, 60);
+    assert!(schedule.due(0));
+    assert!(schedule.due(1));
+    // 补帧跳过整数倍时仍然发送
+    let mut schedule = SnapshotSchedule::new(60, 20);
+    assert!(schedule.due(0));
+    assert!(!schedule.due(2));
+    assert!(schedule.due(4));
+}
+
+#[test]
+fn test_tick_stats() {
+    let step = Duration::from_millis(10);
+    let mut stats = TickStats::default();
+    stats.record(Duration::from_millis(4), step);
+    stats.record(Duration::from_millis(12), step);
+    stats.record_schedule(3, 1);
+    assert_eq!(stats.average(), Duration::from_millis(8));
+    assert_eq!(stats.max, Duration::from_millis(12));
+    assert_eq!(stats.overruns, 1);
+    assert_eq!(stats.catch_ups, 1);
+    assert_eq!(stats.dropped, 1);
+    assert!(stats.is_behind());
+    stats.report();
+    assert_eq!(stats.ticks, 0);
+    assert!(!stats.is_behind());
+}
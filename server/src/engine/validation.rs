@@ -0,0 +1,257 @@
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use common::skill::SkillBook;
+use protocol::data::{control_data::ControlData, skill_data::SkillData};
+
+/// 每秒最多处理的输入数(移动与技能合计), 超出的输入丢弃
+pub const MAX_INPUTS_PER_SECOND: u32 = 60;
+/// 统计窗口内违规达到该次数的客户端被标记
+pub const FLAG_THRESHOLD: u32 = 20;
+/// 违规次数的统计窗口
+pub const VIOLATION_WINDOW: Duration = Duration::from_secs(60);
+
+/// 客户端输入违规类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// 方向不是有限数值, 或技能没有方向
+    InvalidDirection,
+    /// 未定义的动作
+    UnknownAction,
+    /// 技能表中没有定义的技能
+    UnknownSkill,
+    /// 死亡玩家发出的移动或技能
+    Dead,
+    /// 输入频率超过上限
+    InputFlood,
+}
+
+/// 方向归一化为单位向量, 零向量保持为零, 非有限数值视为违规
+pub fn sanitize_direction(direction: (f32, f32)) -> Result<(f32, f32), Violation> {
+    let (x, y) = direction;
+    if !x.is_finite() || !y.is_finite() {
+        return Err(Violation::InvalidDirection);
+    }
+    // 先缩放再求长度, 避免极大的分量平方后溢出
+    let scale = x.abs().max(y.abs());
+    if scale == 0. {
+        return Ok((0., 0.));
+    }
+    let (x, y) = (x / scale, y / scale);
+    let length = (x * x + y * y).sqrt();
+    Ok((x / length, y / length))
+}
+
+/// 校验移动输入, 返回服务器采用的输入
+/// 速度只由动作决定(见ControlData::velocity), 客户端无法通过方向长度加速
+pub fn validate_control(control: ControlData, alive: bool) -> Result<ControlData, Violation> {
+    if control.action > 2 {
+        return Err(Violation::UnknownAction);
+    }
+    // 死亡玩家只允许停止
+    if !alive && control.action != 0 {
+        return Err(Violation::Dead);
+    }
+    let direction = sanitize_direction(control.direction)?;
+    Ok(ControlData {
+        direction,
+        ..control
+    })
+}
+
+/// 校验技能输入, 方向归一化, 贴图按技能表填写, 忽略客户端填写的值
+pub fn validate_skill(
+    skill: SkillData,
+    alive: bool,
+    skill_book: &SkillBook,
+) -> Result<SkillData, Violation> {
+    if !alive {
+        return Err(Violation::Dead);
+    }
+    let definition = skill_book
+        .get(skill.skill_type)
+        .ok_or(Violation::UnknownSkill)?;
+    let direction = sanitize_direction(skill.direction)?;
+    if direction == (0., 0.) {
+        return Err(Violation::InvalidDirection);
+    }
+    Ok(SkillData {
+        direction,
+        texture: definition.texture,
+        ..skill
+    })
+}
+
+/// 单个客户端的输入统计
+struct ClientRecord {
+    // 当前秒的起点与输入数
+    second_start: Instant,
+    inputs: u32,
+    // 违规统计窗口的起点与违规数
+    window_start: Instant,
+    violations: u32,
+    flagged: bool,
+}
+
+impl ClientRecord {
+    fn new(now: Instant) -> Self {
+        ClientRecord {
+            second_start: now,
+            inputs: 0,
+            window_start: now,
+            violations: 0,
+            flagged: false,
+        }
+    }
+}
+
+/// 按客户端统计输入频率和违规次数, 反复违规的客户端被标记并记录日志
+#[derive(Default)]
+pub struct InputValidator {
+    clients: HashMap<u32, ClientRecord>,
+}
+
+impl InputValidator {
+    pub fn new() -> Self {
+        InputValidator::default()
+    }
+
+    /// 记录一次输入, 超过每秒上限时返回违规
+    pub fn check_rate(&mut self, uid: u32, now: Instant) -> Result<(), Violation> {
+        let record = self
+            .clients
+            .entry(uid)
+            .or_insert_with(|| ClientRecord::new(now));
+        if now.duration_since(record.second_start) >= Duration::from_secs(1) {
+            record.second_start = now;
+            record.inputs = 0;
+        }
+        record.inputs += 1;
+        if record.inputs > MAX_INPUTS_PER_SECOND {
+            Err(Violation::InputFlood)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// 记录一次违规, 窗口内违规达到阈值时标记客户端, 首次标记时返回true
+    pub fn record(&mut self, uid: u32, violation: Violation, now: Instant) -> bool {
+        let record = self
+            .clients
+            .entry(uid)
+            .or_insert_with(|| ClientRecord::new(now));
+        if now.duration_since(record.window_start) >= VIOLATION_WINDOW {
+            record.window_start = now;
+            record.violations = 0;
+        }
+        record.violations += 1;
+        if record.violations >= FLAG_THRESHOLD && !record.flagged {
+            record.flagged = true;
+            println!(
+                "玩家{}在{:?}内违规{}次, 标记为可疑客户端, 最近一次: {:?}",
+                uid, VIOLATION_WINDOW, record.violations, violation
+            );
+            return true;
+        }
+        false
+    }
+}
+
+#[test]
+fn test_sanitize_direction() {
+    assert_eq!(sanitize_direction((0., 0.)), Ok((0., 0.)));
+    assert_eq!(sanitize_direction((0., -5.)), Ok((0., -1.)));
+    let (x, y) = sanitize_direction((3e30, 4e30)).unwrap();
+    assert!((x - 0.6).abs() < 0.001 && (y - 0.8).abs() < 0.001);
+    assert_eq!(
+        sanitize_direction((f32::NAN, 1.)),
+        Err(Violation::InvalidDirection)
+    );
+    assert_eq!(
+        sanitize_direction((f32::INFINITY, 0.)),
+        Err(Violation::InvalidDirection)
+    );
+}
+
+#[cfg(test)]
+use protocol::data::skill_data::SkillType;
+
+#[test]
+fn test_validate_input() {
+    let control = |direction, action| ControlData {
+        uid: 1,
+        direction,
+        action,
+        seq: 1,
+    };
+    let valid = validate_control(control((0., 1000.), 2), true).unwrap();
+    assert_eq!(valid.direction, (0., 1.));
+    assert_eq!(
+        validate_control(control((1., 0.), 3), true).unwrap_err(),
+        Violation::UnknownAction
+    );
+    // 死亡玩家只能停止
+    assert!(validate_control(control((1., 0.), 0), false).is_ok());
+    assert_eq!(
+        validate_control(control((1., 0.), 1), false).unwrap_err(),
+        Violation::Dead
+    );
+
+    let skill_book = SkillBook::default();
+    let skill = SkillData {
+        uid: 1,
+        direction: (0., -3.),
+        skill_type: SkillType::Shot,
+        texture: (99, 99, 99),
+    };
+    let valid = validate_skill(skill, true, &skill_book).unwrap();
+    assert_eq!(valid.direction, (0., -1.));
+    assert_eq!(
+        valid.texture,
+        skill_book.get(SkillType::Shot).unwrap().texture
+    );
+    assert_eq!(
+        validate_skill(skill, false, &skill_book),
+        Err(Violation::Dead)
+    );
+    let zero = SkillData {
+        direction: (0., 0.),
+        ..skill
+    };
+    assert_eq!(
+        validate_skill(zero, true, &skill_book),
+        Err(Violation::InvalidDirection)
+    );
+    // 技能表中没有的技能不能释放
+    let skill_book = SkillBook::from_toml("test.toml", "skill = []").unwrap();
+    assert_eq!(
+        validate_skill(skill, true, &skill_book),
+        Err(Violation::UnknownSkill)
+    );
+}
+
+#[test]
+fn test_input_validator() {
+    let mut validator = InputValidator::new();
+    let now = Instant::now();
+    for _ in 0..MAX_INPUTS_PER_SECOND {
+        assert!(validator.check_rate(1, now).is_ok());
+    }
+    assert_eq!(validator.check_rate(1, now), Err(Violation::InputFlood));
+    // 下一秒重新计数
+    assert!(validator
+        .check_rate(1, now + Duration::from_secs(1))
+        .is_ok());
+
+    // 窗口过期后违规重新计数
+    for _ in 1..FLAG_THRESHOLD {
+        assert!(!validator.record(2, Violation::Dead, now));
+    }
+    assert!(!validator.record(2, Violation::Dead, now + VIOLATION_WINDOW));
+    for _ in 2..FLAG_THRESHOLD {
+        assert!(!validator.record(2, Violation::Dead, now + VIOLATION_WINDOW));
+    }
+    // 达到阈值时只标记一次
+    assert!(validator.record(2, Violation::Dead, now + VIOLATION_WINDOW));
+    assert!(!validator.record(2, Violation::Dead, now + VIOLATION_WINDOW));
+}
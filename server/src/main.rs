@@ -1,14 +1,35 @@
-use protocol::packet::Packet;
-use server::{engine::engine_server::engine_start, net::net_server::net_server_start};
+use server::{
+    engine::engine_server::{engine_start, EngineMessage},
+    net::net_server::{net_server_start, NetMessage},
+};
 use tokio::{runtime::Runtime, sync::mpsc};
 
 fn main() {
+    // 加载配置文件, 环境变量和命令行参数
+    let config = match config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    println!("服务器配置: {:?}", config);
+    // 加载技能定义
+    if let Err(e) = common::skill::load(&config.skills_file) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    // 加载地块定义
+    if let Err(e) = common::tileset::load(&config.tiles_file) {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
     let engine_runtime = Runtime::new().unwrap();
     let net_runtime = Runtime::new().unwrap();
     // let cli_runtime = Runtime::new().unwrap();
 
-    let (net_tx, net_rx) = mpsc::channel::<Packet>(100);
-    let (engine_tx, engine_rx) = mpsc::channel::<Packet>(100);
+    let (net_tx, net_rx) = mpsc::channel::<NetMessage>(100);
+    let (engine_tx, engine_rx) = mpsc::channel::<EngineMessage>(100);
 
     // cli模块
     // cli_runtime.spawn(async { cli::Cli::cli_start() });
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/// 统计窗口内同一ip允许的登录/注册次数
+pub const MAX_ADDR_ATTEMPTS: u32 = 20;
+/// 统计窗口内同一ip对同一用户名允许的登录/注册次数
+pub const MAX_USERNAME_ATTEMPTS: u32 = 5;
+/// 尝试次数的统计窗口
+pub const ATTEMPT_WINDOW: Duration = Duration::from_secs(60);
+
+/// 按key统计窗口内的尝试次数
+struct AttemptCounter<K> {
+    max: u32,
+    // key -> (窗口起点, 尝试次数)
+    attempts: HashMap<K, (Instant, u32)>,
+}
+
+impl<K: Hash + Eq> AttemptCounter<K> {
+    fn new(max: u32) -> Self {
+        AttemptCounter {
+            max,
+            attempts: HashMap::new(),
+        }
+    }
+
+    /// 记录一次尝试, 窗口内已达到上限时返回false且不计数
+    fn attempt(&mut self, key: K, now: Instant) -> bool {
+        self.attempts
+            .retain(|_, (window_start, _)| now.duration_since(*window_start) < ATTEMPT_WINDOW);
+        let (_, count) = self.attempts.entry(key).or_insert((now, 0));
+        if *count >= self.max {
+            return false;
+        }
+        *count += 1;
+        true
+    }
+}
+
+/// 登录与注册的频率限制, 同时按来源ip和(ip, 用户名)计数, 防止在线暴力破解密码
+/// 用户名的计数不跨ip共享, 其他人无法通过反复尝试锁定该账号
+pub struct LoginLimiter {
+    addrs: AttemptCounter<IpAddr>,
+    usernames: AttemptCounter<(IpAddr, String)>,
+}
+
+impl Default for LoginLimiter {
+    fn default() -> Self {
+        LoginLimiter {
+            addrs: AttemptCounter::new(MAX_ADDR_ATTEMPTS),
+            usernames: AttemptCounter::new(MAX_USERNAME_ATTEMPTS),
+        }
+    }
+}
+
+impl LoginLimiter {
+    pub fn new() -> Self {
+        LoginLimiter::default()
+    }
+
+    /// 记录一次登录或注册, 超过频率限制时返回false
+    pub fn attempt(&mut self, addr: IpAddr, username: &str, now: Instant) -> bool {
+        self.addrs.attempt(addr, now) && self.usernames.attempt((addr, username.to_string()), now)
+    }
+}
+
+#[test]
+fn test_login_limiter() {
+    let now = Instant::now();
+    let addr: IpAddr = "127.0.0.1".parse().unwrap();
+    let mut limiter = LoginLimiter::new();
+    for _ in 0..MAX_USERNAME_ATTEMPTS {
+        assert!(limiter.attempt(addr, "player_1", now));
+    }
+    assert!(!limiter.attempt(addr, "player_1", now));
+    // 其他用户名不受影响
+    assert!(limiter.attempt(addr, "player_2", now));
+    // 其他ip登录同一用户名不受影响
+    assert!(limiter.attempt("127.0.0.2".parse().unwrap(), "player_1", now));
+    // 窗口过后重新计数
+    let later = now + ATTEMPT_WINDOW;
+    assert!(limiter.attempt(addr, "player_1", later));
+
+    // 同一ip尝试过多用户名
+    let mut limiter = LoginLimiter::new();
+    for i in 0..MAX_ADDR_ATTEMPTS {
+        assert!(limiter.attempt(addr, &format!("player_{}", i), now));
+    }
+    assert!(!limiter.attempt(addr, "player_x", now));
+    assert!(limiter.attempt("127.0.0.2".parse().unwrap(), "player_x", now));
+}
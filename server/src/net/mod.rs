@@ -1 +1,2 @@
+pub mod limiter;
 pub mod net_server;
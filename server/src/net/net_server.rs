@@ -1,20 +1,82 @@
 use data::server_db::{self, find_all_tile, GameData};
 use protocol::{
+    account::AccountRecord,
+    channel::{self, Connection},
     data::{
-        control_data::ControlData, player_data::PlayerData, skill_data::SkillData,
+        account_data::{is_valid_username, AccountData, AccountFailure, CredentialData},
+        control_data::ControlData,
+        hello_data::{HelloData, FEATURE_DELTA},
+        player_data::PlayerData,
+        session_data::SessionData,
+        skill_data::SkillData,
         tile_map_data::TileMapData,
     },
     packet::Packet,
-    route::GameRoute,
+    route::{AccountRoute, GameRoute, HeartbeatRoute},
+    session::Session,
+    snapshot::Snapshot,
 };
 use tokio::net::UdpSocket;
-use tokio::sync::mpsc::{Receiver, Sender};
+
+use crate::engine::engine_server::EngineMessage;
+use crate::net::limiter::LoginLimiter;
+use tokio::sync::{
+    mpsc::{Receiver, Sender},
+    Mutex,
+};
 
 use std::str::FromStr;
-use std::{net::SocketAddr, sync::Arc, time::SystemTime};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Arc,
+    time::{Instant, SystemTime},
+};
+
+type ConnectionMapState = Arc<Mutex<HashMap<SocketAddr, Connection>>>;
+type SessionMapState = Arc<Mutex<HashMap<u32, PlayerSession>>>;
+
+/// 网络模块发往引擎的消息
+#[derive(Debug)]
+pub enum NetMessage {
+    /// 客户端发来的数据包
+    Packet(Packet),
+    /// 玩家离线, 引擎移除玩家实体
+    PlayerLeft(PlayerLeft),
+}
+
+/// 离线原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeaveReason {
+    /// 客户端主动登出
+    Logout,
+    /// 心跳超时
+    Timeout,
+    /// 切换到其他房间
+    ChangeRoom,
+    /// 同一账号在其他地方重新登录
+    Relogin,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct PlayerLeft {
+    pub uid: u32,
+    pub group: u32,
+    pub reason: LeaveReason,
+}
+
+/// 玩家登录会话及当前绑定的地址
+pub struct PlayerSession {
+    pub session: Session,
+    pub addr: SocketAddr,
+    pub group: u32,
+}
 
-pub async fn net_server_start(net_tx: Sender<Packet>, engine_rx: Receiver<Packet>) {
-    if let Ok(game_server_socket) = UdpSocket::bind(config::SERVER_ADDR).await {
+/// 每个地图数据包包含的tile数量, 超出单个数据报的部分由分片层处理
+const TILE_MAP_CHUNK: usize = 512;
+
+pub async fn net_server_start(net_tx: Sender<NetMessage>, engine_rx: Receiver<EngineMessage>) {
+    if let Ok(game_server_socket) = UdpSocket::bind(&config::get().bind_addr).await {
         if let Ok(game_server_addr) = &game_server_socket.local_addr() {
             println!("网络服务器已启动: {:?}", game_server_addr);
 
@@ -23,13 +85,26 @@ pub async fn net_server_start(net_tx: Sender<Packet>, engine_rx: Receiver<Packet
 
             // let game_server_framed = UdpFramed::new(**r, BytesCodec::new());
 
-            let game_server_future = start_listening(r, s.clone(), net_tx);
+            let connection_state = Arc::new(Mutex::new(HashMap::new()));
+            let session_state = Arc::new(Mutex::new(HashMap::new()));
+
+            let game_server_future = start_listening(
+                r,
+                s.clone(),
+                net_tx.clone(),
+                connection_state.clone(),
+                session_state.clone(),
+            );
 
-            let wait_for_send_future = wait_for_send(s, engine_rx);
-            let clean_offline_user_future = clean_offline_user();
+            let wait_for_send_future =
+                wait_for_send(s.clone(), engine_rx, connection_state.clone());
+            let clean_offline_user_future =
+                clean_offline_user(connection_state.clone(), session_state, net_tx);
+            let resend_future = resend(s, connection_state);
 
             tokio::spawn(clean_offline_user_future);
             tokio::spawn(wait_for_send_future);
+            tokio::spawn(resend_future);
             game_server_future.await;
         }
     }
@@ -39,99 +114,320 @@ pub async fn send(socket: Arc<UdpSocket>, packet: Vec<u8>, recv_addr: SocketAddr
     let _ = socket.send_to(&packet[..], recv_addr).await;
 }
 
-pub async fn clean_offline_user() {
+/// 按数据包所属通道发送, 已握手的对端经过可靠通道
+pub async fn send_packet(
+    socket: Arc<UdpSocket>,
+    connection_state: ConnectionMapState,
+    packet: Packet,
+    recv_addr: SocketAddr,
+) {
+    let datagrams = match connection_state.lock().await.get_mut(&recv_addr) {
+        Some(connection) => connection
+            .send(&packet, Instant::now())
+            .unwrap_or_else(|e| {
+                println!("发送到{}失败: {}", recv_addr, e);
+                Vec::new()
+            }),
+        None => channel::encode(&packet)
+            .map(|datagram| vec![datagram])
+            .unwrap_or_else(|e| {
+                println!("发送到{}失败: {}", recv_addr, e);
+                Vec::new()
+            }),
+    };
+    for datagram in datagrams {
+        send(socket.clone(), datagram, recv_addr).await;
+    }
+}
+
+/// 解析数据报, 返回按序交付的数据包与需要回复的数据报
+async fn receive(
+    connection_state: &ConnectionMapState,
+    datagram: &[u8],
+    addr: SocketAddr,
+) -> (Vec<Packet>, Vec<Vec<u8>>) {
+    if let Some(connection) = connection_state.lock().await.get_mut(&addr) {
+        return connection.receive(datagram, Instant::now());
+    }
+    match bincode::deserialize::<Packet>(datagram) {
+        Ok(packet) => (vec![packet], Vec::new()),
+        Err(_) => {
+            // 无法解析的旧版本握手包, 回复拒绝
+            if HelloData::peek_version(datagram).is_some() {
+                let reject = Packet::Heartbeat(HeartbeatRoute::In(HelloData::reject()));
+                return (Vec::new(), vec![bincode::serialize(&reject).unwrap()]);
+            }
+            (Vec::new(), Vec::new())
+        }
+    }
+}
+
+/// 校验会话签名, 返回原始数据报与发送者uid
+/// 未签名的数据报原样返回(uid为None), 签名无效或会话不存在返回None
+/// 已登录的地址只接受签名数据报, 伪造的握手, 确认和心跳无法替换连接或清除待确认数据
+async fn open_session(
+    session_state: &SessionMapState,
+    connection_state: &ConnectionMapState,
+    datagram: &[u8],
+    addr: SocketAddr,
+) -> Option<(Vec<u8>, Option<u32>)> {
+    let mut sessions = session_state.lock().await;
+    let sealed = match bincode::deserialize::<Packet>(datagram) {
+        Ok(Packet::Session(sealed)) => sealed,
+        _ => {
+            if sessions
+                .values()
+                .any(|player_session| player_session.addr == addr)
+            {
+                return None;
+            }
+            return Some((datagram.to_vec(), None));
+        }
+    };
+    let player_session = sessions.get_mut(&sealed.uid)?;
+    let payload = player_session.session.open(&sealed)?.to_vec();
+    if player_session.addr != addr {
+        // 签名有效但地址变化(NAT重绑定等), 会话迁移到新地址
+        println!(
+            "会话迁移: {} {} -> {}",
+            sealed.uid, player_session.addr, addr
+        );
+        migrate_session(
+            connection_state,
+            sealed.uid,
+            player_session.group,
+            player_session.addr,
+            addr,
+        )
+        .await;
+        player_session.addr = addr;
+    }
+    Some((payload, Some(sealed.uid)))
+}
+
+/// 将玩家的连接状态和地址记录迁移到新地址
+async fn migrate_session(
+    connection_state: &ConnectionMapState,
+    uid: u32,
+    group: u32,
+    old_addr: SocketAddr,
+    addr: SocketAddr,
+) {
+    {
+        let mut connections = connection_state.lock().await;
+        if let Some(connection) = connections.remove(&old_addr) {
+            connections.insert(addr, connection);
+        }
+    }
+    let _ = server_db::save(GameData::player_uid_addr(uid, Some(addr.to_string())));
+    let _ = server_db::save(GameData::player_addr_uid(
+        addr.to_string(),
+        Some(uid.to_string()),
+    ));
+    if let Some(version) =
+        server_db::find(GameData::player_addr_version(old_addr.to_string(), None)).ok()
+    {
+        let _ = server_db::save(GameData::player_addr_version(
+            addr.to_string(),
+            Some(version),
+        ));
+    }
+    let _ = server_db::save(GameData::player_addr_health(
+        addr.to_string(),
+        Some(format!(
+            "{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        )),
+    ));
+    // 更新玩家组ip地址
+    if let Some(data) = server_db::find(GameData::player_group_addr(group, None)).ok() {
+        let addr_list: Vec<String> = data
+            .split(",")
+            .map(|addr_db| {
+                if addr_db.eq(&old_addr.to_string()) {
+                    addr.to_string()
+                } else {
+                    addr_db.to_string()
+                }
+            })
+            .collect();
+        let _ = server_db::save(GameData::player_group_addr(
+            group,
+            Some(addr_list.join(",")),
+        ));
+    }
+}
+
+/// 可靠通道超时重发
+pub async fn resend(socket: Arc<UdpSocket>, connection_state: ConnectionMapState) {
+    let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(50));
+    loop {
+        interval.tick().await;
+        let now = Instant::now();
+        let mut datagrams = Vec::new();
+        for (addr, connection) in connection_state.lock().await.iter_mut() {
+            for datagram in connection.resend(now) {
+                datagrams.push((datagram, *addr));
+            }
+        }
+        for (datagram, addr) in datagrams {
+            send(socket.clone(), datagram, addr).await;
+        }
+    }
+}
+
+/// 玩家离线: 清除地址记录, 注销会话并通知引擎移除玩家实体
+/// 会话已迁移到其他地址(重新登录)时只清除旧地址
+async fn player_left(
+    net_tx: &Sender<NetMessage>,
+    connection_state: &ConnectionMapState,
+    session_state: &SessionMapState,
+    uid: u32,
+    addr: SocketAddr,
+    reason: LeaveReason,
+) {
+    forget_addr(connection_state, addr).await;
+    let group = {
+        let mut sessions = session_state.lock().await;
+        if sessions
+            .get(&uid)
+            .map_or(true, |player_session| player_session.addr != addr)
+        {
+            return;
+        }
+        match sessions.remove(&uid) {
+            Some(player_session) => player_session.group,
+            None => return,
+        }
+    };
+    let _ = server_db::remove(GameData::player_uid_addr(uid, None));
+    println!("玩家离线: {} {:?}", uid, reason);
+    let _ = net_tx
+        .send(NetMessage::PlayerLeft(PlayerLeft { uid, group, reason }))
+        .await;
+}
+
+pub async fn clean_offline_user(
+    connection_state: ConnectionMapState,
+    session_state: SessionMapState,
+    net_tx: Sender<NetMessage>,
+) {
     let clean_tick = 5000u128;
     let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(clean_tick as u64));
     loop {
         interval.tick().await;
-        match server_db::find(GameData::player_group_addr(0, None)) {
-            Ok(data) => {
-                // println!("在线玩家列表: [{}]", data);
-                if data.len() > 0 {
-                    for addr_db in data.split(",") {
-                        // 检查玩家ip地址健康检查状态
-                        if let Ok(health) =
-                            server_db::find(GameData::player_addr_health(addr_db.to_string(), None))
-                        {
-                            // println!("{}健康值: {}", addr_db, health);
-                            if let Ok(h) = health.parse::<u128>() {
-                                let now = SystemTime::now()
-                                    .duration_since(SystemTime::UNIX_EPOCH)
-                                    .unwrap()
-                                    .as_millis();
-                                if now > (h + clean_tick) {
-                                    // println!("now: {}", now);
-                                    if let Ok(uid) = server_db::find(GameData::player_addr_uid(
-                                        addr_db.to_string(),
-                                        None,
-                                    )) {
-                                        // println!("uid: {}", uid);
-                                        if let Ok(uid_offline) = uid.parse::<u32>() {
-                                            // println!("uid_offline: {}", uid_offline);
-                                            // 更新在线玩家表
-                                            match server_db::find(GameData::player_online(None)) {
-                                                Ok(data) => {
-                                                    if data.len() > 0 {
-                                                        let mut uid_list: Vec<&str> =
-                                                            data.split(",").collect();
-                                                        let mut rm_index = None;
-                                                        for index in 0..uid_list.len() {
-                                                            if uid_list[index]
-                                                                .eq(&uid_offline.to_string())
-                                                            {
-                                                                rm_index = Some(index);
-                                                                break;
+        for group in config::get().rooms.iter() {
+            match server_db::find(GameData::player_group_addr(*group, None)).ok() {
+                Some(data) => {
+                    // println!("在线玩家列表: [{}]", data);
+                    if data.len() > 0 {
+                        for addr_db in data.split(",") {
+                            // 检查玩家ip地址健康检查状态
+                            if let Some(health) = server_db::find(GameData::player_addr_health(
+                                addr_db.to_string(),
+                                None,
+                            ))
+                            .ok()
+                            {
+                                // println!("{}健康值: {}", addr_db, health);
+                                if let Ok(h) = health.parse::<u128>() {
+                                    let now = SystemTime::now()
+                                        .duration_since(SystemTime::UNIX_EPOCH)
+                                        .unwrap()
+                                        .as_millis();
+                                    if now > (h + clean_tick) {
+                                        // println!("now: {}", now);
+                                        if let Ok(addr) = SocketAddr::from_str(addr_db) {
+                                            connection_state.lock().await.remove(&addr);
+                                        }
+                                        if let Some(uid) = server_db::find(
+                                            GameData::player_addr_uid(addr_db.to_string(), None),
+                                        )
+                                        .ok()
+                                        {
+                                            // println!("uid: {}", uid);
+                                            if let Ok(uid_offline) = uid.parse::<u32>() {
+                                                // println!("uid_offline: {}", uid_offline);
+                                                // 更新在线玩家表
+                                                match server_db::find(GameData::player_online(None))
+                                                {
+                                                    Ok(data) => {
+                                                        if data.len() > 0 {
+                                                            let mut uid_list: Vec<&str> =
+                                                                data.split(",").collect();
+                                                            let mut rm_index = None;
+                                                            for index in 0..uid_list.len() {
+                                                                if uid_list[index]
+                                                                    .eq(&uid_offline.to_string())
+                                                                {
+                                                                    rm_index = Some(index);
+                                                                    break;
+                                                                }
+                                                            }
+                                                            if let Some(index) = rm_index {
+                                                                println!(
+                                                                    "清理未在线玩家uid: {}",
+                                                                    uid_list[index]
+                                                                );
+                                                                uid_list.remove(index);
+                                                                let _ = server_db::save(
+                                                                    GameData::player_online(Some(
+                                                                        uid_list.join(","),
+                                                                    )),
+                                                                );
                                                             }
-                                                        }
-                                                        if let Some(index) = rm_index {
-                                                            println!(
-                                                                "清理未在线玩家uid: {}",
-                                                                uid_list[index]
-                                                            );
-                                                            uid_list.remove(index);
-                                                            let _ = server_db::save(
-                                                                GameData::player_online(Some(
-                                                                    uid_list.join(","),
-                                                                )),
-                                                            );
                                                         }
                                                     }
+                                                    Err(_) => {}
                                                 }
-                                                Err(_) => {}
-                                            }
-                                            // 更新玩家组ip地址
-                                            match server_db::find(GameData::player_group_addr(
-                                                0, None,
-                                            )) {
-                                                Ok(data) => {
-                                                    if data.len() > 0 {
-                                                        let mut addr_list: Vec<&str> =
-                                                            data.split(",").collect();
-                                                        let mut rm_index = None;
-                                                        for index in 0..addr_list.len() {
-                                                            if addr_list[index]
-                                                                .eq(&addr_db.to_string())
-                                                            {
-                                                                rm_index = Some(index);
-                                                                break;
+                                                // 更新玩家组ip地址
+                                                match server_db::find(GameData::player_group_addr(
+                                                    *group, None,
+                                                )) {
+                                                    Ok(data) => {
+                                                        if data.len() > 0 {
+                                                            let mut addr_list: Vec<&str> =
+                                                                data.split(",").collect();
+                                                            let mut rm_index = None;
+                                                            for index in 0..addr_list.len() {
+                                                                if addr_list[index]
+                                                                    .eq(&addr_db.to_string())
+                                                                {
+                                                                    rm_index = Some(index);
+                                                                    break;
+                                                                }
+                                                            }
+                                                            if let Some(index) = rm_index {
+                                                                println!(
+                                                                    "清理未在线玩家IP: {}",
+                                                                    addr_list[index]
+                                                                );
+                                                                addr_list.remove(index);
+                                                                let _ = server_db::save(
+                                                                    GameData::player_group_addr(
+                                                                        *group,
+                                                                        Some(addr_list.join(",")),
+                                                                    ),
+                                                                );
                                                             }
-                                                        }
-                                                        if let Some(index) = rm_index {
-                                                            println!(
-                                                                "清理未在线玩家IP: {}",
-                                                                addr_list[index]
-                                                            );
-                                                            addr_list.remove(index);
-                                                            let _ = server_db::save(
-                                                                GameData::player_group_addr(
-                                                                    0,
-                                                                    Some(addr_list.join(",")),
-                                                                ),
-                                                            );
                                                         }
                                                     }
+                                                    Err(_) => {}
+                                                }
+                                                if let Ok(addr) = SocketAddr::from_str(addr_db) {
+                                                    player_left(
+                                                        &net_tx,
+                                                        &connection_state,
+                                                        &session_state,
+                                                        uid_offline,
+                                                        addr,
+                                                        LeaveReason::Timeout,
+                                                    )
+                                                    .await;
                                                 }
-                                                Err(_) => {}
                                             }
                                         }
                                     }
@@ -140,31 +436,120 @@ pub async fn clean_offline_user() {
                         }
                     }
                 }
+                None => {}
             }
-            Err(_) => {}
         }
     }
 }
 
-pub async fn multicast(socket: Arc<UdpSocket>, group: u32, packet: Vec<u8>) {
+/// 获取客户端握手协商结果, 未握手或版本不兼容返回None
+pub fn find_handshake(addr: &SocketAddr) -> Option<HelloData> {
+    if let Ok(data) = server_db::find(GameData::player_addr_version(addr.to_string(), None)) {
+        let mut data = data.split(",");
+        if let (Some(version), Some(features)) = (data.next(), data.next()) {
+            if let (Ok(version), Ok(features)) = (version.parse::<u32>(), features.parse::<u32>()) {
+                let hello_data = HelloData {
+                    version,
+                    features,
+                    nonce: 0,
+                    result: protocol::data::hello_data::HelloResult::Request,
+                }
+                .negotiate();
+                if hello_data.is_accepted() {
+                    return Some(hello_data);
+                }
+            }
+        }
+    }
+    None
+}
+
+/// 编码发往单个对端的数据报, 支持增量快照的客户端只发送相对已确认快照的变化
+fn encode_packet(
+    connection: Option<&mut Connection>,
+    packet: &Packet,
+    snapshot: &Option<Snapshot>,
+) -> Vec<Vec<u8>> {
+    let result = match connection {
+        Some(connection) => match snapshot {
+            Some(snapshot) if connection.has_feature(FEATURE_DELTA) => {
+                let snapshot_data = connection.snapshots.encode(snapshot.clone());
+                connection.send(
+                    &Packet::Game(GameRoute::Snapshot(snapshot_data)),
+                    Instant::now(),
+                )
+            }
+            _ => connection.send(packet, Instant::now()),
+        },
+        // 未建立连接的对端无法分片, 只发送单个数据报
+        None => channel::encode(packet).map(|datagram| vec![datagram]),
+    };
+    result.unwrap_or_else(|e| {
+        println!("{}", e);
+        Vec::new()
+    })
+}
+
+/// 发送给指定uid的玩家
+pub async fn unicast(
+    socket: Arc<UdpSocket>,
+    connection_state: ConnectionMapState,
+    uid: u32,
+    packet: Packet,
+) {
+    if let Some(data) = server_db::find(GameData::player_uid_addr(uid, None)).ok() {
+        if let Ok(recv_addr) = SocketAddr::from_str(&data) {
+            let snapshot = match &packet {
+                Packet::Game(GameRoute::Update(update_data)) => Some(Snapshot::from(update_data)),
+                _ => None,
+            };
+            let datagrams = encode_packet(
+                connection_state.lock().await.get_mut(&recv_addr),
+                &packet,
+                &snapshot,
+            );
+            for datagram in datagrams {
+                tokio::spawn(send(socket.clone(), datagram, recv_addr));
+            }
+        }
+    }
+}
+
+pub async fn multicast(
+    socket: Arc<UdpSocket>,
+    connection_state: ConnectionMapState,
+    group: u32,
+    packet: Packet,
+) {
     // let mut senders = Vec::new();
-    match server_db::find(GameData::player_group_addr(group, None)) {
-        Ok(data) => {
+    // 状态同步只量化一次, 再按各客户端已确认的基准编码增量
+    let snapshot = match &packet {
+        Packet::Game(GameRoute::Update(update_data)) => Some(Snapshot::from(update_data)),
+        _ => None,
+    };
+    match server_db::find(GameData::player_group_addr(group, None)).ok() {
+        Some(data) => {
             // println!("1");
             if data.len() > 0 {
                 // println!("在线玩家IP: {}", &data);
                 let uid_list: Vec<&str> = data.split(",").collect();
+                let mut connections = connection_state.lock().await;
                 for index in 0..uid_list.len() {
-                    let recv_addr = SocketAddr::from_str(uid_list[index]).unwrap();
-                    let socket = socket.clone();
-                    let packet = packet.clone();
-                    tokio::spawn(send(socket, packet, recv_addr));
+                    // 跳过无法解析的地址(如移除地址后留下的空项)
+                    let recv_addr = match SocketAddr::from_str(uid_list[index]) {
+                        Ok(recv_addr) => recv_addr,
+                        Err(_) => continue,
+                    };
+                    let datagrams =
+                        encode_packet(connections.get_mut(&recv_addr), &packet, &snapshot);
+                    for datagram in datagrams {
+                        tokio::spawn(send(socket.clone(), datagram, recv_addr));
+                    }
                     // senders.push(sender);
                 }
             }
         }
-        Err(_e) => {
-            // println!("{}", _e);
+        None => {
             // println!("{}组无玩家在线!", group);
         }
     }
@@ -176,42 +561,350 @@ pub async fn multicast(socket: Arc<UdpSocket>, group: u32, packet: Vec<u8>) {
     // println!("sended");
 }
 
-pub async fn wait_for_send(socket: Arc<UdpSocket>, mut engine_rx: Receiver<Packet>) {
+pub async fn wait_for_send(
+    socket: Arc<UdpSocket>,
+    mut engine_rx: Receiver<EngineMessage>,
+    connection_state: ConnectionMapState,
+) {
     // let mut interval = tokio::time::interval(tokio::time::Duration::from_millis(10));
     loop {
         // interval.tick().await;
-        if let Some(packet) = engine_rx.recv().await {
+        if let Some(message) = engine_rx.recv().await {
             // println!("{:?}", packet);
             let socket = socket.clone();
-            multicast(socket, 0, bincode::serialize(&packet).unwrap()).await;
+            match message {
+                EngineMessage::Group(group, packet) => {
+                    multicast(socket, connection_state.clone(), group, packet).await
+                }
+                EngineMessage::Player(uid, packet) => {
+                    unicast(socket, connection_state.clone(), uid, packet).await
+                }
+            }
             // let _ = tokio::join!(multicast(socket, 0, bincode::serialize(&packet).unwrap()));
         }
     }
 }
 
+/// 注册新账号并创建玩家数据
+fn register_account(credential_data: &CredentialData) -> Result<u32, AccountFailure> {
+    if !is_valid_username(&credential_data.username) {
+        return Err(AccountFailure::InvalidUsername);
+    }
+    if server_db::find_account(&credential_data.username).is_ok() {
+        return Err(AccountFailure::UsernameTaken);
+    }
+    let uid = server_db::next_u64(GameData::player_queue_uid(None))
+        .map_err(|_| AccountFailure::ServerError)? as u32;
+    let record = AccountRecord::new(uid, rand::random(), &credential_data.password);
+    match server_db::create_account(&credential_data.username, &record) {
+        Ok(true) => {}
+        // 并发注册了同名账号
+        Ok(false) => return Err(AccountFailure::UsernameTaken),
+        Err(_) => return Err(AccountFailure::ServerError),
+    }
+    println!("新玩家注册: {} {}", credential_data.username, uid);
+    let _ = server_db::save_player(PlayerData {
+        uid,
+        hp: 100,
+        mp: 100,
+        max_hp: 100,
+        max_mp: 100,
+    });
+    Ok(uid)
+}
+
+/// 校验账号密码
+fn login_account(credential_data: &CredentialData) -> Result<u32, AccountFailure> {
+    let record = server_db::find_account(&credential_data.username)
+        .map_err(|_| AccountFailure::UnknownUser)?;
+    if record.verify(&credential_data.password) {
+        Ok(record.uid)
+    } else {
+        Err(AccountFailure::WrongPassword)
+    }
+}
+
+/// 在阻塞线程池中执行注册或登录(密码哈希耗时较长, 不能阻塞接收循环), 完成后进入游戏或回复失败原因
+fn spawn_account<F>(
+    account: F,
+    send_socket: Arc<UdpSocket>,
+    net_tx: Sender<NetMessage>,
+    connection_state: ConnectionMapState,
+    session_state: SessionMapState,
+    group: u32,
+    addr: SocketAddr,
+) where
+    F: FnOnce() -> Result<u32, AccountFailure> + Send + 'static,
+{
+    tokio::spawn(async move {
+        let result = tokio::task::spawn_blocking(account)
+            .await
+            .unwrap_or(Err(AccountFailure::ServerError));
+        match result {
+            Ok(uid) => {
+                enter_game(
+                    send_socket,
+                    &net_tx,
+                    &connection_state,
+                    &session_state,
+                    uid,
+                    group,
+                    addr,
+                )
+                .await
+            }
+            Err(failure) => {
+                println!("{}登录失败: {:?}", &addr, failure);
+                send_packet(
+                    send_socket,
+                    connection_state,
+                    Packet::Account(AccountRoute::Failed(failure)),
+                    addr,
+                )
+                .await
+            }
+        }
+    });
+}
+
+/// 会话所在的房间, 未登录时为0号房间
+async fn session_group(session_state: &SessionMapState, uid: Option<u32>) -> u32 {
+    match uid {
+        Some(uid) => session_state
+            .lock()
+            .await
+            .get(&uid)
+            .map_or(0, |player_session| player_session.group),
+        None => 0,
+    }
+}
+
+/// 把地址加入玩家组
+fn add_group_addr(group: u32, addr: SocketAddr) {
+    match server_db::find(GameData::player_group_addr(group, None)) {
+        Ok(data) => {
+            if data.len() > 0 {
+                let mut exist = false;
+                for addr_db in data.split(",") {
+                    if addr_db.eq(&addr.to_string()) {
+                        exist = true;
+                        break;
+                    }
+                }
+                if !exist {
+                    let _ = server_db::save(GameData::player_group_addr(
+                        group,
+                        Some(format!("{},{}", data, addr)),
+                    ));
+                }
+            } else {
+                let _ = server_db::save(GameData::player_group_addr(
+                    group,
+                    Some(format!("{}", addr)),
+                ));
+            }
+        }
+        Err(_) => {
+            let _ = server_db::save(GameData::player_group_addr(
+                group,
+                Some(format!("{}", addr)),
+            ));
+        }
+    }
+}
+
+/// 把地址移出玩家组
+fn remove_group_addr(group: u32, addr: SocketAddr) {
+    if let Ok(data) = server_db::find(GameData::player_group_addr(group, None)) {
+        let addr_list: Vec<&str> = data
+            .split(",")
+            .filter(|addr_db| !addr_db.is_empty() && !addr_db.eq(&addr.to_string()))
+            .collect();
+        let _ = server_db::save(GameData::player_group_addr(
+            group,
+            Some(addr_list.join(",")),
+        ));
+    }
+}
+
+/// 清除地址的可靠通道状态和地址对应的uid记录
+async fn forget_addr(connection_state: &ConnectionMapState, addr: SocketAddr) {
+    connection_state.lock().await.remove(&addr);
+    let _ = server_db::remove(GameData::player_addr_uid(addr.to_string(), None));
+}
+
+/// 登录成功: 记录玩家地址, 通知引擎生成玩家实体并签发会话令牌
+async fn enter_game(
+    send_socket: Arc<UdpSocket>,
+    net_tx: &Sender<NetMessage>,
+    connection_state: &ConnectionMapState,
+    session_state: &SessionMapState,
+    uid: u32,
+    group: u32,
+    addr: SocketAddr,
+) {
+    // 记录ip地址对应的玩家uid
+    let _ = server_db::save(GameData::player_addr_uid(
+        addr.to_string(),
+        Some(uid.to_string()),
+    ));
+    // 记录玩家uid对应的ip地址
+    let _ = server_db::save(GameData::player_uid_addr(uid, Some(addr.to_string())));
+    // 更新在线玩家表
+    match server_db::find(GameData::player_online(None)) {
+        Ok(data) => {
+            if data.len() > 0 {
+                let mut exist = false;
+                for uid_db in data.split(",") {
+                    if uid_db.eq(&uid.to_string()) {
+                        exist = true;
+                        break;
+                    }
+                }
+                if !exist {
+                    let _ =
+                        server_db::save(GameData::player_online(Some(format!("{},{}", data, uid))));
+                }
+            } else {
+                let _ = server_db::save(GameData::player_online(Some(format!("{}", uid))));
+            }
+        }
+        Err(_) => {
+            let _ = server_db::save(GameData::player_online(Some(format!("{}", uid))));
+        }
+    }
+    // 更新玩家组ip地址
+    add_group_addr(group, addr);
+    // 添加玩家ip地址健康检查状态
+    let _ = server_db::save(GameData::player_addr_health(
+        addr.to_string(),
+        Some(format!(
+            "{}",
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap()
+                .as_millis()
+        )),
+    ));
+    // 签发会话令牌, 之后的数据报都需要用令牌签名
+    let session_data = SessionData {
+        uid,
+        token: rand::random::<u128>(),
+    };
+    // 同一账号重复登录时替换旧会话, 旧客户端的数据报不再通过校验
+    let replaced = session_state.lock().await.insert(
+        uid,
+        PlayerSession {
+            session: Session::new(uid, session_data.token),
+            addr,
+            group,
+        },
+    );
+    if let Some(old) = replaced {
+        // 清除旧会话的地址记录, 旧地址不再收到该玩家的数据
+        if old.addr != addr || old.group != group {
+            remove_group_addr(old.group, old.addr);
+        }
+        if old.addr != addr {
+            forget_addr(connection_state, old.addr).await;
+        }
+        // 先通知引擎移除旧会话的实体, 再生成新实体
+        println!("玩家重复登录: {}", uid);
+        let _ = net_tx
+            .send(NetMessage::PlayerLeft(PlayerLeft {
+                uid,
+                group: old.group,
+                reason: LeaveReason::Relogin,
+            }))
+            .await;
+    }
+    // 发送生成玩家实体事件到引擎
+    let packet_login = Packet::Account(AccountRoute::Enter(AccountData { uid, group }));
+    let _ = net_tx.try_send(NetMessage::Packet(packet_login.clone()));
+    send_packet(
+        send_socket.clone(),
+        connection_state.clone(),
+        packet_login,
+        addr,
+    )
+    .await;
+    send_packet(
+        send_socket.clone(),
+        connection_state.clone(),
+        Packet::Account(AccountRoute::Session(session_data)),
+        addr,
+    )
+    .await;
+}
+
 pub async fn start_listening(
     socket: Arc<UdpSocket>,
     send_socket: Arc<UdpSocket>,
-    net_tx: Sender<Packet>,
+    net_tx: Sender<NetMessage>,
+    connection_state: ConnectionMapState,
+    session_state: SessionMapState,
 ) {
-    let mut buf = [0; config::PACKET_SIZE];
+    let mut buf = vec![0u8; config::get().packet_size];
+    let mut limiter = LoginLimiter::new();
     // let mut interval = tokio::time::interval(tokio::time::Duration::from_nanos(10));
     loop {
         // interval.tick().await;
         // println!("接收ing");
         if let Ok((len, addr)) = socket.recv_from(&mut buf).await {
             // println!("服务器收到数据: {}", &len);
-            if let Ok(packet) = bincode::deserialize::<Packet>(&buf[..len]) {
+            // 校验会话签名, 伪造或重放的数据报直接丢弃
+            let (datagram, session_uid) =
+                match open_session(&session_state, &connection_state, &buf[..len], addr).await {
+                    Some(opened) => opened,
+                    None => continue,
+                };
+            // 可靠通道: 回复确认并按序取出数据包
+            let (packets, replies) = receive(&connection_state, &datagram, addr).await;
+            for reply in replies {
+                send(send_socket.clone(), reply, addr).await;
+            }
+            for packet in packets {
                 // println!("服务器收到数据: {:?}", &packet);
                 // 转发事件
                 match packet {
                     Packet::Heartbeat(heartbeat_route) => match heartbeat_route {
-                        protocol::route::HeartbeatRoute::In => {}
+                        protocol::route::HeartbeatRoute::In(hello_data) => {
+                            // 协商协议版本与功能
+                            let hello_reply = hello_data.negotiate();
+                            println!("{}握手: {:?} -> {:?}", &addr, &hello_data, &hello_reply);
+                            if hello_reply.is_accepted() {
+                                // 新连接(或客户端重启)重建可靠通道状态
+                                let mut connections = connection_state.lock().await;
+                                let renew = connections.get(&addr).map_or(true, |connection| {
+                                    connection.nonce != hello_data.nonce
+                                });
+                                if renew {
+                                    connections.insert(
+                                        addr,
+                                        Connection::new(hello_reply.features, hello_data.nonce),
+                                    );
+                                }
+                                let _ = server_db::save(GameData::player_addr_version(
+                                    addr.to_string(),
+                                    Some(format!(
+                                        "{},{}",
+                                        hello_reply.version, hello_reply.features
+                                    )),
+                                ));
+                            }
+                            let _ = tokio::spawn(send(
+                                send_socket.clone(),
+                                bincode::serialize(&Packet::Heartbeat(
+                                    protocol::route::HeartbeatRoute::In(hello_reply),
+                                ))
+                                .unwrap(),
+                                addr,
+                            ));
+                        }
                         protocol::route::HeartbeatRoute::Out => {}
                         protocol::route::HeartbeatRoute::Keep(_) => {
                             // 回ping
-                            let _ =
-                                tokio::spawn(send(send_socket.clone(), buf[..len].to_vec(), addr));
+                            let _ = tokio::spawn(send(send_socket.clone(), datagram.clone(), addr));
                             // 添加玩家ip地址健康检查状态
                             let _ = server_db::save(GameData::player_addr_health(
                                 addr.to_string(),
@@ -226,161 +919,122 @@ pub async fn start_listening(
                         }
                     },
                     Packet::Account(account_route) => match account_route {
-                        protocol::route::AccountRoute::Login(account_data) => {
-                            println!("{}登录事件: {:?}", &addr, &account_data);
-                            // 根据玩家ip注册或获取uid
-                            let mut uid = account_data.uid;
-                            match server_db::find(GameData::player_addr_uid(addr.to_string(), None))
-                            {
-                                Ok(data) => {
-                                    if let Ok(id) = data.parse::<u32>() {
-                                        uid = id;
-                                    } else {
-                                        if let Ok(id) =
-                                            server_db::next_u64(GameData::player_queue_uid(None))
-                                        {
-                                            uid = id as u32;
-                                            println!("新玩家注册: {}", uid);
-                                            let _ = server_db::save(GameData::player_addr_uid(
-                                                addr.to_string(),
-                                                Some(uid.to_string()),
-                                            ));
-                                            let _ = server_db::save_player(PlayerData {
-                                                uid,
-                                                hp: 100,
-                                                mp: 100,
-                                                max_hp: 100,
-                                                max_mp: 100,
-                                            });
-                                        }
-                                    }
-                                }
-                                Err(_) => {
-                                    if let Ok(id) =
-                                        server_db::next_u64(GameData::player_queue_uid(None))
-                                    {
-                                        uid = id as u32;
-                                        println!("新玩家注册: {}", uid);
-                                        let _ = server_db::save(GameData::player_addr_uid(
-                                            addr.to_string(),
-                                            Some(uid.to_string()),
-                                        ));
-                                        let _ = server_db::save_player(PlayerData {
-                                            uid,
-                                            hp: 100,
-                                            mp: 100,
-                                            max_hp: 100,
-                                            max_mp: 100,
-                                        });
-                                    }
-                                }
+                        AccountRoute::Register(credential_data) => {
+                            println!("{}注册事件: {}", &addr, &credential_data.username);
+                            if find_handshake(&addr).is_none() {
+                                let _ = tokio::spawn(send(
+                                    send_socket.clone(),
+                                    bincode::serialize(&Packet::Heartbeat(
+                                        protocol::route::HeartbeatRoute::In(HelloData::reject()),
+                                    ))
+                                    .unwrap(),
+                                    addr,
+                                ));
+                                continue;
                             }
-                            // 更新在线玩家表
-                            match server_db::find(GameData::player_online(None)) {
-                                Ok(data) => {
-                                    if data.len() > 0 {
-                                        let mut exist = false;
-                                        for uid_db in data.split(",") {
-                                            if uid_db.eq(&uid.to_string()) {
-                                                exist = true;
-                                                break;
-                                            }
-                                        }
-                                        if !exist {
-                                            let _ = server_db::save(GameData::player_online(Some(
-                                                format!("{},{}", data, uid),
-                                            )));
-                                        }
-                                    } else {
-                                        let _ = server_db::save(GameData::player_online(Some(
-                                            format!("{}", uid),
-                                        )));
-                                    }
-                                }
-                                Err(_) => {
-                                    let _ = server_db::save(GameData::player_online(Some(
-                                        format!("{}", uid),
-                                    )));
-                                }
+                            if !config::get().rooms.contains(&credential_data.group) {
+                                send_packet(
+                                    send_socket.clone(),
+                                    connection_state.clone(),
+                                    Packet::Account(AccountRoute::Failed(
+                                        AccountFailure::UnknownRoom,
+                                    )),
+                                    addr,
+                                )
+                                .await;
+                                continue;
                             }
-                            // 更新玩家组ip地址
-                            match server_db::find(GameData::player_group_addr(
-                                account_data.group,
-                                None,
-                            )) {
-                                Ok(data) => {
-                                    if data.len() > 0 {
-                                        let mut exist = false;
-                                        for addr_db in data.split(",") {
-                                            if addr_db.eq(&addr.to_string()) {
-                                                exist = true;
-                                                break;
-                                            }
-                                        }
-                                        if !exist {
-                                            let _ = server_db::save(GameData::player_group_addr(
-                                                account_data.group,
-                                                Some(format!("{},{}", data, addr)),
-                                            ));
-                                        }
-                                    } else {
-                                        let _ = server_db::save(GameData::player_group_addr(
-                                            account_data.group,
-                                            Some(format!("{}", addr)),
-                                        ));
-                                    }
-                                }
-                                Err(_) => {
-                                    let _ = server_db::save(GameData::player_group_addr(
-                                        account_data.group,
-                                        Some(format!("{}", addr)),
-                                    ));
-                                }
+                            if !limiter.attempt(
+                                addr.ip(),
+                                &credential_data.username,
+                                Instant::now(),
+                            ) {
+                                println!("{}注册过于频繁: {}", &addr, &credential_data.username);
+                                send_packet(
+                                    send_socket.clone(),
+                                    connection_state.clone(),
+                                    Packet::Account(AccountRoute::Failed(
+                                        AccountFailure::TooManyAttempts,
+                                    )),
+                                    addr,
+                                )
+                                .await;
+                                continue;
                             }
-                            // 添加玩家ip地址健康检查状态
-                            let _ = server_db::save(GameData::player_addr_health(
-                                addr.to_string(),
-                                Some(format!(
-                                    "{}",
-                                    SystemTime::now()
-                                        .duration_since(SystemTime::UNIX_EPOCH)
-                                        .unwrap()
-                                        .as_millis()
-                                )),
-                            ));
-                            // 发送生成玩家实体事件到引擎
-                            let packet_login =
-                                Packet::Account(protocol::route::AccountRoute::Login(
-                                    protocol::data::account_data::AccountData {
-                                        uid,
-                                        group: account_data.group,
-                                    },
+                            let group = credential_data.group;
+                            spawn_account(
+                                move || register_account(&credential_data),
+                                send_socket.clone(),
+                                net_tx.clone(),
+                                connection_state.clone(),
+                                session_state.clone(),
+                                group,
+                                addr,
+                            );
+                        }
+                        AccountRoute::Login(credential_data) => {
+                            println!("{}登录事件: {}", &addr, &credential_data.username);
+                            // 未完成握手或版本不兼容的客户端拒绝登录
+                            if find_handshake(&addr).is_none() {
+                                let _ = tokio::spawn(send(
+                                    send_socket.clone(),
+                                    bincode::serialize(&Packet::Heartbeat(
+                                        protocol::route::HeartbeatRoute::In(HelloData::reject()),
+                                    ))
+                                    .unwrap(),
+                                    addr,
                                 ));
-                            let _ = net_tx.try_send(packet_login.clone());
-                            let _ = tokio::spawn(send(
+                                continue;
+                            }
+                            if !config::get().rooms.contains(&credential_data.group) {
+                                send_packet(
+                                    send_socket.clone(),
+                                    connection_state.clone(),
+                                    Packet::Account(AccountRoute::Failed(
+                                        AccountFailure::UnknownRoom,
+                                    )),
+                                    addr,
+                                )
+                                .await;
+                                continue;
+                            }
+                            if !limiter.attempt(
+                                addr.ip(),
+                                &credential_data.username,
+                                Instant::now(),
+                            ) {
+                                println!("{}登录过于频繁: {}", &addr, &credential_data.username);
+                                send_packet(
+                                    send_socket.clone(),
+                                    connection_state.clone(),
+                                    Packet::Account(AccountRoute::Failed(
+                                        AccountFailure::TooManyAttempts,
+                                    )),
+                                    addr,
+                                )
+                                .await;
+                                continue;
+                            }
+                            let group = credential_data.group;
+                            spawn_account(
+                                move || login_account(&credential_data),
                                 send_socket.clone(),
-                                bincode::serialize(&packet_login).unwrap(),
+                                net_tx.clone(),
+                                connection_state.clone(),
+                                session_state.clone(),
+                                group,
                                 addr,
-                            ));
+                            );
                         }
                         protocol::route::AccountRoute::Logout(account_data) => {
                             println!("{}登出事件: {:?}", &addr, &account_data);
-                            // 根据玩家ip获取uid
-                            let uid;
-                            match server_db::find(GameData::player_addr_uid(addr.to_string(), None))
-                            {
-                                Ok(data) => {
-                                    if let Ok(id) = data.parse::<u32>() {
-                                        uid = id;
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                                Err(e) => {
-                                    println!("报错: {}", e);
-                                    continue;
-                                }
-                            }
+                            // 会话对应的uid
+                            let uid = match session_uid {
+                                Some(uid) => uid,
+                                None => continue,
+                            };
+                            // 以会话记录的房间为准
+                            let group = session_group(&session_state, session_uid).await;
                             // 更新在线玩家表
                             match server_db::find(GameData::player_online(None)) {
                                 Ok(data) => {
@@ -404,147 +1058,199 @@ pub async fn start_listening(
                                 Err(_) => {}
                             }
                             // 更新玩家组ip地址
-                            match server_db::find(GameData::player_group_addr(
-                                account_data.group,
-                                None,
-                            )) {
-                                Ok(data) => {
-                                    if data.len() > 0 {
-                                        let mut addr_list: Vec<&str> = data.split(",").collect();
-                                        let mut rm_index = None;
-                                        for index in 0..addr_list.len() {
-                                            if addr_list[index].eq(&addr.to_string()) {
-                                                rm_index = Some(index);
-                                                break;
-                                            }
-                                        }
-                                        if let Some(index) = rm_index {
-                                            addr_list.remove(index);
-                                            let _ = server_db::save(GameData::player_group_addr(
-                                                account_data.group,
-                                                Some(addr_list.join(",")),
-                                            ));
-                                        }
-                                    }
-                                }
-                                Err(_) => {}
-                            }
+                            remove_group_addr(group, addr);
+                            player_left(
+                                &net_tx,
+                                &connection_state,
+                                &session_state,
+                                uid,
+                                addr,
+                                LeaveReason::Logout,
+                            )
+                            .await;
                         }
-                        protocol::route::AccountRoute::GetInfo(account_data) => {
-                            // 根据玩家ip获取uid
-                            let mut uid = account_data.uid;
-                            match server_db::find(GameData::player_addr_uid(addr.to_string(), None))
-                            {
-                                Ok(data) => {
-                                    if let Ok(id) = data.parse::<u32>() {
-                                        uid = id;
-                                    }
-                                }
-                                Err(_) => {}
+                        protocol::route::AccountRoute::GetInfo(_) => {
+                            let uid = match session_uid {
+                                Some(uid) => uid,
+                                None => continue,
+                            };
+                            // 未完成握手或版本不兼容的客户端拒绝登录
+                            if find_handshake(&addr).is_none() {
+                                let _ = tokio::spawn(send(
+                                    send_socket.clone(),
+                                    bincode::serialize(&Packet::Heartbeat(
+                                        protocol::route::HeartbeatRoute::In(HelloData::reject()),
+                                    ))
+                                    .unwrap(),
+                                    addr,
+                                ));
+                                continue;
                             }
-                            // 发送生成玩家实体事件到引擎
+                            // 以会话记录的uid和房间为准, 忽略客户端填写的值
+                            let group = session_group(&session_state, session_uid).await;
                             let packet_login =
-                                Packet::Account(protocol::route::AccountRoute::Login(
-                                    protocol::data::account_data::AccountData {
-                                        uid,
-                                        group: account_data.group,
-                                    },
+                                Packet::Account(protocol::route::AccountRoute::Enter(
+                                    protocol::data::account_data::AccountData { uid, group },
                                 ));
-                            let _ = tokio::spawn(send(
+                            send_packet(
                                 send_socket.clone(),
-                                bincode::serialize(&packet_login).unwrap(),
+                                connection_state.clone(),
+                                packet_login,
                                 addr,
-                            ));
+                            )
+                            .await;
+                        }
+                        // 会话令牌只由服务器下发
+                        AccountRoute::Session(_) => {}
+                        AccountRoute::Enter(_) => {}
+                        AccountRoute::Failed(_) => {}
+                        AccountRoute::ChangeRoom(account_data) => {
+                            let uid = match session_uid {
+                                Some(uid) => uid,
+                                None => continue,
+                            };
+                            if !config::get().rooms.contains(&account_data.group) {
+                                send_packet(
+                                    send_socket.clone(),
+                                    connection_state.clone(),
+                                    Packet::Account(AccountRoute::Failed(
+                                        AccountFailure::UnknownRoom,
+                                    )),
+                                    addr,
+                                )
+                                .await;
+                                continue;
+                            }
+                            let old_group = match session_state.lock().await.get_mut(&uid) {
+                                Some(player_session)
+                                    if player_session.group != account_data.group =>
+                                {
+                                    let old_group = player_session.group;
+                                    player_session.group = account_data.group;
+                                    old_group
+                                }
+                                _ => continue,
+                            };
+                            println!(
+                                "{}切换房间: {} {} -> {}",
+                                &addr, uid, old_group, account_data.group
+                            );
+                            remove_group_addr(old_group, addr);
+                            add_group_addr(account_data.group, addr);
+                            // 先离开旧房间, 再进入新房间
+                            let _ = net_tx
+                                .send(NetMessage::PlayerLeft(PlayerLeft {
+                                    uid,
+                                    group: old_group,
+                                    reason: LeaveReason::ChangeRoom,
+                                }))
+                                .await;
+                            let packet_enter = Packet::Account(AccountRoute::Enter(AccountData {
+                                uid,
+                                group: account_data.group,
+                            }));
+                            let _ = net_tx.send(NetMessage::Packet(packet_enter.clone())).await;
+                            send_packet(
+                                send_socket.clone(),
+                                connection_state.clone(),
+                                packet_enter,
+                                addr,
+                            )
+                            .await;
                         }
                     },
+                    // 未登录的客户端只能握手和登录
+                    Packet::Game(_) if session_uid.is_none() => {}
                     Packet::Game(game_route) => match game_route {
                         protocol::route::GameRoute::Control(control_data) => {
                             // println!("{}控制: {:?}", &addr, &control_data);
-                            // 根据玩家ip注册或获取uid
-                            let uid;
-                            match server_db::find(GameData::player_addr_uid(addr.to_string(), None))
-                            {
-                                Ok(data) => {
-                                    if let Ok(id) = data.parse::<u32>() {
-                                        uid = id;
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                                Err(e) => {
-                                    println!("{}报错: {}", &addr, e);
-                                    continue;
-                                }
-                            }
+                            // 会话对应的uid
+                            let uid = match session_uid {
+                                Some(uid) => uid,
+                                None => continue,
+                            };
                             let packet_control = Packet::Game(GameRoute::Control(ControlData {
                                 uid,
                                 direction: control_data.direction,
                                 action: control_data.action,
+                                seq: control_data.seq,
                             }));
-                            if let Ok(_) = net_tx.try_send(packet_control) {
+                            if let Ok(_) = net_tx.try_send(NetMessage::Packet(packet_control)) {
                                 // println!("{}转递控制: {:?}", &addr, &control_data);
                             }
                         }
                         protocol::route::GameRoute::Tile(tile_state) => {
-                            if let Ok(tile) = server_db::find_tile_map(tile_state.point) {
+                            // 玩家所在房间的地图
+                            let map_id = session_group(&session_state, session_uid).await;
+                            if let Some(tile) =
+                                server_db::find_tile_map(map_id, tile_state.point).ok()
+                            {
                                 let packet_tile =
                                     Packet::Game(protocol::route::GameRoute::Tile(tile));
                                 // println!("send: {:?}", &packet_tile);
-                                let _ = tokio::spawn(send(
+                                send_packet(
                                     send_socket.clone(),
-                                    bincode::serialize(&packet_tile).unwrap(),
+                                    connection_state.clone(),
+                                    packet_tile,
                                     addr,
-                                ));
+                                )
+                                .await;
                             }
                         }
                         GameRoute::Skill(skill_data) => {
-                            let uid;
-                            match server_db::find(GameData::player_addr_uid(addr.to_string(), None))
-                            {
-                                Ok(data) => {
-                                    if let Ok(id) = data.parse::<u32>() {
-                                        uid = id;
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                                Err(e) => {
-                                    println!("{}报错: {}", &addr, e);
-                                    continue;
-                                }
-                            }
+                            let uid = match session_uid {
+                                Some(uid) => uid,
+                                None => continue,
+                            };
                             let packet_control = Packet::Game(GameRoute::Skill(SkillData {
                                 uid,
                                 direction: skill_data.direction,
                                 skill_type: skill_data.skill_type,
                                 texture: skill_data.texture,
                             }));
-                            if let Ok(_) = net_tx.try_send(packet_control) {
+                            if let Ok(_) = net_tx.try_send(NetMessage::Packet(packet_control)) {
                                 // println!("{}转递控制: {:?}", &addr, &control_data);
                             }
                         }
                         GameRoute::TileMap(_tile_map_data) => {
-                            let tiles = find_all_tile();
-                            let mut tiles_iter = tiles.chunks(40);
+                            let map_id = session_group(&session_state, session_uid).await;
+                            let tiles = find_all_tile(map_id);
+                            let mut tiles_iter = tiles.chunks(TILE_MAP_CHUNK);
                             while let Some(t) = tiles_iter.next() {
                                 let packet_tile = Packet::Game(
                                     protocol::route::GameRoute::TileMap(TileMapData {
-                                        map_id: 0,
+                                        map_id: map_id as u128,
                                         tiles: t.to_vec(),
                                     }),
                                 );
-                                let _ = tokio::spawn(send(
+                                send_packet(
                                     send_socket.clone(),
-                                    bincode::serialize(&packet_tile).unwrap(),
+                                    connection_state.clone(),
+                                    packet_tile,
                                     addr,
-                                ));
+                                )
+                                .await;
                             }
                         }
                         GameRoute::Update(_) => {}
                         GameRoute::Player(_) => {}
                         GameRoute::PlayerList(_) => {}
+                        GameRoute::Snapshot(_) => {}
+                        GameRoute::Interest(_) => {}
+                        GameRoute::PlayerLeft(_) => {}
+                        GameRoute::Cooldown(_) => {}
+                        GameRoute::Kill(_) => {}
+                        GameRoute::Respawn(_) => {}
+                        GameRoute::SnapshotAck(seq) => {
+                            if let Some(connection) = connection_state.lock().await.get_mut(&addr) {
+                                connection.snapshots.ack(seq);
+                            }
+                        }
                     },
-                    // _ => println!("{}收到事件未处理: {:?}", &addr, &packet),
+                    // 可靠通道数据已在receive中处理
+                    Packet::Channel(_) => {}
+                    // 嵌套的会话数据报不处理
+                    Packet::Session(_) => {} // _ => println!("{}收到事件未处理: {:?}", &addr, &packet),
                 }
                 // socket.send((Bytes::from("收到！"), addr)).await.unwrap();
             }